@@ -4,9 +4,11 @@ use log::{info, warn};
 use std::fmt;
 use tokio::sync::{Mutex, RwLock};
 
-use crate::data::session::Session;
+use crate::data::recorder::Recorder;
+use crate::data::session::{Session, SessionCommand};
 use crate::error::error_generic;
 use crate::prelude::*;
+use crate::system::user::get_user_settings;
 use crate::utils::bluetooth_utils::{get_central, get_device_type, get_manager};
 use crate::utils::byte::convert_i16_to_u8;
 
@@ -18,8 +20,8 @@ use super::constants::{
 };
 use super::event_handlers::{
     handle_characteristic_subscription, handle_cycling_device_notifications,
-    handle_heart_rate_notifications, listen_to_events, write_to_characteristic,
-    CharacteristicAction,
+    handle_heart_rate_notifications, handle_session_recording, listen_to_events,
+    write_to_characteristic, CharacteristicAction,
 };
 
 lazy_static! {
@@ -69,7 +71,7 @@ pub struct Bluetooth {
 
     pub heart_rate_device: RwLock<Option<Peripheral>>,
     pub cycling_device: RwLock<Option<Peripheral>>,
-    pub session: RwLock<Option<Session>>,
+    pub recorder: Recorder,
 }
 
 impl Bluetooth {
@@ -82,6 +84,8 @@ impl Bluetooth {
             _ => BluetoothStatus::Error,
         };
 
+        let settings = get_user_settings().await;
+
         let bluetooth = Self {
             central: RwLock::new(central),
             manager: Mutex::new(manager),
@@ -89,12 +93,14 @@ impl Bluetooth {
             status: Mutex::new(status),
             heart_rate_device: RwLock::new(None),
             cycling_device: RwLock::new(None),
-            session: RwLock::new(None),
+            recorder: Recorder::new(&settings),
         };
 
         *BLUETOOTH.write().await = Some(bluetooth);
 
         listen_to_events().await;
+
+        tokio::spawn(handle_session_recording());
     }
 
     pub async fn start_scan(&self, scan_filter: DeviceType) -> Result<()> {
@@ -412,82 +418,42 @@ impl Bluetooth {
         Ok(())
     }
 
-    pub async fn start_session(&self) -> Result<()> {
-        let cd_guard = self.cycling_device.read().await;
-        let Some(cycling_device) = cd_guard.as_ref() else {
-            return Err(error_generic("Unable to read cycling device"))
+    pub async fn handle_session_command(
+        &self,
+        command: SessionCommand,
+        workout_id: Option<String>,
+    ) -> Result<()> {
+        // Resolved first, so that the trainer is not started for a workout that can't be found
+        let workout = self.recorder.validate(command, workout_id).await?;
+
+        let control: &[u8] = match command {
+            SessionCommand::Start | SessionCommand::Resume => &[FTMSControlOpCode::Start as u8],
+            SessionCommand::Pause => &[FTMSControlOpCode::Stop as u8, StopControl::Pause as u8],
+            SessionCommand::Finish => &[FTMSControlOpCode::Stop as u8, StopControl::Stop as u8],
+            SessionCommand::Lap => &[],
         };
 
-        write_to_characteristic(
-            FITNESS_MACHINE_CONTROL_POINT_UUID,
-            &cycling_device,
-            &[FTMSControlOpCode::Start as u8],
-            WriteType::WithResponse,
-        )
-        .await?;
-
-        let mut session_guard = self.session.write().await;
-        let Some(session) = session_guard.as_mut() else {
-            let mut session = Session::new();
-            session.start_session();
-
-            *session_guard = Some(session);
-
-            return Ok(());
-        };
-
-        session.start_session();
-
-        Ok(())
-    }
-
-    pub async fn stop_session(&self, action: &str) -> Result<()> {
-        let cd_guard = self.cycling_device.read().await;
-        let Some(cycling_device) = cd_guard.as_ref() else {
-            return Err(error_generic("Unable to read cycling device"))
-        };
-
-        let action_code = match action {
-            "stop" => StopControl::Stop,
-            "pause" => StopControl::Pause,
-            _ => {
-                return Err(error_generic(
-                    "Action not supported for stopping the session.",
-                ))
-            }
-        };
-
-        write_to_characteristic(
-            FITNESS_MACHINE_CONTROL_POINT_UUID,
-            &cycling_device,
-            &[FTMSControlOpCode::Stop as u8, action_code as u8],
-            WriteType::WithResponse,
-        )
-        .await?;
-
-        let mut session_guard = self.session.write().await;
-        let Some(session) = session_guard.as_mut() else {
-            return Err(error_generic("Unable to read session"))
-        };
+        if !control.is_empty() {
+            let cd_guard = self.cycling_device.read().await;
+            let Some(cycling_device) = cd_guard.as_ref() else {
+                return Err(error_generic("Unable to read cycling device"))
+            };
 
-        match action {
-            "stop" => session.stop_session(),
-            "pause" => session.pause_session(),
-            _ => {}
+            write_to_characteristic(
+                FITNESS_MACHINE_CONTROL_POINT_UUID,
+                &cycling_device,
+                control,
+                WriteType::WithResponse,
+            )
+            .await?;
         }
 
-        // TODO: Persist session data
-        // TODO: Reset session data
+        self.recorder.apply(command, workout).await?;
 
         Ok(())
     }
 
-    pub async fn get_session_data(&self) -> Result<Session> {
-        let session_guard = self.session.read().await;
-        let Some(session) = session_guard.as_ref() else {
-            return Err(error_generic("Unable to read session"));
-        };
-
-        Ok(session.get_session_data())
+    pub async fn get_session_data(&self) -> Session {
+        self.recorder.get_session_data().await
     }
 }
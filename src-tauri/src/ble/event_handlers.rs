@@ -5,11 +5,12 @@ use log::{error, info};
 use std::fmt;
 use std::pin::Pin;
 use tauri::{AppHandle, Manager as _};
+use tokio::time::{interval, Duration};
 use uuid::Uuid;
 
 use crate::data::heart_rate_measurement::parse_hrm_data;
 use crate::data::indoor_bike_data::parse_indoor_bike_data;
-use crate::data::session;
+use crate::data::pipeline::SensorSource;
 use crate::error::error_generic;
 use crate::prelude::*;
 use crate::utils::bluetooth_utils::get_uuid_characteristic;
@@ -169,12 +170,11 @@ pub async fn handle_heart_rate_notifications() {
     while let Some(data) = notification_stream.next().await {
         let data = parse_hrm_data(&data.value);
 
-        if let Some(app_handle) = TAURI_APP_HANDLE.lock().await.as_ref() {
-            let mut session_guard = bt.session.write().await;
-            if let Some(session) = session_guard.as_mut() {
-                session.add_heart_rate_data(data.bpm);
-            };
+        bt.recorder
+            .push_heart_rate_measurement(SensorSource::HeartRateMonitor, &data)
+            .await;
 
+        if let Some(app_handle) = TAURI_APP_HANDLE.lock().await.as_ref() {
             app_handle.emit_all("hrm_notification", data).ok();
         }
     }
@@ -211,31 +211,9 @@ pub async fn handle_cycling_device_notifications() {
             Characteristic::IndoorBikeData => {
                 let mut data = parse_indoor_bike_data(&data.value);
 
-                let mut session_guard = bt.session.write().await;
-                if let Some(session) = session_guard.as_mut() {
-                    match (data.cadence, data.speed, data.power, data.distance) {
-                        (Some(cadence), Some(speed), Some(power), _) => {
-                            session.add_indoor_bike_data(session::IndoorBikeData {
-                                cadence,
-                                speed,
-                                power,
-                            });
-                        }
-                        _ => {}
-                    }
-
-                    match (data.speed, data.distance) {
-                        (Some(speed), None) => {
-                            let distance = session.calculate_total_distance(speed);
-
-                            data.distance = Some(distance);
-                        }
-                        (_, Some(distance)) => {
-                            session.set_total_distance(distance);
-                        }
-                        _ => {}
-                    }
-                };
+                bt.recorder
+                    .push_indoor_bike_data(SensorSource::SmartTrainer, &mut data)
+                    .await;
 
                 app_handle.emit_all("indoor_bike_notification", data).ok();
             }
@@ -255,6 +233,29 @@ pub async fn handle_cycling_device_notifications() {
     }
 }
 
+/// Resamples the sensor streams into the session once per second
+pub async fn handle_session_recording() {
+    let mut ticker = interval(Duration::from_secs(1));
+
+    loop {
+        ticker.tick().await;
+
+        let bluetooth_guard = BLUETOOTH.read().await;
+        let Some(bt) = bluetooth_guard.as_ref() else {
+            error!("{}::handle_session_recording: Bluetooth not found", LOGGER_NAME);
+            return;
+        };
+
+        let Some(second) = bt.recorder.record().await else {
+            continue;
+        };
+
+        if let Some(app_handle) = TAURI_APP_HANDLE.lock().await.as_ref() {
+            second.emit(app_handle);
+        }
+    }
+}
+
 pub async fn handle_characteristic_subscription(
     uuid: Uuid,
     peripheral: &Peripheral,
@@ -0,0 +1,209 @@
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::prelude::*;
+use crate::system::directory;
+
+use super::{metrics::normalized_power, pipeline::Record, session::Session};
+
+// Shorter rides don't say much about aerobic fitness, in seconds
+const MIN_DURATION: usize = 600;
+
+// Records within this fraction of the average power count as steady
+const STEADY_POWER_TOLERANCE: f64 = 0.1;
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AerobicAnalysis {
+    // Power to heart rate ratio of each half
+    pub first_half_ratio: Option<f64>,
+    pub second_half_ratio: Option<f64>,
+    // Pw:HR decoupling in percent, positive when the second half is less efficient
+    pub decoupling: Option<f64>,
+    // Normalized Power / average heart rate
+    pub efficiency_factor: Option<f64>,
+    // Heart rate increase at steady power between the halves, in percent
+    pub cardiac_drift: Option<f64>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AerobicTrendEntry {
+    pub session_id: String,
+    pub date: Option<DateTime<Local>>,
+    pub analysis: AerobicAnalysis,
+}
+
+/// Decoupling and efficiency of a session.
+///
+/// Everything is left out for rides shorter than 10 minutes or without heart rate.
+pub fn analyze_aerobic(session: &Session) -> AerobicAnalysis {
+    let records = &session.records;
+
+    if records.len() < MIN_DURATION {
+        return AerobicAnalysis::default();
+    }
+
+    let (first_half, second_half) = records.split_at(records.len() / 2);
+
+    let first_half_ratio = power_to_heart_rate(first_half);
+    let second_half_ratio = power_to_heart_rate(second_half);
+
+    let decoupling = match (first_half_ratio, second_half_ratio) {
+        (Some(first), Some(second)) => Some((first - second) / first * 100.0),
+        _ => None,
+    };
+
+    let efficiency_factor = match (normalized_power(records), average_heart_rate(records)) {
+        (Some(np), Some(heart_rate)) => Some(np / heart_rate),
+        _ => None,
+    };
+
+    AerobicAnalysis {
+        first_half_ratio,
+        second_half_ratio,
+        decoupling,
+        efficiency_factor,
+        cardiac_drift: cardiac_drift(records),
+    }
+}
+
+/// Analysis of all saved sessions, oldest first
+pub fn load_aerobic_trend() -> Result<Vec<AerobicTrendEntry>> {
+    let mut trend: Vec<AerobicTrendEntry> =
+        directory::list_session_headers(|session| session.aerobic.is_some())?
+            .into_iter()
+            .map(|(session_id, session)| AerobicTrendEntry {
+                session_id,
+                date: session.start_time,
+                analysis: session
+                    .aerobic
+                    .clone()
+                    .unwrap_or_else(|| analyze_aerobic(&session)),
+            })
+            .filter(|entry| entry.analysis.decoupling.is_some())
+            .collect();
+
+    trend.sort_by_key(|entry| entry.date);
+
+    Ok(trend)
+}
+
+fn power_to_heart_rate(records: &[Record]) -> Option<f64> {
+    let (power, heart_rate, count) = records
+        .iter()
+        .filter_map(|r| Some((r.power?, r.heart_rate?)))
+        .filter(|(_, heart_rate)| *heart_rate > 0)
+        .fold((0.0, 0.0, 0), |(power, heart_rate, count), (p, hr)| {
+            (power + p as f64, heart_rate + hr as f64, count + 1)
+        });
+
+    if count == 0 || power == 0.0 {
+        return None;
+    }
+
+    Some(power / heart_rate)
+}
+
+fn cardiac_drift(records: &[Record]) -> Option<f64> {
+    let power: Vec<u16> = records.iter().filter_map(|r| r.power).collect();
+    let average_power = average(&power)?;
+    let tolerance = average_power * STEADY_POWER_TOLERANCE;
+
+    let steady_heart_rate: Vec<u16> = records
+        .iter()
+        .filter(|r| {
+            r.power
+                .is_some_and(|p| (p as f64 - average_power).abs() <= tolerance)
+        })
+        .filter_map(|r| r.heart_rate)
+        .filter(|hr| *hr > 0)
+        .collect();
+
+    let (first_half, second_half) = steady_heart_rate.split_at(steady_heart_rate.len() / 2);
+
+    match (average(first_half), average(second_half)) {
+        (Some(first), Some(second)) => Some((second - first) / first * 100.0),
+        _ => None,
+    }
+}
+
+fn average_heart_rate(records: &[Record]) -> Option<f64> {
+    let heart_rate: Vec<u16> = records
+        .iter()
+        .filter_map(|r| r.heart_rate)
+        .filter(|hr| *hr > 0)
+        .collect();
+
+    average(&heart_rate)
+}
+
+fn average(values: &[u16]) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+
+    let sum: f64 = values.iter().map(|v| *v as f64).sum();
+
+    Some(sum / values.len() as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(seconds: usize, heart_rate: impl Fn(usize) -> Option<u16>) -> Session {
+        let mut session = Session::new();
+
+        session.records = (0..seconds)
+            .map(|second| Record {
+                elapsed: second as u32,
+                power: Some(200),
+                heart_rate: heart_rate(second),
+                ..Default::default()
+            })
+            .collect();
+
+        session
+    }
+
+    fn assert_close(value: Option<f64>, expected: f64) {
+        assert!((value.unwrap() - expected).abs() < 1e-6, "{:?}", value);
+    }
+
+    #[test]
+    fn decouples_when_heart_rate_drifts_at_steady_power() {
+        // 200 W for an hour, heart rate 125 in the first half and 130 in the second
+        let session = session(3600, |second| Some(if second < 1800 { 125 } else { 130 }));
+
+        let analysis = analyze_aerobic(&session);
+
+        assert_close(analysis.first_half_ratio, 1.6);
+        assert_close(analysis.second_half_ratio, 200.0 / 130.0);
+        // (1.6 - 1.5385) / 1.6
+        assert_close(analysis.decoupling, 3.846153846);
+        assert_close(analysis.efficiency_factor, 200.0 / 127.5);
+        assert_close(analysis.cardiac_drift, 4.0);
+    }
+
+    #[test]
+    fn is_coupled_without_drift() {
+        let analysis = analyze_aerobic(&session(1200, |_| Some(140)));
+
+        assert_close(analysis.decoupling, 0.0);
+        assert_close(analysis.cardiac_drift, 0.0);
+    }
+
+    #[test]
+    fn skips_short_rides_and_rides_without_heart_rate() {
+        assert!(analyze_aerobic(&session(599, |_| Some(140)))
+            .decoupling
+            .is_none());
+
+        let analysis = analyze_aerobic(&session(1200, |_| None));
+
+        assert!(analysis.decoupling.is_none());
+        assert!(analysis.efficiency_factor.is_none());
+        assert!(analysis.cardiac_drift.is_none());
+    }
+}
@@ -0,0 +1,357 @@
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::error::error_generic;
+use crate::prelude::*;
+use crate::system::directory;
+use crate::workouts::{
+    activities::{WorkoutType, ACTIVITIES},
+    library,
+};
+
+use super::{pipeline::Record, session::Session};
+
+// Width of the power bands heart rate is compared in, in watts
+const POWER_BAND: u16 = 25;
+
+// Seconds a session needs in a power band for its heart rate to count
+const MIN_BAND_DURATION: usize = 30;
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Alignment {
+    // Fixed windows of `interval` seconds from the start
+    ElapsedTime { interval: u32 },
+    // The steps of the workout the sessions were ridden with
+    WorkoutStep,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ComparedSession {
+    pub id: String,
+    pub name: Option<String>,
+    pub date: Option<DateTime<Local>>,
+}
+
+#[derive(Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SegmentValues {
+    pub average_power: Option<f64>,
+    pub average_heart_rate: Option<f64>,
+    pub average_cadence: Option<f64>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Segment {
+    // Elapsed seconds, `end` is exclusive
+    pub start: u32,
+    pub end: u32,
+    pub workout_type: Option<WorkoutType>,
+    // One per compared session, in the order of `SessionComparison::sessions`
+    pub values: Vec<SegmentValues>,
+    // Difference of each session to the first one
+    pub deltas: Vec<SegmentValues>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HeartRateAtPower {
+    // Lower bound of the power band
+    pub power: u16,
+    // One per compared session
+    pub heart_rate: Vec<f64>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionComparison {
+    // Oldest first, the first session is the reference for the deltas
+    pub sessions: Vec<ComparedSession>,
+    pub segments: Vec<Segment>,
+    pub heart_rate_at_power: Vec<HeartRateAtPower>,
+    // The steps of the workout were edited between the sessions
+    pub workout_changed: bool,
+}
+
+/// Lines up saved sessions of the same workout to show the progress between them
+pub async fn compare_sessions(
+    session_ids: &[String],
+    alignment: Alignment,
+) -> Result<SessionComparison> {
+    if session_ids.len() < 2 {
+        return Err(error_generic(
+            "At least two sessions are needed for a comparison",
+        ));
+    }
+
+    let mut sessions = session_ids
+        .iter()
+        .map(|session_id| Ok((session_id.clone(), directory::load_session(session_id)?)))
+        .collect::<Result<Vec<(String, Session)>>>()?;
+
+    sessions.sort_by_key(|(_, session)| session.start_time);
+
+    let workout_id = sessions[0].1.workout_id.clone();
+
+    if sessions
+        .iter()
+        .any(|(_, session)| session.workout_id != workout_id)
+    {
+        return Err(error_generic(
+            "Sessions were not ridden with the same workout",
+        ));
+    }
+
+    // Steps are lined up with the workout as it is now
+    let version = match &workout_id {
+        Some(workout_id) => library::current_version(workout_id).await,
+        None => None,
+    }
+    .or_else(|| sessions[0].1.workout_version.clone());
+
+    let workout_changed = sessions
+        .iter()
+        .any(|(_, session)| session.workout_version != version);
+
+    let bounds = match alignment {
+        Alignment::ElapsedTime { interval } => {
+            let duration = sessions
+                .iter()
+                .map(|(_, session)| session.records.len() as u32)
+                .max()
+                .unwrap_or(0);
+
+            elapsed_bounds(duration, interval)
+        }
+        Alignment::WorkoutStep => {
+            let Some(workout_id) = workout_id else {
+                return Err(error_generic("Sessions were not ridden with a workout"));
+            };
+
+            step_bounds(&workout_id).await?
+        }
+    };
+
+    let segments = bounds
+        .into_iter()
+        .map(|(start, end, workout_type)| {
+            let values: Vec<SegmentValues> = sessions
+                .iter()
+                .map(|(_, session)| segment_values(&session.records, start, end))
+                .collect();
+
+            let deltas = values
+                .iter()
+                .map(|value| difference(value, &values[0]))
+                .collect();
+
+            Segment {
+                start,
+                end,
+                workout_type,
+                values,
+                deltas,
+            }
+        })
+        .collect();
+
+    Ok(SessionComparison {
+        heart_rate_at_power: heart_rate_at_power(&sessions),
+        workout_changed,
+        segments,
+        sessions: sessions
+            .into_iter()
+            .map(|(id, session)| ComparedSession {
+                id,
+                name: session.name,
+                date: session.start_time,
+            })
+            .collect(),
+    })
+}
+
+fn elapsed_bounds(duration: u32, interval: u32) -> Vec<(u32, u32, Option<WorkoutType>)> {
+    let interval = interval.max(1);
+
+    (0..duration)
+        .step_by(interval as usize)
+        .map(|start| (start, (start + interval).min(duration), None))
+        .collect()
+}
+
+async fn step_bounds(workout_id: &str) -> Result<Vec<(u32, u32, Option<WorkoutType>)>> {
+    let Some(lock) = ACTIVITIES.get() else {
+        return Err(error_generic("Activities are not loaded"));
+    };
+
+    let activities = lock.read().await;
+
+    let Some(activity) = activities.iter().find(|activity| activity.id == workout_id) else {
+        return Err(error_generic("Workout of the sessions not found"));
+    };
+
+    let mut start = 0;
+
+    let bounds = activity
+        .workouts
+        .iter()
+        .map(|workout| {
+            let end = start + workout.duration as u32;
+            let bound = (start, end, Some(workout.workout_type.clone()));
+            start = end;
+
+            bound
+        })
+        .collect();
+
+    Ok(bounds)
+}
+
+fn segment_values(records: &[Record], start: u32, end: u32) -> SegmentValues {
+    let start = (start as usize).min(records.len());
+    let end = (end as usize).min(records.len());
+    let records = &records[start..end];
+
+    SegmentValues {
+        average_power: average(records.iter().filter_map(|r| r.power)),
+        average_heart_rate: average(
+            records
+                .iter()
+                .filter_map(|r| r.heart_rate)
+                .filter(|hr| *hr > 0),
+        ),
+        average_cadence: average(records.iter().filter_map(|r| r.cadence).filter(|c| *c > 0)),
+    }
+}
+
+fn difference(value: &SegmentValues, reference: &SegmentValues) -> SegmentValues {
+    let delta = |value: Option<f64>, reference: Option<f64>| Some(value? - reference?);
+
+    SegmentValues {
+        average_power: delta(value.average_power, reference.average_power),
+        average_heart_rate: delta(value.average_heart_rate, reference.average_heart_rate),
+        average_cadence: delta(value.average_cadence, reference.average_cadence),
+    }
+}
+
+/// Average heart rate per power band, for the bands all sessions spent enough time in
+fn heart_rate_at_power(sessions: &[(String, Session)]) -> Vec<HeartRateAtPower> {
+    let bands: Vec<BTreeMap<u16, Vec<u16>>> = sessions
+        .iter()
+        .map(|(_, session)| {
+            let mut bands: BTreeMap<u16, Vec<u16>> = BTreeMap::new();
+
+            for record in session.records.iter() {
+                if let (Some(power), Some(heart_rate)) = (record.power, record.heart_rate) {
+                    if power > 0 && heart_rate > 0 {
+                        bands
+                            .entry(power / POWER_BAND * POWER_BAND)
+                            .or_default()
+                            .push(heart_rate);
+                    }
+                }
+            }
+
+            bands.retain(|_, heart_rate| heart_rate.len() >= MIN_BAND_DURATION);
+
+            bands
+        })
+        .collect();
+
+    bands[0]
+        .keys()
+        .filter(|power| bands.iter().all(|session| session.contains_key(power)))
+        .map(|power| HeartRateAtPower {
+            power: *power,
+            heart_rate: bands
+                .iter()
+                .filter_map(|session| average(session[power].iter().copied()))
+                .collect(),
+        })
+        .collect()
+}
+
+fn average(values: impl Iterator<Item = u16>) -> Option<f64> {
+    let (sum, count) = values.fold((0.0, 0), |(sum, count), value| {
+        (sum + value as f64, count + 1)
+    });
+
+    (count > 0).then(|| sum / count as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(seconds: u32, power: u16, heart_rate: u16) -> (String, Session) {
+        let mut session = Session::new();
+
+        session.records = (0..seconds)
+            .map(|elapsed| Record {
+                elapsed,
+                power: Some(power),
+                heart_rate: Some(heart_rate),
+                cadence: Some(if elapsed % 2 == 0 { 90 } else { 0 }),
+                ..Default::default()
+            })
+            .collect();
+
+        (String::new(), session)
+    }
+
+    #[test]
+    fn splits_the_elapsed_time_into_windows() {
+        assert_eq!(
+            elapsed_bounds(250, 100)
+                .into_iter()
+                .map(|(start, end, _)| (start, end))
+                .collect::<Vec<(u32, u32)>>(),
+            vec![(0, 100), (100, 200), (200, 250)]
+        );
+    }
+
+    #[test]
+    fn compares_segments_with_the_first_session() {
+        let (_, first) = session(120, 200, 150);
+        let (_, second) = session(60, 220, 145);
+
+        let reference = segment_values(&first.records, 0, 120);
+        let value = segment_values(&second.records, 0, 120);
+
+        assert_eq!(reference.average_power, Some(200.0));
+        // Coasting is left out of the cadence
+        assert_eq!(reference.average_cadence, Some(90.0));
+
+        let delta = difference(&value, &reference);
+
+        assert_eq!(delta.average_power, Some(20.0));
+        assert_eq!(delta.average_heart_rate, Some(-5.0));
+
+        // A session that ended before the segment has no values
+        let missing = segment_values(&second.records, 60, 120);
+        assert_eq!(missing.average_power, None);
+        assert_eq!(difference(&missing, &reference).average_power, None);
+    }
+
+    #[test]
+    fn keeps_the_power_bands_of_all_sessions() {
+        let sessions = [
+            session(60, 210, 150),
+            session(60, 205, 140),
+            // Too short to count
+            session(MIN_BAND_DURATION as u32 - 1, 260, 160),
+        ];
+
+        let bands = heart_rate_at_power(&sessions[..2]);
+
+        assert_eq!(bands.len(), 1);
+        assert_eq!(bands[0].power, 200);
+        assert_eq!(bands[0].heart_rate, vec![150.0, 140.0]);
+
+        let sessions = [sessions[0].clone(), sessions[2].clone()];
+        assert!(heart_rate_at_power(&sessions).is_empty());
+    }
+}
@@ -0,0 +1,229 @@
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::error::error_generic;
+use crate::prelude::*;
+use crate::system::{
+    directory,
+    user::{get_user_settings, save_user_settings, UserSettings},
+};
+
+use super::power_curve::{load_personal_records, session_power_curve, PersonalRecord};
+
+// Durations the critical power model is fitted over, in seconds.
+// Shorter efforts are limited by anaerobic power, longer ones by endurance.
+const MIN_MODEL_DURATION: usize = 180;
+const MAX_MODEL_DURATION: usize = 1200;
+
+// The fit needs a spread of durations to be meaningful
+const MIN_MODEL_SPAN: usize = 600;
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FtpTest {
+    // FTP is 75% of the best 1 minute power
+    Ramp,
+    // FTP is 95% of the best 20 minute power
+    TwentyMinute,
+}
+
+impl FtpTest {
+    // Seconds of the effort the estimate is based on and its share of FTP
+    fn protocol(&self) -> (usize, f64) {
+        match self {
+            FtpTest::Ramp => (60, 0.75),
+            FtpTest::TwentyMinute => (1200, 0.95),
+        }
+    }
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FtpEstimate {
+    pub session_id: String,
+    pub test: FtpTest,
+    pub ftp: u16,
+    // Best power over the test duration
+    pub power: u16,
+    pub current_ftp: u16,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FtpHistoryEntry {
+    pub date: DateTime<Local>,
+    pub ftp: u16,
+    pub previous_ftp: u16,
+    pub test: Option<FtpTest>,
+    pub session_id: Option<String>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CriticalPowerModel {
+    // Watts
+    pub critical_power: u16,
+    // Joules
+    pub w_prime: u32,
+    // How well the model matches the power curve, 1 is a perfect fit
+    pub r_squared: f64,
+}
+
+/// FTP estimated from a saved session ridden with one of the test protocols
+pub async fn estimate_ftp(session_id: &str, test: FtpTest) -> Result<FtpEstimate> {
+    let session = directory::load_session(session_id)?;
+    let power_curve = session_power_curve(&session);
+
+    let (duration, factor) = test.protocol();
+
+    let Some(power) = power_curve.get(duration - 1).copied().filter(|p| *p > 0) else {
+        return Err(error_generic("Session is too short for this test"));
+    };
+
+    Ok(FtpEstimate {
+        session_id: session_id.to_string(),
+        test,
+        ftp: (power as f64 * factor).round() as u16,
+        power,
+        current_ftp: get_user_settings().await.ftp,
+    })
+}
+
+/// Sets the FTP from a test result, keeping the previous value in the history
+pub async fn accept_ftp_estimate(session_id: &str, test: FtpTest) -> Result<UserSettings> {
+    let estimate = estimate_ftp(session_id, test).await?;
+    let mut settings = get_user_settings().await;
+
+    settings.ftp_history.push(FtpHistoryEntry {
+        date: Local::now(),
+        ftp: estimate.ftp,
+        previous_ftp: settings.ftp,
+        test: Some(test),
+        session_id: Some(estimate.session_id),
+    });
+    settings.ftp = estimate.ftp;
+
+    save_user_settings(settings.clone()).await?;
+
+    Ok(settings)
+}
+
+/// Fits the two parameter critical power model to the power curve of the last 90 days.
+///
+/// The work of each maximal effort is linear in its duration,
+/// `work = critical_power * duration + w_prime`.
+pub fn fit_critical_power() -> Result<CriticalPowerModel> {
+    fit_power_curve(&load_personal_records()?.last_90_days)
+}
+
+fn fit_power_curve(records: &[PersonalRecord]) -> Result<CriticalPowerModel> {
+    let points: Vec<(f64, f64)> = records
+        .iter()
+        .filter(|record| {
+            (MIN_MODEL_DURATION..=MAX_MODEL_DURATION).contains(&(record.duration as usize))
+        })
+        .filter(|record| record.power > 0)
+        .map(|record| {
+            let duration = record.duration as f64;
+
+            (duration, record.power as f64 * duration)
+        })
+        .collect();
+
+    let span = points.last().map(|(duration, _)| *duration as usize);
+
+    if span.is_none_or(|longest| longest < MIN_MODEL_DURATION + MIN_MODEL_SPAN) {
+        return Err(error_generic(
+            "Not enough long efforts in the last 90 days to fit the model",
+        ));
+    }
+
+    let count = points.len() as f64;
+    let mean_duration = points.iter().map(|(t, _)| t).sum::<f64>() / count;
+    let mean_work = points.iter().map(|(_, w)| w).sum::<f64>() / count;
+
+    let covariance: f64 = points
+        .iter()
+        .map(|(t, w)| (t - mean_duration) * (w - mean_work))
+        .sum();
+    let variance: f64 = points
+        .iter()
+        .map(|(t, _)| (t - mean_duration).powi(2))
+        .sum();
+
+    let critical_power = covariance / variance;
+    let w_prime = mean_work - critical_power * mean_duration;
+
+    if critical_power <= 0.0 || w_prime <= 0.0 {
+        return Err(error_generic("Power curve does not fit the model"));
+    }
+
+    let total: f64 = points.iter().map(|(_, w)| (w - mean_work).powi(2)).sum();
+    let residual: f64 = points
+        .iter()
+        .map(|(t, w)| (w - (critical_power * t + w_prime)).powi(2))
+        .sum();
+
+    Ok(CriticalPowerModel {
+        critical_power: critical_power.round() as u16,
+        w_prime: w_prime.round() as u32,
+        r_squared: 1.0 - residual / total,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records(power: impl Fn(u32) -> u16) -> Vec<PersonalRecord> {
+        (1..=1200)
+            .map(|duration| PersonalRecord {
+                duration,
+                power: power(duration),
+                session_id: "ride".to_string(),
+                date: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fits_exact_points() {
+        // 250 W critical power and 20 kJ W', only durations with whole watts
+        let records: Vec<PersonalRecord> = records(|duration| (250 + 20000 / duration) as u16)
+            .into_iter()
+            .filter(|record| 20000 % record.duration == 0)
+            .collect();
+
+        let model = fit_power_curve(&records).unwrap();
+
+        assert_eq!(model.critical_power, 250);
+        assert_eq!(model.w_prime, 20000);
+        assert!((model.r_squared - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn fits_a_rounded_power_curve() {
+        let records = records(|duration| (250.0 + 20000.0 / duration as f64).round() as u16);
+
+        let model = fit_power_curve(&records).unwrap();
+
+        assert_eq!(model.critical_power, 250);
+        assert!(model.w_prime.abs_diff(20000) < 100);
+        assert!(model.r_squared > 0.999);
+    }
+
+    #[test]
+    fn needs_long_efforts() {
+        let records: Vec<PersonalRecord> = records(|duration| (250 + 20000 / duration) as u16)
+            .into_iter()
+            .take(600)
+            .collect();
+
+        assert!(fit_power_curve(&records).is_err());
+    }
+
+    #[test]
+    fn rejects_a_curve_without_w_prime() {
+        assert!(fit_power_curve(&records(|_| 250)).is_err());
+    }
+}
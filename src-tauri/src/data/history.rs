@@ -0,0 +1,183 @@
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use std::cmp::Reverse;
+
+use crate::error::error_generic;
+use crate::prelude::*;
+use crate::storage::{self, SessionRepository};
+use crate::system::{directory, user::UserSettings};
+
+use super::{metrics::calculate_metrics, session::Session};
+
+const MAX_RPE: u8 = 10;
+
+/// What the session list shows for a saved session
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionSummary {
+    pub id: String,
+    pub name: Option<String>,
+    pub workout_name: Option<String>,
+    pub date: Option<DateTime<Local>>,
+    // Moving time in seconds
+    pub duration: u32,
+    // Meters
+    pub distance: u32,
+    pub normalized_power: Option<u16>,
+    pub training_stress_score: Option<f64>,
+    pub rpe: Option<u8>,
+}
+
+impl SessionSummary {
+    pub fn new(id: &str, session: &Session, settings: &UserSettings) -> Self {
+        let metrics = match &session.metrics {
+            Some(metrics) => metrics.clone(),
+            None => calculate_metrics(session, settings.ftp, settings.weight),
+        };
+
+        Self {
+            id: id.to_string(),
+            name: session.name.clone(),
+            workout_name: session.workout_name.clone(),
+            date: session.start_time,
+            duration: metrics.duration,
+            distance: metrics.distance,
+            normalized_power: metrics.normalized_power,
+            training_stress_score: metrics.training_stress_score,
+            rpe: session.rpe,
+        }
+    }
+}
+
+/// Summaries of all saved sessions, newest first.
+///
+/// Sessions saved without metrics are calculated from their records.
+pub fn list_sessions(settings: &UserSettings) -> Result<Vec<SessionSummary>> {
+    list_repository_sessions(storage::repository()?, settings)
+}
+
+fn list_repository_sessions(
+    repository: &dyn SessionRepository,
+    settings: &UserSettings,
+) -> Result<Vec<SessionSummary>> {
+    let mut summaries: Vec<SessionSummary> =
+        directory::list_repository_headers(repository, |session| session.metrics.is_some())?
+            .iter()
+            .map(|(session_id, session)| SessionSummary::new(session_id, session, settings))
+            .collect();
+
+    summaries.sort_by_key(|summary| Reverse(summary.date));
+
+    Ok(summaries)
+}
+
+pub fn rename_session(session_id: &str, name: &str) -> Result<()> {
+    let name = name.trim();
+
+    directory::rename_session(session_id, (!name.is_empty()).then_some(name))
+}
+
+pub fn annotate_session(session_id: &str, notes: Option<String>, rpe: Option<u8>) -> Result<()> {
+    if rpe.is_some_and(|rpe| rpe == 0 || rpe > MAX_RPE) {
+        return Err(error_generic("RPE must be between 1 and 10"));
+    }
+
+    let notes = notes.filter(|notes| !notes.trim().is_empty());
+
+    directory::annotate_session(session_id, notes.as_deref(), rpe)
+}
+
+pub fn delete_session(session_id: &str) -> Result<()> {
+    directory::delete_session(session_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{metrics::Metrics, pipeline::Record};
+    use crate::storage::{import_json_sessions, sqlite::SqliteRepository};
+    use chrono::{Duration, TimeZone};
+    use std::{fs, path::Path};
+
+    fn ride() -> Session {
+        let mut session = Session::new();
+        session.records = (0..3600)
+            .map(|elapsed| Record {
+                elapsed,
+                power: Some(200),
+                ..Default::default()
+            })
+            .collect();
+
+        session
+    }
+
+    #[test]
+    fn summarizes_the_saved_metrics() {
+        let mut session = ride();
+        session.name = Some("Endurance".to_string());
+        session.rpe = Some(4);
+        session.metrics = Some(Metrics {
+            duration: 3000,
+            training_stress_score: Some(75.0),
+            ..Default::default()
+        });
+
+        let summary = SessionSummary::new("ride", &session, &UserSettings::default());
+
+        assert_eq!(summary.id, "ride");
+        assert_eq!(summary.name.as_deref(), Some("Endurance"));
+        assert_eq!(summary.duration, 3000);
+        assert_eq!(summary.training_stress_score, Some(75.0));
+        assert_eq!(summary.rpe, Some(4));
+    }
+
+    #[test]
+    fn calculates_missing_metrics_with_the_current_ftp() {
+        let settings = UserSettings {
+            ftp: 200,
+            ..Default::default()
+        };
+
+        let summary = SessionSummary::new("ride", &ride(), &settings);
+
+        assert_eq!(summary.duration, 3600);
+        assert_eq!(summary.normalized_power, Some(200));
+        assert!((summary.training_stress_score.unwrap() - 100.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn rejects_an_rpe_out_of_range() {
+        assert!(annotate_session("ride", None, Some(0)).is_err());
+        assert!(annotate_session("ride", None, Some(MAX_RPE + 1)).is_err());
+    }
+
+    #[test]
+    fn lists_sessions_saved_before_the_timeline() {
+        let directory =
+            std::env::temp_dir().join(format!("cycling_trainer_history_{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(
+            directory.join("20230501183000.json"),
+            include_str!("../../fixtures/legacy_session.json"),
+        )
+        .unwrap();
+
+        let repository = SqliteRepository::open(Path::new(":memory:")).unwrap();
+        import_json_sessions(&repository, &directory).unwrap();
+
+        fs::remove_dir_all(&directory).unwrap();
+
+        let summaries = list_repository_sessions(&repository, &UserSettings::default()).unwrap();
+
+        assert_eq!(summaries.len(), 1);
+
+        let summary = &summaries[0];
+        let end_time = Local.with_ymd_and_hms(2023, 5, 1, 18, 30, 0).unwrap();
+
+        assert_eq!(summary.id, "20230501183000");
+        assert_eq!(summary.date, Some(end_time - Duration::seconds(6)));
+        assert_eq!(summary.duration, 6);
+        assert_eq!(summary.distance, 50);
+    }
+}
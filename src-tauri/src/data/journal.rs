@@ -0,0 +1,530 @@
+use chrono::{DateTime, Duration, Local};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::{BufRead, BufReader, Write},
+    path::PathBuf,
+    sync::Mutex,
+};
+
+use crate::error::error_generic;
+use crate::prelude::*;
+use crate::system::{
+    directory,
+    user::{get_user_settings, UserSettings},
+};
+
+use super::{
+    aerobic::analyze_aerobic,
+    metrics::calculate_metrics,
+    pipeline::{interpolate_gaps, Record},
+    power_curve::mean_max_power,
+    session::{Session, SessionCommand, SessionStatus},
+};
+
+const LOGGER_NAME: &str = "data::journal";
+
+// Records are appended once this many seconds were recorded
+const JOURNAL_INTERVAL: usize = 5;
+
+// Journals left over by a previous run of the app, found on startup
+static RECOVERABLE: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// One line of the journal
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Entry {
+    Started {
+        start_time: DateTime<Local>,
+        // Workout ridden, older journals have none
+        #[serde(default)]
+        workout_id: Option<String>,
+        #[serde(default)]
+        workout_name: Option<String>,
+        #[serde(default)]
+        workout_version: Option<String>,
+    },
+    Records {
+        records: Vec<Record>,
+        total_distance: u32,
+    },
+    Lap {
+        elapsed: u32,
+    },
+    Resumed {
+        paused_time: u32,
+    },
+    Finished {
+        end_time: DateTime<Local>,
+        paused_time: u32,
+    },
+}
+
+/// A session that was recorded but never saved
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecoverableSession {
+    pub id: String,
+    pub start_time: Option<DateTime<Local>>,
+    // Seconds
+    pub duration: u32,
+    // Meters
+    pub distance: u32,
+    // The session was finished before the app stopped
+    pub finished: bool,
+}
+
+/// Append-only copy of the running session, so that it survives a crash.
+///
+/// The journal is removed once the session is saved or discarded.
+pub struct Journal {
+    session_id: String,
+    file: fs::File,
+    // Records of the session already written to the journal
+    written: usize,
+}
+
+impl Journal {
+    pub fn create(session: &Session) -> Result<Self> {
+        let Some(start_time) = session.start_time else {
+            return Err(error_generic("Session has no start time"));
+        };
+
+        let session_id = start_time.format("%Y%m%d%H%M%S").to_string();
+        let file = fs::File::create(get_journal_file(&session_id)?)?;
+
+        let mut journal = Self {
+            session_id,
+            file,
+            written: 0,
+        };
+
+        journal.append(&Entry::Started {
+            start_time,
+            workout_id: session.workout_id.clone(),
+            workout_name: session.workout_name.clone(),
+            workout_version: session.workout_version.clone(),
+        })?;
+
+        Ok(journal)
+    }
+
+    /// Appends the new records, every few seconds unless `force` is set
+    pub fn sync(&mut self, session: &Session, force: bool) -> Result<()> {
+        let pending = session.records.len().saturating_sub(self.written);
+
+        if pending == 0 || (!force && pending < JOURNAL_INTERVAL) {
+            return Ok(());
+        }
+
+        self.append(&Entry::Records {
+            records: session.records[self.written..].to_vec(),
+            total_distance: session.total_distance,
+        })?;
+
+        self.written = session.records.len();
+
+        Ok(())
+    }
+
+    /// Records the effect of a command that was applied to the session
+    pub fn command(&mut self, command: SessionCommand, session: &Session) -> Result<()> {
+        match command {
+            SessionCommand::Start => Ok(()),
+            SessionCommand::Pause => self.sync(session, true),
+            SessionCommand::Resume => self.append(&Entry::Resumed {
+                paused_time: session.paused_time,
+            }),
+            SessionCommand::Lap => {
+                self.sync(session, true)?;
+
+                match session.laps.last() {
+                    Some(elapsed) => self.append(&Entry::Lap { elapsed: *elapsed }),
+                    None => Ok(()),
+                }
+            }
+            SessionCommand::Finish => {
+                self.sync(session, true)?;
+                self.append(&Entry::Finished {
+                    end_time: session.end_time.unwrap_or_else(Local::now),
+                    paused_time: session.paused_time,
+                })
+            }
+        }
+    }
+
+    /// Removes the journal, e.g. after the session was saved
+    pub fn discard(self) {
+        discard_journal(&self.session_id);
+    }
+
+    fn append(&mut self, entry: &Entry) -> Result<()> {
+        let mut line =
+            serde_json::to_string(entry).map_err(|err| error_generic(&err.to_string()))?;
+        line.push('\n');
+
+        self.file.write_all(line.as_bytes())?;
+        self.file.sync_data()?;
+
+        Ok(())
+    }
+}
+
+/// Looks for journals of sessions that were not saved when the app stopped
+pub fn initialize() {
+    let journals = match list_journals() {
+        Ok(journals) => journals,
+        Err(err) => {
+            warn!(
+                "{}::initialize: Unable to read the journal directory: {}",
+                LOGGER_NAME, err
+            );
+
+            return;
+        }
+    };
+
+    let mut recoverable = Vec::new();
+
+    for session_id in journals {
+        match read_journal(&session_id) {
+            Ok((session, _)) if !session.records.is_empty() => recoverable.push(session_id),
+            // Nothing was recorded, so nothing is lost
+            Ok(_) => discard_journal(&session_id),
+            Err(err) => warn!(
+                "{}::initialize: Unable to read journal {}: {}",
+                LOGGER_NAME, session_id, err
+            ),
+        }
+    }
+
+    if !recoverable.is_empty() {
+        info!(
+            "{}::initialize: Found {} unsaved sessions.",
+            LOGGER_NAME,
+            recoverable.len()
+        );
+    }
+
+    if let Ok(mut guard) = RECOVERABLE.lock() {
+        *guard = recoverable;
+    }
+}
+
+pub fn list_recoverable_sessions() -> Result<Vec<RecoverableSession>> {
+    let session_ids = RECOVERABLE
+        .lock()
+        .map_err(|_| error_generic("Journal lock poisoned"))?
+        .clone();
+
+    let sessions = session_ids
+        .into_iter()
+        .filter_map(|session_id| match read_journal(&session_id) {
+            Ok((session, finished)) => Some(RecoverableSession {
+                start_time: session.start_time,
+                duration: session.moving_time(),
+                distance: session.total_distance,
+                finished,
+                id: session_id,
+            }),
+            Err(err) => {
+                warn!(
+                    "{}::list_recoverable_sessions: Skipping journal {}: {}",
+                    LOGGER_NAME, session_id, err
+                );
+
+                None
+            }
+        })
+        .collect();
+
+    Ok(sessions)
+}
+
+/// Saves the journaled session and removes its journal
+pub async fn recover_session(session_id: &str) -> Result<String> {
+    check_recoverable(session_id)?;
+
+    let file = fs::File::open(get_journal_file(session_id)?)?;
+    let settings = get_user_settings().await;
+    let session = recover(BufReader::new(file), session_id, &settings)?;
+
+    directory::save_session(&session, session_id)?;
+    forget_recoverable(session_id);
+    discard_journal(session_id);
+
+    info!(
+        "{}::recover_session: Recovered session {}",
+        LOGGER_NAME, session_id
+    );
+
+    Ok(session_id.to_string())
+}
+
+pub fn discard_recoverable_session(session_id: &str) -> Result<()> {
+    check_recoverable(session_id)?;
+    forget_recoverable(session_id);
+    discard_journal(session_id);
+
+    Ok(())
+}
+
+// Rebuilds the session of a journal with what is calculated when a session is saved
+fn recover(journal: impl BufRead, session_id: &str, settings: &UserSettings) -> Result<Session> {
+    let (mut session, _) = parse_journal(journal, session_id)?;
+
+    interpolate_gaps(&mut session.records);
+
+    session.metrics = Some(calculate_metrics(&session, settings.ftp, settings.weight));
+    session.power_curve = Some(mean_max_power(&session.records));
+    session.aerobic = Some(analyze_aerobic(&session));
+
+    Ok(session)
+}
+
+fn read_journal(session_id: &str) -> Result<(Session, bool)> {
+    let file = fs::File::open(get_journal_file(session_id)?)?;
+
+    parse_journal(BufReader::new(file), session_id)
+}
+
+/// Rebuilds the session from its journal as a finished session,
+/// along with whether it was finished before the app stopped.
+///
+/// Reading stops at the first broken line, which is where the app stopped writing.
+fn parse_journal(journal: impl BufRead, session_id: &str) -> Result<(Session, bool)> {
+    let mut session = Session::new();
+    session.status = SessionStatus::Finished;
+
+    for line in journal.lines() {
+        let entry = match serde_json::from_str::<Entry>(&line?) {
+            Ok(entry) => entry,
+            Err(err) => {
+                warn!(
+                    "{}::parse_journal: Journal {} ends with a broken entry: {}",
+                    LOGGER_NAME, session_id, err
+                );
+
+                break;
+            }
+        };
+
+        match entry {
+            Entry::Started {
+                start_time,
+                workout_id,
+                workout_name,
+                workout_version,
+            } => {
+                session.start_time = Some(start_time);
+                session.workout_id = workout_id;
+                session.workout_name = workout_name;
+                session.workout_version = workout_version;
+            }
+            Entry::Records {
+                records,
+                total_distance,
+            } => {
+                session.records.extend(records);
+                session.total_distance = total_distance;
+            }
+            Entry::Lap { elapsed } => session.laps.push(elapsed),
+            Entry::Resumed { paused_time } => {
+                session.add_pause(paused_time.saturating_sub(session.paused_time));
+            }
+            Entry::Finished {
+                end_time,
+                paused_time,
+            } => {
+                session.end_time = Some(end_time);
+                session.add_pause(paused_time.saturating_sub(session.paused_time));
+            }
+        }
+    }
+
+    let Some(start_time) = session.start_time else {
+        return Err(error_generic("Journal has no start time"));
+    };
+
+    let finished = session.end_time.is_some();
+
+    // The session ends with the last journaled record when it was not finished
+    if !finished {
+        let elapsed = session.moving_time() + session.paused_time;
+        session.end_time = Some(start_time + Duration::seconds(elapsed as i64));
+    }
+
+    Ok((session, finished))
+}
+
+// Journals of sessions recorded since the app started are not recoverable
+fn check_recoverable(session_id: &str) -> Result<()> {
+    let recoverable = RECOVERABLE
+        .lock()
+        .map_err(|_| error_generic("Journal lock poisoned"))?;
+
+    if !recoverable.iter().any(|id| id == session_id) {
+        return Err(error_generic("No unsaved session with this id"));
+    }
+
+    Ok(())
+}
+
+fn forget_recoverable(session_id: &str) {
+    if let Ok(mut recoverable) = RECOVERABLE.lock() {
+        recoverable.retain(|id| id != session_id);
+    }
+}
+
+fn discard_journal(session_id: &str) {
+    let result = get_journal_file(session_id).and_then(|path| Ok(fs::remove_file(path)?));
+
+    if let Err(err) = result {
+        warn!(
+            "{}::discard_journal: Unable to remove journal {}: {}",
+            LOGGER_NAME, session_id, err
+        );
+    }
+}
+
+fn list_journals() -> Result<Vec<String>> {
+    let mut session_ids = Vec::new();
+
+    for entry in fs::read_dir(directory::get_journal_directory()?)? {
+        let path = entry?.path();
+
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "jsonl")
+        {
+            if let Some(stem) = path.file_stem() {
+                session_ids.push(stem.to_string_lossy().to_string());
+            }
+        }
+    }
+
+    Ok(session_ids)
+}
+
+fn get_journal_file(session_id: &str) -> Result<PathBuf> {
+    Ok(directory::get_journal_directory()?.join(format!("{}.jsonl", session_id)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::session::Pause;
+    use chrono::TimeZone;
+    use std::io::Cursor;
+
+    fn records(from: u32, to: u32) -> Vec<Record> {
+        (from..to)
+            .map(|elapsed| Record {
+                elapsed,
+                power: Some(200),
+                heart_rate: Some(140),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn line(entry: &Entry) -> String {
+        serde_json::to_string(entry).unwrap() + "\n"
+    }
+
+    #[test]
+    fn recovers_a_session_cut_off_mid_write() {
+        let start_time = Local.with_ymd_and_hms(2026, 3, 1, 7, 0, 0).unwrap();
+
+        let mut journal = String::new();
+        journal += &line(&Entry::Started {
+            start_time,
+            workout_id: Some("tempo".to_string()),
+            workout_name: Some("Tempo".to_string()),
+            workout_version: Some("abc123".to_string()),
+        });
+        journal += &line(&Entry::Records {
+            records: records(0, 5),
+            total_distance: 40,
+        });
+        journal += &line(&Entry::Lap { elapsed: 5 });
+        journal += &line(&Entry::Resumed { paused_time: 30 });
+        journal += &line(&Entry::Records {
+            records: records(5, 8),
+            total_distance: 65,
+        });
+        // The app stopped while writing this line
+        journal += r#"{"type":"records","records":[{"elapsed":8,"#;
+
+        let settings = UserSettings {
+            ftp: 200,
+            ..Default::default()
+        };
+
+        let session = recover(Cursor::new(journal), "20260301070000", &settings).unwrap();
+
+        let elapsed: Vec<u32> = session
+            .records
+            .iter()
+            .map(|record| record.elapsed)
+            .collect();
+
+        assert_eq!(elapsed, (0..8).collect::<Vec<u32>>());
+        assert_eq!(session.laps, vec![5]);
+        assert_eq!(session.moving_time(), 8);
+        assert_eq!(session.paused_time, 30);
+        assert_eq!(
+            session.pauses,
+            vec![Pause {
+                elapsed: 5,
+                duration: 30
+            }]
+        );
+        assert_eq!(session.total_distance, 65);
+        assert_eq!(session.status, SessionStatus::Finished);
+        assert_eq!(session.start_time, Some(start_time));
+        assert_eq!(session.workout_id.as_deref(), Some("tempo"));
+        assert_eq!(session.workout_name.as_deref(), Some("Tempo"));
+        assert_eq!(session.workout_version.as_deref(), Some("abc123"));
+        // Not finished, so it ends with the last record
+        assert_eq!(session.end_time, Some(start_time + Duration::seconds(38)));
+        assert!(session.metrics.is_some());
+        assert_eq!(session.power_curve.as_ref().unwrap()[0], 200);
+    }
+
+    #[test]
+    fn keeps_the_end_of_a_finished_session() {
+        let start_time = Local.with_ymd_and_hms(2026, 3, 1, 7, 0, 0).unwrap();
+        let end_time = start_time + Duration::seconds(90);
+
+        // Written before the workout was journaled
+        let mut journal = format!(
+            "{{\"type\":\"started\",\"start_time\":\"{}\"}}\n",
+            start_time.to_rfc3339()
+        );
+        journal += &line(&Entry::Records {
+            records: records(0, 60),
+            total_distance: 500,
+        });
+        journal += &line(&Entry::Finished {
+            end_time,
+            paused_time: 30,
+        });
+
+        let (session, finished) = parse_journal(Cursor::new(journal), "id").unwrap();
+
+        assert!(finished);
+        assert_eq!(session.start_time, Some(start_time));
+        assert_eq!(session.workout_id, None);
+        assert_eq!(session.end_time, Some(end_time));
+        assert_eq!(session.moving_time(), 60);
+    }
+
+    #[test]
+    fn rejects_a_journal_without_start() {
+        let journal = line(&Entry::Lap { elapsed: 5 });
+
+        assert!(parse_journal(Cursor::new(journal), "id").is_err());
+    }
+}
@@ -0,0 +1,201 @@
+use serde::{Deserialize, Serialize};
+
+use super::{pipeline::Record, session::Session};
+
+// Rolling window used for Normalized Power, in seconds
+const NORMALIZED_POWER_WINDOW: usize = 30;
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Metrics {
+    // Moving time in seconds
+    pub duration: u32,
+    // Meters
+    pub distance: u32,
+    pub average_power: Option<u16>,
+    pub max_power: Option<u16>,
+    pub normalized_power: Option<u16>,
+    pub intensity_factor: Option<f64>,
+    pub training_stress_score: Option<f64>,
+    pub variability_index: Option<f64>,
+    // Kilojoules
+    pub work: Option<f64>,
+    pub watts_per_kg: Option<f64>,
+    pub average_heart_rate: Option<u16>,
+    pub max_heart_rate: Option<u16>,
+    pub average_cadence: Option<u16>,
+    pub max_cadence: Option<u16>,
+}
+
+/// Calculates the training metrics of a session.
+///
+/// FTP based metrics are left out when `ftp` is 0 and W/kg when `weight` is 0.
+pub fn calculate_metrics(session: &Session, ftp: u16, weight: f64) -> Metrics {
+    let records = &session.records;
+
+    let power: Vec<u16> = records.iter().filter_map(|r| r.power).collect();
+    let heart_rate: Vec<u16> = records.iter().filter_map(|r| r.heart_rate).collect();
+
+    // Coasting is excluded from the average cadence
+    let cadence: Vec<u16> = records
+        .iter()
+        .filter_map(|r| r.cadence)
+        .filter(|c| *c > 0)
+        .collect();
+
+    let average_power = average(&power);
+    let normalized_power = normalized_power(records);
+
+    let intensity_factor = match (normalized_power, ftp) {
+        (Some(np), ftp) if ftp > 0 => Some(np / ftp as f64),
+        _ => None,
+    };
+
+    let training_stress_score = match (normalized_power, intensity_factor) {
+        (Some(np), Some(intensity_factor)) => {
+            let seconds = records.len() as f64;
+
+            Some((seconds * np * intensity_factor) / (ftp as f64 * 3600.0) * 100.0)
+        }
+        _ => None,
+    };
+
+    let variability_index = match (normalized_power, average_power) {
+        (Some(np), Some(average_power)) if average_power > 0.0 => Some(np / average_power),
+        _ => None,
+    };
+
+    // Each record covers one second, so the sum of watts is the work in joules
+    let work = if power.is_empty() {
+        None
+    } else {
+        Some(power.iter().map(|p| *p as f64).sum::<f64>() / 1000.0)
+    };
+
+    let watts_per_kg = match average_power {
+        Some(average_power) if weight > 0.0 => Some(average_power / weight),
+        _ => None,
+    };
+
+    Metrics {
+        duration: session.moving_time(),
+        distance: session.total_distance,
+        average_power: average_power.map(|p| p.round() as u16),
+        max_power: power.iter().max().copied(),
+        normalized_power: normalized_power.map(|p| p.round() as u16),
+        intensity_factor,
+        training_stress_score,
+        variability_index,
+        work,
+        watts_per_kg,
+        average_heart_rate: average(&heart_rate).map(|hr| hr.round() as u16),
+        max_heart_rate: heart_rate.iter().max().copied(),
+        average_cadence: average(&cadence).map(|c| c.round() as u16),
+        max_cadence: cadence.iter().max().copied(),
+    }
+}
+
+/// 4th root of the mean of the 4th powers of the 30 second rolling average power
+pub fn normalized_power(records: &[Record]) -> Option<f64> {
+    if records.len() < NORMALIZED_POWER_WINDOW || records.iter().all(|r| r.power.is_none()) {
+        return None;
+    }
+
+    // Dropouts count as no power
+    let power: Vec<f64> = records
+        .iter()
+        .map(|r| r.power.unwrap_or(0) as f64)
+        .collect();
+
+    let mut window_sum: f64 = power[..NORMALIZED_POWER_WINDOW].iter().sum();
+    let mut sum_of_powers = (window_sum / NORMALIZED_POWER_WINDOW as f64).powi(4);
+
+    for i in NORMALIZED_POWER_WINDOW..power.len() {
+        window_sum += power[i] - power[i - NORMALIZED_POWER_WINDOW];
+        sum_of_powers += (window_sum / NORMALIZED_POWER_WINDOW as f64).powi(4);
+    }
+
+    let windows = (power.len() - NORMALIZED_POWER_WINDOW + 1) as f64;
+
+    Some((sum_of_powers / windows).powf(0.25))
+}
+
+fn average(values: &[u16]) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+
+    let sum: f64 = values.iter().map(|v| *v as f64).sum();
+
+    Some(sum / values.len() as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(power: impl Iterator<Item = u16>) -> Session {
+        let mut session = Session::new();
+
+        session.records = power
+            .enumerate()
+            .map(|(elapsed, power)| Record {
+                elapsed: elapsed as u32,
+                power: Some(power),
+                ..Default::default()
+            })
+            .collect();
+
+        session
+    }
+
+    #[test]
+    fn one_hour_at_ftp_is_100_tss() {
+        let session = session(std::iter::repeat_n(200, 3600));
+
+        let metrics = calculate_metrics(&session, 200, 80.0);
+
+        assert_eq!(metrics.duration, 3600);
+        assert_eq!(metrics.average_power, Some(200));
+        assert_eq!(metrics.normalized_power, Some(200));
+        assert!((metrics.intensity_factor.unwrap() - 1.0).abs() < 1e-9);
+        assert!((metrics.training_stress_score.unwrap() - 100.0).abs() < 1e-6);
+        assert!((metrics.variability_index.unwrap() - 1.0).abs() < 1e-9);
+        assert!((metrics.work.unwrap() - 720.0).abs() < 1e-9);
+        assert!((metrics.watts_per_kg.unwrap() - 2.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn half_an_hour_at_threshold_of_a_higher_ftp() {
+        let session = session(std::iter::repeat_n(150, 1800));
+
+        let metrics = calculate_metrics(&session, 200, 0.0);
+
+        // IF 0.75 for half an hour
+        assert!((metrics.intensity_factor.unwrap() - 0.75).abs() < 1e-9);
+        assert!((metrics.training_stress_score.unwrap() - 28.125).abs() < 1e-6);
+        assert_eq!(metrics.watts_per_kg, None);
+    }
+
+    #[test]
+    fn normalized_power_weights_surges() {
+        // Alternating minutes at 100 and 300 watts
+        let session =
+            session((0..3600).map(|second| if (second / 60) % 2 == 0 { 100 } else { 300 }));
+
+        let metrics = calculate_metrics(&session, 0, 0.0);
+
+        assert_eq!(metrics.average_power, Some(200));
+        assert!(metrics.normalized_power.unwrap() > 200);
+        assert_eq!(metrics.intensity_factor, None);
+        assert_eq!(metrics.training_stress_score, None);
+    }
+
+    #[test]
+    fn needs_a_full_window_for_normalized_power() {
+        let session = session(std::iter::repeat_n(200, 29));
+
+        assert_eq!(normalized_power(&session.records), None);
+        assert_eq!(normalized_power(&[]), None);
+    }
+}
@@ -1,4 +1,18 @@
+pub mod aerobic;
+pub mod comparison;
+pub mod ftp;
 pub mod heart_rate_measurement;
+pub mod history;
 pub mod indoor_bike_data;
+pub mod journal;
+pub mod metrics;
+pub mod physics;
+pub mod pipeline;
+pub mod power_curve;
+pub mod recorder;
 pub mod session;
 pub mod simulation;
+pub mod training_load;
+pub mod training_summary;
+pub mod w_balance;
+pub mod zones;
@@ -0,0 +1,123 @@
+use serde::{Deserialize, Serialize};
+
+const GRAVITY: f64 = 9.80665;
+const AIR_DENSITY: f64 = 1.225;
+const DRIVETRAIN_EFFICIENCY: f64 = 0.976;
+const MPS_TO_KPH: f64 = 3.6;
+
+// Used when the user has not set a weight
+const DEFAULT_RIDER_WEIGHT: f64 = 75.0;
+
+/// Parameters of the virtual speed model used for trainers that don't report speed
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct VirtualSpeedConfig {
+    // Always use the virtual speed, even if the trainer reports speed
+    pub enabled: bool,
+    // Kilograms
+    pub bike_weight: f64,
+    // Drag coefficient times frontal area, m^2
+    pub cda: f64,
+    // Rolling resistance coefficient
+    pub crr: f64,
+    // Road gradient as a fraction, e.g. 0.05 for 5%
+    pub grade: f64,
+}
+
+impl Default for VirtualSpeedConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bike_weight: 8.0,
+            cda: 0.32,
+            crr: 0.004,
+            grade: 0.0,
+        }
+    }
+}
+
+/// Steady state speed in km/h for the given power.
+///
+/// Solves `P * efficiency = v * (m * g * (Crr * cos(a) + sin(a)) + 0.5 * rho * CdA * v^2)` for `v`.
+pub fn virtual_speed(power: u16, rider_weight: f64, config: &VirtualSpeedConfig) -> f64 {
+    let rider_weight = if rider_weight > 0.0 {
+        rider_weight
+    } else {
+        DEFAULT_RIDER_WEIGHT
+    };
+
+    let mass = rider_weight + config.bike_weight;
+    let angle = config.grade.atan();
+
+    // Cubic in the form a * v^3 + b * v - c = 0
+    let a = 0.5 * AIR_DENSITY * config.cda;
+    let b = mass * GRAVITY * (config.crr * angle.cos() + angle.sin());
+    let c = power as f64 * DRIVETRAIN_EFFICIENCY;
+
+    if a <= 0.0 {
+        return if b > 0.0 { c / b * MPS_TO_KPH } else { 0.0 };
+    }
+
+    // Depressed cubic t^3 + p * t + q = 0
+    let p = b / a;
+    let q = -c / a;
+    let discriminant = (q / 2.0).powi(2) + (p / 3.0).powi(3);
+
+    let speed = if discriminant >= 0.0 {
+        let root = discriminant.sqrt();
+
+        (-q / 2.0 + root).cbrt() + (-q / 2.0 - root).cbrt()
+    } else {
+        // Three real roots on descents, the largest one is the rolling speed
+        let r = 2.0 * (-p / 3.0).sqrt();
+        let phi = ((3.0 * q) / (2.0 * p) * (-3.0 / p).sqrt()).acos();
+
+        r * (phi / 3.0).cos()
+    };
+
+    speed.max(0.0) * MPS_TO_KPH
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_power_on_flat_road_is_standing_still() {
+        let speed = virtual_speed(0, 75.0, &VirtualSpeedConfig::default());
+
+        assert_eq!(speed, 0.0);
+    }
+
+    #[test]
+    fn speed_on_flat_road() {
+        let speed = virtual_speed(200, 75.0, &VirtualSpeedConfig::default());
+
+        assert!(speed > 30.0 && speed < 36.0, "speed was {}", speed);
+    }
+
+    #[test]
+    fn climbing_is_slower_than_flat() {
+        let climb = VirtualSpeedConfig {
+            grade: 0.06,
+            ..Default::default()
+        };
+
+        let flat_speed = virtual_speed(250, 75.0, &VirtualSpeedConfig::default());
+        let climb_speed = virtual_speed(250, 75.0, &climb);
+
+        assert!(climb_speed < flat_speed / 2.0);
+    }
+
+    #[test]
+    fn rolls_downhill_without_power() {
+        let descent = VirtualSpeedConfig {
+            grade: -0.05,
+            ..Default::default()
+        };
+
+        let speed = virtual_speed(0, 75.0, &descent);
+
+        assert!(speed > 40.0, "speed was {}", speed);
+    }
+}
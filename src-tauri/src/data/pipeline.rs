@@ -0,0 +1,524 @@
+use serde::{Deserialize, Serialize};
+
+use super::{heart_rate_measurement::HeartRateMeasurement, indoor_bike_data::IndoorBikeData};
+
+// Seconds without a measured value before a field is considered dropped out
+const DEFAULT_DROPOUT_TIMEOUT: u32 = 5;
+
+const FIELDS: [Field; 4] = [Field::HeartRate, Field::Power, Field::Cadence, Field::Speed];
+
+/// Strategy used to fill a second in which a sensor did not report a value
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GapFill {
+    HoldLast,
+    Zero,
+    Interpolate,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SensorSource {
+    HeartRateMonitor,
+    SmartTrainer,
+    Simulation,
+    // Calculated from power, see `physics::virtual_speed`
+    VirtualSpeed,
+    // Read from an activity file recorded elsewhere
+    Imported,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Field {
+    HeartRate,
+    Power,
+    Cadence,
+    Speed,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineConfig {
+    pub gap_fill: GapFill,
+    pub dropout_timeout: u32,
+}
+
+impl Default for PipelineConfig {
+    fn default() -> Self {
+        Self {
+            gap_fill: GapFill::HoldLast,
+            dropout_timeout: DEFAULT_DROPOUT_TIMEOUT,
+        }
+    }
+}
+
+/// Where the value of a field in a record came from.
+/// `fill` is set when the value was not measured during that second.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Attribution {
+    pub source: SensorSource,
+    pub fill: Option<GapFill>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Sources {
+    pub heart_rate: Option<Attribution>,
+    pub power: Option<Attribution>,
+    pub cadence: Option<Attribution>,
+    pub speed: Option<Attribution>,
+}
+
+/// One second of the session timeline
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Record {
+    pub elapsed: u32,
+    pub heart_rate: Option<u16>,
+    pub power: Option<u16>,
+    pub cadence: Option<u16>,
+    pub speed: Option<u16>,
+    pub sources: Sources,
+    pub dropouts: Vec<Field>,
+    // W' balance in joules, see `w_balance::WBalance`
+    #[serde(default)]
+    pub w_balance: Option<u32>,
+}
+
+impl Record {
+    fn get(&self, field: Field) -> (Option<u16>, Option<Attribution>) {
+        match field {
+            Field::HeartRate => (self.heart_rate, self.sources.heart_rate),
+            Field::Power => (self.power, self.sources.power),
+            Field::Cadence => (self.cadence, self.sources.cadence),
+            Field::Speed => (self.speed, self.sources.speed),
+        }
+    }
+
+    fn set(&mut self, field: Field, value: Option<u16>, attribution: Option<Attribution>) {
+        let (slot, source) = match field {
+            Field::HeartRate => (&mut self.heart_rate, &mut self.sources.heart_rate),
+            Field::Power => (&mut self.power, &mut self.sources.power),
+            Field::Cadence => (&mut self.cadence, &mut self.sources.cadence),
+            Field::Speed => (&mut self.speed, &mut self.sources.speed),
+        };
+
+        *slot = value;
+        *source = attribution;
+    }
+}
+
+#[derive(Default)]
+struct Channel {
+    // Samples received since the last tick
+    pending: Vec<u16>,
+    pending_source: Option<SensorSource>,
+
+    // Last measured value, used for filling gaps
+    last: Option<(u16, SensorSource)>,
+    ticks_since_seen: u32,
+}
+
+/// Merges the sensor streams into a fixed 1 Hz timeline.
+///
+/// Sensors push samples whenever they notify, and `tick` is called once per second
+/// to close the current bucket and append a `Record` to the timeline.
+pub struct Pipeline {
+    config: PipelineConfig,
+    heart_rate: Channel,
+    power: Channel,
+    cadence: Channel,
+    speed: Channel,
+}
+
+impl Pipeline {
+    pub fn new(config: PipelineConfig) -> Self {
+        Self {
+            config,
+            heart_rate: Channel::default(),
+            power: Channel::default(),
+            cadence: Channel::default(),
+            speed: Channel::default(),
+        }
+    }
+
+    pub fn push(&mut self, source: SensorSource, field: Field, value: u16) {
+        let channel = self.channel(field);
+
+        channel.pending.push(value);
+        channel.pending_source = Some(source);
+    }
+
+    pub fn push_heart_rate_measurement(
+        &mut self,
+        source: SensorSource,
+        data: &HeartRateMeasurement,
+    ) {
+        // A strap that reports poor skin contact sends unreliable values
+        if data.is_sensor_contact_supported && !data.is_sensor_in_contact {
+            return;
+        }
+
+        self.push(source, Field::HeartRate, data.bpm);
+    }
+
+    pub fn push_indoor_bike_data(&mut self, source: SensorSource, data: &IndoorBikeData) {
+        let fields = [
+            (Field::Power, data.power),
+            (Field::Cadence, data.cadence),
+            (Field::Speed, data.speed),
+        ];
+
+        for (field, value) in fields {
+            if let Some(value) = value {
+                self.push(source, field, value);
+            }
+        }
+    }
+
+    /// Closes the current one second bucket and appends its record to `records`
+    pub fn tick(&mut self, records: &mut Vec<Record>) -> Record {
+        let mut record = Record {
+            elapsed: records.len() as u32,
+            ..Default::default()
+        };
+
+        for field in FIELDS {
+            self.resample(field, &mut record);
+        }
+
+        records.push(record.clone());
+
+        record
+    }
+
+    /// Drops the samples received since the last tick, e.g. while the session is paused
+    pub fn discard_pending(&mut self) {
+        for field in FIELDS {
+            let channel = self.channel(field);
+
+            channel.pending.clear();
+            channel.pending_source = None;
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.heart_rate = Channel::default();
+        self.power = Channel::default();
+        self.cadence = Channel::default();
+        self.speed = Channel::default();
+    }
+
+    fn resample(&mut self, field: Field, record: &mut Record) {
+        let gap_fill = self.config.gap_fill;
+        let dropout_timeout = self.config.dropout_timeout;
+        let channel = self.channel(field);
+
+        if let Some(source) = channel.pending_source.take() {
+            let sum: u32 = channel.pending.iter().map(|v| *v as u32).sum();
+            let value = (sum as f64 / channel.pending.len() as f64).round() as u16;
+
+            channel.pending.clear();
+
+            channel.last = Some((value, source));
+            channel.ticks_since_seen = 0;
+
+            record.set(field, Some(value), Some(Attribution { source, fill: None }));
+
+            return;
+        }
+
+        // Sensor has not reported yet, e.g. not connected
+        let Some((last_value, source)) = channel.last else {
+            return;
+        };
+
+        channel.ticks_since_seen += 1;
+
+        if channel.ticks_since_seen > dropout_timeout {
+            record.dropouts.push(field);
+
+            return;
+        }
+
+        let value = match gap_fill {
+            GapFill::Zero => 0,
+            // Held while riding, see `interpolate_gaps`
+            GapFill::HoldLast | GapFill::Interpolate => last_value,
+        };
+
+        record.set(
+            field,
+            Some(value),
+            Some(Attribution {
+                source,
+                fill: Some(gap_fill),
+            }),
+        );
+    }
+
+    fn channel(&mut self, field: Field) -> &mut Channel {
+        match field {
+            Field::HeartRate => &mut self.heart_rate,
+            Field::Power => &mut self.power,
+            Field::Cadence => &mut self.cadence,
+            Field::Speed => &mut self.speed,
+        }
+    }
+}
+
+/// Replaces the values held through gaps in `GapFill::Interpolate` mode with a
+/// straight line between the measurements around each gap.
+///
+/// Applied once the session is complete, as the held values were already shown
+/// live and written to the journal. Gaps that end in a dropout keep their values.
+pub fn interpolate_gaps(records: &mut [Record]) {
+    for field in FIELDS {
+        // Index and value of the last measurement
+        let mut measured: Option<(usize, u16)> = None;
+
+        for index in 0..records.len() {
+            let (value, attribution) = records[index].get(field);
+
+            match attribution.map(|attribution| attribution.fill) {
+                Some(None) => {
+                    if let (Some((start, from)), Some(to)) = (measured, value) {
+                        interpolate_gap(field, &mut records[start + 1..index], from, to);
+                    }
+
+                    measured = value.map(|value| (index, value));
+                }
+                Some(Some(GapFill::Interpolate)) => {}
+                _ => measured = None,
+            }
+        }
+    }
+}
+
+fn interpolate_gap(field: Field, gap: &mut [Record], from: u16, to: u16) {
+    let steps = gap.len() as f64 + 1.0;
+    let delta = to as f64 - from as f64;
+
+    for (i, record) in gap.iter_mut().enumerate() {
+        let (_, attribution) = record.get(field);
+        let value = from as f64 + delta * (i as f64 + 1.0) / steps;
+
+        record.set(field, Some(value.round() as u16), attribution);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pipeline(gap_fill: GapFill, dropout_timeout: u32) -> Pipeline {
+        Pipeline::new(PipelineConfig {
+            gap_fill,
+            dropout_timeout,
+        })
+    }
+
+    // One tick per item, `None` for a second without a power sample
+    fn ride(pipeline: &mut Pipeline, powers: &[Option<u16>]) -> Vec<Record> {
+        let mut records = Vec::new();
+
+        for power in powers {
+            if let Some(power) = power {
+                pipeline.push(SensorSource::SmartTrainer, Field::Power, *power);
+            }
+
+            pipeline.tick(&mut records);
+        }
+
+        records
+    }
+
+    fn powers(records: &[Record]) -> Vec<Option<u16>> {
+        records.iter().map(|record| record.power).collect()
+    }
+
+    fn power_fills(records: &[Record]) -> Vec<Option<GapFill>> {
+        records
+            .iter()
+            .map(|record| record.sources.power.and_then(|source| source.fill))
+            .collect()
+    }
+
+    #[test]
+    fn averages_irregular_samples_per_second() {
+        let mut pipeline = pipeline(GapFill::HoldLast, 5);
+        let mut records = Vec::new();
+
+        pipeline.push(SensorSource::HeartRateMonitor, Field::HeartRate, 120);
+        pipeline.push(SensorSource::SmartTrainer, Field::Power, 200);
+        pipeline.push(SensorSource::SmartTrainer, Field::Power, 211);
+        pipeline.tick(&mut records);
+
+        pipeline.push(SensorSource::SmartTrainer, Field::Power, 230);
+        pipeline.push(SensorSource::SmartTrainer, Field::Power, 240);
+        pipeline.push(SensorSource::SmartTrainer, Field::Power, 250);
+        pipeline.tick(&mut records);
+
+        pipeline.push(SensorSource::HeartRateMonitor, Field::HeartRate, 124);
+        pipeline.push(SensorSource::HeartRateMonitor, Field::HeartRate, 126);
+        pipeline.tick(&mut records);
+
+        let elapsed: Vec<u32> = records.iter().map(|record| record.elapsed).collect();
+        let heart_rates: Vec<Option<u16>> =
+            records.iter().map(|record| record.heart_rate).collect();
+
+        assert_eq!(elapsed, vec![0, 1, 2]);
+        assert_eq!(powers(&records), vec![Some(206), Some(240), Some(240)]);
+        assert_eq!(heart_rates, vec![Some(120), Some(120), Some(125)]);
+        assert_eq!(
+            records[1].sources.heart_rate.unwrap().fill,
+            Some(GapFill::HoldLast)
+        );
+        assert_eq!(
+            records[2].sources.power.unwrap().fill,
+            Some(GapFill::HoldLast)
+        );
+    }
+
+    #[test]
+    fn holds_the_last_value_through_gaps() {
+        let records = ride(
+            &mut pipeline(GapFill::HoldLast, 5),
+            &[Some(100), None, None, Some(160)],
+        );
+
+        assert_eq!(
+            powers(&records),
+            vec![Some(100), Some(100), Some(100), Some(160)]
+        );
+        assert_eq!(
+            power_fills(&records),
+            vec![None, Some(GapFill::HoldLast), Some(GapFill::HoldLast), None]
+        );
+    }
+
+    #[test]
+    fn fills_gaps_with_zero() {
+        let records = ride(
+            &mut pipeline(GapFill::Zero, 5),
+            &[Some(100), None, None, Some(160)],
+        );
+
+        assert_eq!(
+            powers(&records),
+            vec![Some(100), Some(0), Some(0), Some(160)]
+        );
+        assert_eq!(
+            power_fills(&records),
+            vec![None, Some(GapFill::Zero), Some(GapFill::Zero), None]
+        );
+    }
+
+    #[test]
+    fn interpolates_gaps_once_the_session_is_complete() {
+        let mut records = ride(
+            &mut pipeline(GapFill::Interpolate, 5),
+            &[Some(100), None, None, Some(160)],
+        );
+
+        // Records that were already emitted are not rewritten
+        assert_eq!(
+            powers(&records),
+            vec![Some(100), Some(100), Some(100), Some(160)]
+        );
+
+        interpolate_gaps(&mut records);
+
+        assert_eq!(
+            powers(&records),
+            vec![Some(100), Some(120), Some(140), Some(160)]
+        );
+        assert_eq!(
+            power_fills(&records),
+            vec![
+                None,
+                Some(GapFill::Interpolate),
+                Some(GapFill::Interpolate),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn detects_dropouts_after_the_timeout() {
+        let mut records = ride(
+            &mut pipeline(GapFill::Interpolate, 2),
+            &[Some(100), None, None, None, None, Some(160)],
+        );
+
+        assert_eq!(
+            powers(&records),
+            vec![Some(100), Some(100), Some(100), None, None, Some(160)]
+        );
+        assert!(records[2].dropouts.is_empty());
+        assert_eq!(records[3].dropouts, vec![Field::Power]);
+        assert_eq!(records[4].dropouts, vec![Field::Power]);
+        assert!(records[5].dropouts.is_empty());
+
+        // A gap that ends in a dropout is not interpolated
+        interpolate_gaps(&mut records);
+
+        assert_eq!(records[1].power, Some(100));
+        assert_eq!(records[2].power, Some(100));
+    }
+
+    #[test]
+    fn attributes_each_field_to_its_source() {
+        let mut pipeline = pipeline(GapFill::HoldLast, 5);
+        let mut records = Vec::new();
+
+        pipeline.push_heart_rate_measurement(
+            SensorSource::HeartRateMonitor,
+            &HeartRateMeasurement {
+                bpm: 130,
+                is_sensor_in_contact: true,
+                is_sensor_contact_supported: true,
+            },
+        );
+        pipeline.push_indoor_bike_data(
+            SensorSource::SmartTrainer,
+            &IndoorBikeData {
+                cadence: Some(90),
+                speed: None,
+                distance: None,
+                power: Some(180),
+            },
+        );
+        pipeline.push(SensorSource::VirtualSpeed, Field::Speed, 30);
+        pipeline.tick(&mut records);
+
+        let measured = |source| Some(Attribution { source, fill: None });
+        let sources = &records[0].sources;
+
+        assert_eq!(sources.heart_rate, measured(SensorSource::HeartRateMonitor));
+        assert_eq!(sources.power, measured(SensorSource::SmartTrainer));
+        assert_eq!(sources.cadence, measured(SensorSource::SmartTrainer));
+        assert_eq!(sources.speed, measured(SensorSource::VirtualSpeed));
+    }
+
+    #[test]
+    fn ignores_heart_rate_without_skin_contact() {
+        let mut pipeline = pipeline(GapFill::HoldLast, 5);
+        let mut records = Vec::new();
+
+        pipeline.push_heart_rate_measurement(
+            SensorSource::HeartRateMonitor,
+            &HeartRateMeasurement {
+                bpm: 200,
+                is_sensor_in_contact: false,
+                is_sensor_contact_supported: true,
+            },
+        );
+        pipeline.tick(&mut records);
+
+        assert_eq!(records[0].heart_rate, None);
+        assert_eq!(records[0].sources.heart_rate, None);
+    }
+}
@@ -0,0 +1,268 @@
+use chrono::{DateTime, Duration, Local};
+use serde::Serialize;
+
+use crate::prelude::*;
+use crate::system::directory;
+
+use super::{pipeline::Record, session::Session};
+
+// Longest duration of the curve, in seconds
+const MAX_DURATION: usize = 3600;
+const RECENT_DAYS: i64 = 90;
+
+// Durations checked for new personal records during a ride, in seconds
+const KEY_DURATIONS: [usize; 6] = [1, 5, 60, 300, 1200, 3600];
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PersonalRecord {
+    // Seconds
+    pub duration: u32,
+    pub power: u16,
+    pub session_id: String,
+    pub date: Option<DateTime<Local>>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PersonalRecords {
+    pub all_time: Vec<PersonalRecord>,
+    pub last_90_days: Vec<PersonalRecord>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewPersonalRecord {
+    pub duration: u32,
+    pub power: u16,
+    pub previous_power: u16,
+}
+
+/// Best average power for every duration from 1 second up to an hour.
+/// Index 0 holds the best 1 second power.
+pub fn mean_max_power(records: &[Record]) -> Vec<u16> {
+    // Dropouts count as no power
+    let mut prefix = Vec::with_capacity(records.len() + 1);
+    prefix.push(0u64);
+
+    for record in records {
+        let last = prefix[prefix.len() - 1];
+        prefix.push(last + record.power.unwrap_or(0) as u64);
+    }
+
+    let longest = records.len().min(MAX_DURATION);
+
+    (1..=longest)
+        .map(|duration| {
+            let best = (duration..prefix.len())
+                .map(|end| prefix[end] - prefix[end - duration])
+                .max()
+                .unwrap_or(0);
+
+            (best as f64 / duration as f64).round() as u16
+        })
+        .collect()
+}
+
+/// Power curve stored with the session, calculated for sessions saved without one
+pub fn session_power_curve(session: &Session) -> Vec<u16> {
+    match &session.power_curve {
+        Some(power_curve) => power_curve.clone(),
+        None => mean_max_power(&session.records),
+    }
+}
+
+/// Aggregates the power curves of all saved sessions into personal record tables
+pub fn load_personal_records() -> Result<PersonalRecords> {
+    let sessions = directory::list_session_headers(|session| session.power_curve.is_some())?;
+    let recent_since = Local::now() - Duration::days(RECENT_DAYS);
+
+    let mut all_time: Vec<PersonalRecord> = Vec::new();
+    let mut last_90_days: Vec<PersonalRecord> = Vec::new();
+
+    for (session_id, session) in sessions.iter() {
+        let power_curve = session_power_curve(session);
+        let is_recent = session.start_time.is_some_and(|date| date >= recent_since);
+
+        merge_power_curve(&mut all_time, &power_curve, session_id, session.start_time);
+
+        if is_recent {
+            merge_power_curve(
+                &mut last_90_days,
+                &power_curve,
+                session_id,
+                session.start_time,
+            );
+        }
+    }
+
+    Ok(PersonalRecords {
+        all_time,
+        last_90_days,
+    })
+}
+
+fn merge_power_curve(
+    table: &mut Vec<PersonalRecord>,
+    power_curve: &[u16],
+    session_id: &str,
+    date: Option<DateTime<Local>>,
+) {
+    for (index, power) in power_curve.iter().enumerate() {
+        let record = PersonalRecord {
+            duration: index as u32 + 1,
+            power: *power,
+            session_id: session_id.to_string(),
+            date,
+        };
+
+        match table.get_mut(index) {
+            Some(best) if best.power >= *power => {}
+            Some(best) => *best = record,
+            None => table.push(record),
+        }
+    }
+}
+
+/// Watches the power of a ride for new all-time records at the key durations
+pub struct PersonalRecordTracker {
+    bests: Vec<(usize, Option<u16>)>,
+    prefix: Vec<u64>,
+}
+
+impl PersonalRecordTracker {
+    /// `best_power_curve` holds the all-time best power per duration, starting at 1 second
+    pub fn new(best_power_curve: &[u16]) -> Self {
+        let bests = KEY_DURATIONS
+            .iter()
+            .map(|duration| (*duration, best_power_curve.get(duration - 1).copied()))
+            .collect();
+
+        Self {
+            bests,
+            prefix: vec![0],
+        }
+    }
+
+    /// Adds one second of power and returns the records it sets
+    pub fn update(&mut self, power: Option<u16>) -> Vec<NewPersonalRecord> {
+        let last = self.prefix[self.prefix.len() - 1];
+        self.prefix.push(last + power.unwrap_or(0) as u64);
+
+        let end = self.prefix.len() - 1;
+        let mut new_records = Vec::new();
+
+        for (duration, best) in self.bests.iter_mut() {
+            if end < *duration {
+                continue;
+            }
+
+            let sum = self.prefix[end] - self.prefix[end - *duration];
+            let average = (sum as f64 / *duration as f64).round() as u16;
+
+            // Nothing to beat yet, e.g. the first ride
+            let Some(previous_power) = *best else {
+                continue;
+            };
+
+            if average <= previous_power {
+                continue;
+            }
+
+            new_records.push(NewPersonalRecord {
+                duration: *duration as u32,
+                power: average,
+                previous_power,
+            });
+
+            *best = Some(average);
+        }
+
+        new_records
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records(power: &[Option<u16>]) -> Vec<Record> {
+        power
+            .iter()
+            .enumerate()
+            .map(|(elapsed, power)| Record {
+                elapsed: elapsed as u32,
+                power: *power,
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn steps(steps: &[(u16, usize)]) -> Vec<Option<u16>> {
+        steps
+            .iter()
+            .flat_map(|(power, seconds)| std::iter::repeat_n(Some(*power), *seconds))
+            .collect()
+    }
+
+    #[test]
+    fn finds_the_best_average_per_duration() {
+        // 10 s at 100 W, 5 s at 400 W, 10 s at 200 W
+        let records = records(&steps(&[(100, 10), (400, 5), (200, 10)]));
+
+        let curve = mean_max_power(&records);
+
+        assert_eq!(curve.len(), 25);
+        assert_eq!(curve[0], 400);
+        assert_eq!(curve[4], 400);
+        // 5 s at 400 W and 5 s at 200 W
+        assert_eq!(curve[9], 300);
+        // 5 s at 400 W and 10 s at 200 W
+        assert_eq!(curve[14], 267);
+        assert_eq!(curve[24], 200);
+    }
+
+    #[test]
+    fn counts_dropouts_as_no_power() {
+        let curve = mean_max_power(&records(&[Some(300), None, Some(300)]));
+
+        assert_eq!(curve, vec![300, 150, 200]);
+    }
+
+    #[test]
+    fn keeps_the_best_of_each_session() {
+        let mut table = Vec::new();
+
+        merge_power_curve(&mut table, &[400, 300], "first", None);
+        merge_power_curve(&mut table, &[350, 320, 250], "second", None);
+
+        let bests: Vec<(u16, &str)> = table
+            .iter()
+            .map(|record| (record.power, record.session_id.as_str()))
+            .collect();
+
+        assert_eq!(
+            bests,
+            vec![(400, "first"), (320, "second"), (250, "second")]
+        );
+    }
+
+    #[test]
+    fn reports_new_records_at_key_durations() {
+        let mut tracker = PersonalRecordTracker::new(&[500, 450, 420, 410, 400]);
+
+        for _ in 0..4 {
+            assert!(tracker.update(Some(450)).is_empty());
+        }
+
+        let new_records = tracker.update(Some(450));
+
+        assert_eq!(new_records.len(), 1);
+        assert_eq!(new_records[0].duration, 5);
+        assert_eq!(new_records[0].power, 450);
+        assert_eq!(new_records[0].previous_power, 400);
+
+        // The new record has to be beaten now
+        assert!(tracker.update(Some(450)).is_empty());
+    }
+}
@@ -0,0 +1,400 @@
+use chrono::Local;
+use log::warn;
+use std::time::Instant;
+use tauri::{AppHandle, Manager};
+use tokio::sync::{Mutex, RwLock};
+
+use crate::error::error_generic;
+use crate::prelude::*;
+use crate::system::{
+    directory,
+    user::{get_user_settings, UserSettings},
+};
+use crate::workouts::library;
+
+use super::{
+    aerobic::analyze_aerobic,
+    heart_rate_measurement::HeartRateMeasurement,
+    indoor_bike_data::IndoorBikeData,
+    journal::Journal,
+    metrics::{calculate_metrics, Metrics},
+    physics::virtual_speed,
+    pipeline::{interpolate_gaps, Field, Pipeline, SensorSource},
+    power_curve::{mean_max_power, NewPersonalRecord, PersonalRecordTracker},
+    session::{Session, SessionCommand, SessionStatus},
+    w_balance::{WBalance, WBalanceWarning},
+    zones::{live_time_in_zones, TimeInZones, Zones},
+};
+
+const LOGGER_NAME: &str = "data::recorder";
+
+/// Library workout ridden in a session, as it was when the session started
+pub struct SessionWorkout {
+    pub id: String,
+    pub name: String,
+    pub version: String,
+}
+
+/// Events raised by a second appended to the session
+pub struct RecordedSecond {
+    pub personal_records: Vec<NewPersonalRecord>,
+    pub w_balance_warning: Option<WBalanceWarning>,
+}
+
+impl RecordedSecond {
+    pub fn emit(&self, app_handle: &AppHandle) {
+        if let Some(warning) = &self.w_balance_warning {
+            app_handle
+                .emit_all("w_balance_warning", warning.clone())
+                .ok();
+        }
+
+        for personal_record in self.personal_records.iter() {
+            app_handle
+                .emit_all("personal_record", personal_record.clone())
+                .ok();
+        }
+    }
+}
+
+/// Owns the session and the sensor pipeline feeding it.
+///
+/// Used by both the bluetooth devices and the simulation so that the
+/// session lifecycle behaves the same for both.
+pub struct Recorder {
+    session: RwLock<Session>,
+    pipeline: Mutex<Pipeline>,
+    personal_records: Mutex<Option<PersonalRecordTracker>>,
+    w_balance: Mutex<Option<WBalance>>,
+    journal: Mutex<Option<Journal>>,
+    // Read again whenever a session starts
+    settings: RwLock<UserSettings>,
+}
+
+impl Recorder {
+    pub fn new(settings: &UserSettings) -> Self {
+        Self {
+            session: RwLock::new(Session::new()),
+            pipeline: Mutex::new(Pipeline::new(settings.recording.clone())),
+            personal_records: Mutex::new(None),
+            w_balance: Mutex::new(None),
+            journal: Mutex::new(None),
+            settings: RwLock::new(settings.clone()),
+        }
+    }
+
+    /// Checks that `command` is valid without applying it,
+    /// e.g. before sending the matching control to the trainer.
+    ///
+    /// Returns the workout a started session rides, see `apply`.
+    pub async fn validate(
+        &self,
+        command: SessionCommand,
+        workout_id: Option<String>,
+    ) -> Result<Option<SessionWorkout>> {
+        self.session.read().await.transition(command)?;
+
+        match (command, workout_id) {
+            (SessionCommand::Start, Some(workout_id)) => {
+                let (name, version) = library::find_workout(&workout_id)
+                    .await
+                    .ok_or_else(|| error_generic("Workout not found"))?;
+
+                Ok(Some(SessionWorkout {
+                    id: workout_id,
+                    name,
+                    version,
+                }))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Applies `command` to the session, `workout` is the one returned by `validate`
+    pub async fn apply(
+        &self,
+        command: SessionCommand,
+        workout: Option<SessionWorkout>,
+    ) -> Result<SessionStatus> {
+        let settings = get_user_settings().await;
+
+        // Queries the saved sessions, so it is done before taking the locks
+        let tracker = match command {
+            SessionCommand::Start => match directory::best_power_curve() {
+                Ok(best_power_curve) => Some(PersonalRecordTracker::new(&best_power_curve)),
+                Err(err) => {
+                    warn!(
+                        "{}::apply: Unable to load personal records: {}",
+                        LOGGER_NAME, err
+                    );
+
+                    None
+                }
+            },
+            _ => None,
+        };
+
+        let mut pipeline = self.pipeline.lock().await;
+        let mut session = self.session.write().await;
+
+        let status = session.apply(command)?;
+
+        if let SessionCommand::Start = command {
+            // Bound when the session starts, as the workout may be edited during the ride
+            if let Some(workout) = workout {
+                session.workout_id = Some(workout.id);
+                session.workout_name = Some(workout.name);
+                session.workout_version = Some(workout.version);
+            }
+
+            *pipeline = Pipeline::new(settings.recording.clone());
+            *self.personal_records.lock().await = tracker;
+            *self.w_balance.lock().await = WBalance::new(&settings.w_balance);
+            *self.settings.write().await = settings;
+
+            let mut journal = self.journal.lock().await;
+
+            // A finished session that was not saved is discarded with its journal
+            if let Some(previous) = journal.take() {
+                previous.discard();
+            }
+
+            *journal = match Journal::create(&session) {
+                Ok(journal) => Some(journal),
+                Err(err) => {
+                    warn!(
+                        "{}::apply: Unable to create the session journal: {}",
+                        LOGGER_NAME, err
+                    );
+
+                    None
+                }
+            };
+        } else {
+            self.write_journal(|journal| journal.command(command, &session))
+                .await;
+        }
+
+        Ok(status)
+    }
+
+    pub async fn push_heart_rate_measurement(
+        &self,
+        source: SensorSource,
+        data: &HeartRateMeasurement,
+    ) {
+        self.pipeline
+            .lock()
+            .await
+            .push_heart_rate_measurement(source, data);
+    }
+
+    /// Feeds the pipeline, filling in speed and distance when the trainer does not report them
+    pub async fn push_indoor_bike_data(&self, source: SensorSource, data: &mut IndoorBikeData) {
+        let now = Instant::now();
+        let settings = self.settings.read().await;
+
+        let speed = get_virtual_speed(data, &settings);
+
+        drop(settings);
+
+        {
+            let mut pipeline = self.pipeline.lock().await;
+
+            match speed {
+                Some(speed) => {
+                    data.speed = Some(speed.round() as u16);
+
+                    let measured = IndoorBikeData {
+                        speed: None,
+                        ..data.clone()
+                    };
+
+                    pipeline.push_indoor_bike_data(source, &measured);
+                    pipeline.push(
+                        SensorSource::VirtualSpeed,
+                        Field::Speed,
+                        speed.round() as u16,
+                    );
+                }
+                None => pipeline.push_indoor_bike_data(source, data),
+            }
+        }
+
+        let mut session = self.session.write().await;
+
+        if !session.is_running() {
+            return;
+        }
+
+        let distance = match (speed, data.speed, data.distance) {
+            // Only replaces a trainer distance when the virtual speed is enabled
+            (Some(speed), _, _) => session.add_distance(speed, now),
+            (None, _, Some(distance)) => session.add_device_distance(distance),
+            (None, Some(speed), None) => session.add_distance(speed as f64, now),
+            _ => return,
+        };
+
+        data.distance = Some(distance);
+    }
+
+    /// Called once per second to append the resampled data to the session.
+    ///
+    /// The caller emits the events of the second, see `RecordedSecond::emit`.
+    pub async fn record(&self) -> Option<RecordedSecond> {
+        let mut pipeline = self.pipeline.lock().await;
+        let mut session = self.session.write().await;
+
+        let mut record = session.record(&mut pipeline)?;
+
+        let mut w_balance_warning = None;
+
+        if let Some(w_balance) = self.w_balance.lock().await.as_mut() {
+            record.w_balance = Some(w_balance.update(record.power));
+
+            if let Some(last) = session.records.last_mut() {
+                last.w_balance = record.w_balance;
+            }
+
+            if w_balance.should_warn() {
+                w_balance_warning = Some(WBalanceWarning {
+                    elapsed: record.elapsed,
+                    w_balance: record.w_balance.unwrap_or(0),
+                    w_prime: w_balance.w_prime(),
+                });
+            }
+        }
+
+        let personal_records = match self.personal_records.lock().await.as_mut() {
+            Some(tracker) => tracker.update(record.power),
+            None => Vec::new(),
+        };
+
+        self.write_journal(|journal| journal.sync(&session, false))
+            .await;
+
+        Some(RecordedSecond {
+            personal_records,
+            w_balance_warning,
+        })
+    }
+
+    pub async fn get_session_data(&self) -> Session {
+        self.session.read().await.get_session_data()
+    }
+
+    pub async fn get_metrics(&self) -> Metrics {
+        let settings = get_user_settings().await;
+        let session = self.session.read().await;
+
+        calculate_metrics(&session, settings.ftp, settings.weight)
+    }
+
+    pub async fn get_time_in_zones(&self) -> TimeInZones {
+        let settings = get_user_settings().await;
+        let session = self.session.read().await;
+
+        live_time_in_zones(&session, &Zones::from_settings(&settings))
+    }
+
+    /// Persists the finished session and resets the recorder for the next one
+    pub async fn save(&self) -> Result<String> {
+        let mut pipeline = self.pipeline.lock().await;
+        let mut session = self.session.write().await;
+
+        let SessionStatus::Finished = session.status else {
+            return Err(error_generic("Only finished sessions can be saved"));
+        };
+
+        interpolate_gaps(&mut session.records);
+
+        let settings = get_user_settings().await;
+        session.metrics = Some(calculate_metrics(&session, settings.ftp, settings.weight));
+        session.power_curve = Some(mean_max_power(&session.records));
+        session.aerobic = Some(analyze_aerobic(&session));
+
+        let start_time = session.start_time.unwrap_or_else(Local::now);
+        let session_id = start_time.format("%Y%m%d%H%M%S").to_string();
+
+        directory::save_session(&session, &session_id)?;
+
+        if let Some(journal) = self.journal.lock().await.take() {
+            journal.discard();
+        }
+
+        *session = Session::new();
+        pipeline.reset();
+
+        Ok(session_id)
+    }
+
+    // A failing journal should not stop the recording
+    async fn write_journal(&self, write: impl FnOnce(&mut Journal) -> Result<()>) {
+        if let Some(journal) = self.journal.lock().await.as_mut() {
+            if let Err(err) = write(journal) {
+                warn!(
+                    "{}::write_journal: Unable to write the session journal: {}",
+                    LOGGER_NAME, err
+                );
+            }
+        }
+    }
+}
+
+/// Speed in km/h calculated from the power, when the virtual speed is enabled
+/// or the trainer reports neither speed nor distance
+fn get_virtual_speed(data: &IndoorBikeData, settings: &UserSettings) -> Option<f64> {
+    let power = data.power?;
+    let reported = data.speed.is_some() || data.distance.is_some();
+
+    (settings.virtual_speed.enabled || !reported)
+        .then(|| virtual_speed(power, settings.weight, &settings.virtual_speed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data(speed: Option<u16>, distance: Option<u32>) -> IndoorBikeData {
+        IndoorBikeData {
+            cadence: Some(90),
+            speed,
+            distance,
+            power: Some(200),
+        }
+    }
+
+    fn settings(enabled: bool) -> UserSettings {
+        let mut settings = UserSettings {
+            weight: 75.0,
+            ..Default::default()
+        };
+        settings.virtual_speed.enabled = enabled;
+
+        settings
+    }
+
+    #[test]
+    fn keeps_what_the_trainer_reports_without_virtual_speed() {
+        let settings = settings(false);
+
+        assert!(get_virtual_speed(&data(Some(30), None), &settings).is_none());
+        assert!(get_virtual_speed(&data(None, Some(1000)), &settings).is_none());
+        assert!(get_virtual_speed(&data(None, None), &settings).is_some());
+    }
+
+    #[test]
+    fn replaces_what_the_trainer_reports_with_virtual_speed() {
+        let settings = settings(true);
+
+        assert!(get_virtual_speed(&data(Some(30), Some(1000)), &settings).is_some());
+        assert!(get_virtual_speed(
+            &IndoorBikeData {
+                power: None,
+                ..data(None, None)
+            },
+            &settings
+        )
+        .is_none());
+    }
+}
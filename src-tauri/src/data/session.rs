@@ -1,88 +1,391 @@
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
 use std::time::{Duration, Instant};
-use serde::Serialize;
 
-const KPH_TO_KPS: f64 = 1.0 / 3600.0;
+use crate::error::error_generic;
+use crate::prelude::*;
 
-#[derive(Clone, Serialize)]
+use super::{
+    aerobic::AerobicAnalysis,
+    metrics::Metrics,
+    pipeline::{Pipeline, Record},
+};
+
+const KPH_TO_MPS: f64 = 1.0 / 3.6;
+
+// Longer gaps between speed samples are treated as a dropout instead of
+// being integrated with a stale speed
+const MAX_DISTANCE_GAP: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum SessionStatus {
-    Started,
+    Idle,
+    Running,
     Paused,
-    Stopped,
+    Finished,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionCommand {
+    Start,
+    Pause,
+    Resume,
+    Lap,
+    Finish,
 }
 
-#[derive(Clone, Serialize)]
-pub struct IndoorBikeData {
-    pub cadence: u16,
-    pub speed: u16,
-    pub power: u16,
+/// A pause taken during the session
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Pause {
+    // Moving time at which the session was paused
+    pub elapsed: u32,
+    // Seconds
+    pub duration: u32,
 }
 
-#[derive(Clone, Serialize)]
+#[derive(Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Session {
     pub status: SessionStatus,
-    pub indoor_bike_data: Vec<IndoorBikeData>,
-    pub heart_rate_data: Vec<u16>,
+    pub start_time: Option<DateTime<Local>>,
+    pub end_time: Option<DateTime<Local>>,
+    // Seconds spent paused, excluded from the moving time
+    pub paused_time: u32,
+    // Older sessions only have the total paused time
+    #[serde(default)]
+    pub pauses: Vec<Pause>,
+    // Elapsed seconds at which each lap after the first one starts
+    pub laps: Vec<u32>,
+    pub records: Vec<Record>,
     pub total_distance: u32,
+    // Calculated when the session is saved
+    #[serde(default)]
+    pub metrics: Option<Metrics>,
+    // Best average power per duration, see `power_curve::mean_max_power`
+    #[serde(default)]
+    pub power_curve: Option<Vec<u16>>,
+    // Pw:HR decoupling and efficiency, see `aerobic::analyze_aerobic`
+    #[serde(default)]
+    pub aerobic: Option<AerobicAnalysis>,
+
+    // Set by the user after the ride
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub workout_name: Option<String>,
+    // Library id and version of the workout that was ridden, see `workouts::library`
+    #[serde(default)]
+    pub workout_id: Option<String>,
+    #[serde(default)]
+    pub workout_version: Option<String>,
+    #[serde(default)]
+    pub notes: Option<String>,
+    // Rate of perceived exertion, 1 to 10
+    #[serde(default)]
+    pub rpe: Option<u8>,
+
+    #[serde(skip)]
+    paused_at: Option<Instant>,
+
+    // Time base for the distance integration, reset on every status change
+    #[serde(skip)]
+    distance: f64,
+    #[serde(skip)]
+    last_speed_at: Option<Instant>,
+    #[serde(skip)]
+    last_device_distance: Option<u32>,
 }
 
 impl Session {
     pub fn new() -> Self {
         Self {
-            status: SessionStatus::Stopped,
-            indoor_bike_data: Vec::new(),
-            heart_rate_data: Vec::new(),
+            status: SessionStatus::Idle,
+            start_time: None,
+            end_time: None,
+            paused_time: 0,
+            pauses: Vec::new(),
+            laps: Vec::new(),
+            records: Vec::new(),
             total_distance: 0,
+            metrics: None,
+            power_curve: None,
+            aerobic: None,
+            name: None,
+            workout_name: None,
+            workout_id: None,
+            workout_version: None,
+            notes: None,
+            rpe: None,
+            paused_at: None,
+            distance: 0.0,
+            last_speed_at: None,
+            last_device_distance: None,
         }
     }
 
-    pub fn start_session(&mut self) {
-        self.status = SessionStatus::Started;
+    /// Returns the status the session would be in after `command`,
+    /// or an error if the command is not valid in the current status.
+    pub fn transition(&self, command: SessionCommand) -> Result<SessionStatus> {
+        match (self.status, command) {
+            (SessionStatus::Idle | SessionStatus::Finished, SessionCommand::Start) => {
+                Ok(SessionStatus::Running)
+            }
+            (SessionStatus::Running, SessionCommand::Pause) => Ok(SessionStatus::Paused),
+            (SessionStatus::Paused, SessionCommand::Resume) => Ok(SessionStatus::Running),
+            (SessionStatus::Running, SessionCommand::Lap) => Ok(SessionStatus::Running),
+            (SessionStatus::Running | SessionStatus::Paused, SessionCommand::Finish) => {
+                Ok(SessionStatus::Finished)
+            }
+            (status, command) => {
+                let message = format!("Unable to {:?} a session that is {:?}", command, status);
+                Err(error_generic(message.as_str()))
+            }
+        }
     }
 
-    pub fn pause_session(&mut self) {
-        self.status = SessionStatus::Paused;
-    }
+    /// Starting from a finished session discards it in favor of a new one.
+    pub fn apply(&mut self, command: SessionCommand) -> Result<SessionStatus> {
+        let status = self.transition(command)?;
+
+        match command {
+            SessionCommand::Start => {
+                *self = Session::new();
+                self.start_time = Some(Local::now());
+            }
+            SessionCommand::Pause => {
+                self.paused_at = Some(Instant::now());
+            }
+            SessionCommand::Resume => self.end_pause(),
+            SessionCommand::Lap => {
+                let elapsed = self.moving_time();
 
-    pub fn stop_session(&mut self) {
-        self.status = SessionStatus::Stopped;
+                // Ignore empty laps, e.g. a double press
+                if self.laps.last() != Some(&elapsed) && elapsed > 0 {
+                    self.laps.push(elapsed);
+                }
+            }
+            SessionCommand::Finish => {
+                self.end_pause();
+                self.end_time = Some(Local::now());
+            }
+        }
+
+        if command != SessionCommand::Lap {
+            self.last_speed_at = None;
+            self.last_device_distance = None;
+        }
+
+        self.status = status;
+
+        Ok(status)
     }
 
-    pub fn add_indoor_bike_data(&mut self, data: IndoorBikeData) {
-        self.indoor_bike_data.push(data);
+    pub fn is_running(&self) -> bool {
+        self.status == SessionStatus::Running
     }
 
-    pub fn add_heart_rate_data(&mut self, bpm: u16) {
-        self.heart_rate_data.push(bpm);
+    /// Seconds recorded while the session was running
+    pub fn moving_time(&self) -> u32 {
+        self.records.len() as u32
     }
 
-    /// This is used for when FTMS supports total distance
-    pub fn set_total_distance(&mut self, distance: u32) {
-        self.total_distance = distance
+    /// Appends the resampled second to the timeline while the session is running
+    pub fn record(&mut self, pipeline: &mut Pipeline) -> Option<Record> {
+        if !self.is_running() {
+            pipeline.discard_pending();
+            return None;
+        }
+
+        Some(pipeline.tick(&mut self.records))
     }
 
-    pub fn calculate_total_distance(&mut self, speed: u16) -> u32 {
-        let elapsed_time = get_time_change();
+    /// This is used for when FTMS supports total distance.
+    /// Only the distance covered while running is added, so the trainer's own
+    /// counter can keep going through pauses and restarts.
+    pub fn add_device_distance(&mut self, distance: u32) -> u32 {
+        if let Some(last_distance) = self.last_device_distance.replace(distance) {
+            // The trainer resets its counter when it starts a new session
+            let delta = distance.checked_sub(last_distance).unwrap_or(distance);
 
-        let speed_kps = (speed as f64) * KPH_TO_KPS;
-        let distance = ((speed_kps * elapsed_time.as_secs_f64()) * 1000.0) as u32;
+            self.distance += delta as f64;
+            self.total_distance = self.distance as u32;
+        }
 
-        self.total_distance += distance;
+        self.total_distance
+    }
+
+    /// Integrates the speed in km/h over the time since the previous sample
+    pub fn add_distance(&mut self, speed: f64, now: Instant) -> u32 {
+        if let Some(last_speed_at) = self.last_speed_at.replace(now) {
+            let elapsed = now.saturating_duration_since(last_speed_at);
+
+            if elapsed <= MAX_DISTANCE_GAP {
+                self.distance += speed * KPH_TO_MPS * elapsed.as_secs_f64();
+                self.total_distance = self.distance as u32;
+            }
+        }
 
         self.total_distance
     }
 
+    /// Seconds between the start and the record at `elapsed`, including the
+    /// pauses taken before it.
+    ///
+    /// Paused time that is not in `pauses` is left after the last record.
+    pub fn time_since_start(&self, elapsed: u32) -> u32 {
+        let paused: u32 = self
+            .pauses
+            .iter()
+            .filter(|pause| pause.elapsed <= elapsed)
+            .map(|pause| pause.duration)
+            .sum();
+
+        elapsed + paused
+    }
+
+    /// Adds a pause at the current moving time, keeping `paused_time` as the total
+    pub fn add_pause(&mut self, duration: u32) {
+        if duration == 0 {
+            return;
+        }
+
+        let elapsed = self.moving_time();
+
+        match self.pauses.last_mut() {
+            // Pausing again before any record was taken
+            Some(pause) if pause.elapsed == elapsed => pause.duration += duration,
+            _ => self.pauses.push(Pause { elapsed, duration }),
+        }
+
+        self.paused_time += duration;
+    }
+
     pub fn get_session_data(&self) -> Session {
         self.to_owned()
     }
+
+    fn end_pause(&mut self) {
+        if let Some(paused_at) = self.paused_at.take() {
+            self.add_pause(paused_at.elapsed().as_secs() as u32);
+        }
+    }
 }
 
-fn get_time_change() -> Duration {
-    static mut LAST_CALL: Option<Instant> = None;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn running_session() -> Session {
+        let mut session = Session::new();
+        session.apply(SessionCommand::Start).unwrap();
+
+        session
+    }
+
+    fn seconds(start: Instant, seconds: u64) -> Instant {
+        start + Duration::from_secs(seconds)
+    }
+
+    #[test]
+    fn integrates_speed_over_time() {
+        let start = Instant::now();
+        let mut session = running_session();
+
+        for second in 0..=10 {
+            session.add_distance(36.0, seconds(start, second));
+        }
+
+        assert_eq!(session.total_distance, 100);
+    }
+
+    #[test]
+    fn excludes_paused_time_from_distance() {
+        let start = Instant::now();
+        let mut session = running_session();
+
+        session.add_distance(36.0, seconds(start, 0));
+        session.add_distance(36.0, seconds(start, 1));
+
+        session.apply(SessionCommand::Pause).unwrap();
+        session.apply(SessionCommand::Resume).unwrap();
+
+        // First sample after resuming only sets the time base
+        assert_eq!(session.add_distance(36.0, seconds(start, 61)), 10);
+        assert_eq!(session.add_distance(36.0, seconds(start, 62)), 20);
+    }
+
+    #[test]
+    fn restarts_distance_for_new_session() {
+        let start = Instant::now();
+        let mut session = running_session();
+
+        session.add_distance(36.0, seconds(start, 0));
+        session.add_distance(36.0, seconds(start, 1));
+        session.apply(SessionCommand::Finish).unwrap();
+        session.apply(SessionCommand::Start).unwrap();
 
-    let now = Instant::now();
+        assert_eq!(session.total_distance, 0);
+        assert_eq!(session.add_distance(36.0, seconds(start, 30)), 0);
+        assert_eq!(session.add_distance(36.0, seconds(start, 31)), 10);
+    }
+
+    #[test]
+    fn keeps_time_base_per_session() {
+        let start = Instant::now();
+        let mut first = running_session();
+        let mut second = running_session();
+
+        first.add_distance(36.0, seconds(start, 0));
+        second.add_distance(36.0, seconds(start, 1));
+        first.add_distance(36.0, seconds(start, 2));
+        second.add_distance(36.0, seconds(start, 3));
+
+        assert_eq!(first.total_distance, 20);
+        assert_eq!(second.total_distance, 20);
+    }
+
+    #[test]
+    fn ignores_gaps_longer_than_dropout() {
+        let start = Instant::now();
+        let mut session = running_session();
+
+        session.add_distance(36.0, seconds(start, 0));
+        session.add_distance(36.0, seconds(start, 30));
+        session.add_distance(36.0, seconds(start, 31));
+
+        assert_eq!(session.total_distance, 10);
+    }
 
-    let last_time = unsafe { LAST_CALL.replace(now).unwrap_or(now) };
+    #[test]
+    fn accumulates_device_distance_across_pauses_and_resets() {
+        let mut session = running_session();
 
-    now - last_time
+        assert_eq!(session.add_device_distance(1000), 0);
+        assert_eq!(session.add_device_distance(1050), 50);
+
+        session.apply(SessionCommand::Pause).unwrap();
+        session.apply(SessionCommand::Resume).unwrap();
+
+        assert_eq!(session.add_device_distance(1200), 50);
+        assert_eq!(session.add_device_distance(1210), 60);
+
+        // Trainer counter restarted
+        assert_eq!(session.add_device_distance(5), 65);
+    }
+
+    #[test]
+    fn rejects_invalid_transitions() {
+        let mut session = Session::new();
+
+        assert!(session.apply(SessionCommand::Pause).is_err());
+        assert!(session.apply(SessionCommand::Resume).is_err());
+
+        session.apply(SessionCommand::Start).unwrap();
+
+        assert!(session.apply(SessionCommand::Resume).is_err());
+        assert_eq!(session.status, SessionStatus::Running);
+    }
 }
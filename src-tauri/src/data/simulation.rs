@@ -1,21 +1,27 @@
-use std::sync::OnceLock;
+use std::sync::{Once, OnceLock};
 
 use rand::Rng;
 use tauri::Manager;
-use tokio::sync::{Mutex, RwLock};
-use tokio::time::{sleep, Duration};
+use tokio::sync::Mutex;
+use tokio::time::{interval, Duration};
 
+use crate::prelude::*;
+use crate::system::user::APP_USER;
 use crate::TAURI_APP_HANDLE;
 
-use super::session::SessionStatus;
 use super::{
     heart_rate_measurement::HeartRateMeasurement,
     indoor_bike_data::IndoorBikeData,
-    session::{self, Session},
+    pipeline::SensorSource,
+    recorder::Recorder,
+    session::{Session, SessionCommand},
 };
 
 pub static SIMULATION: OnceLock<Simulation> = OnceLock::new();
 
+// Guards the loops of the simulation, which run for as long as the app does
+static SIMULATION_LOOPS: Once = Once::new();
+
 pub enum SimulationStatus {
     Started,
     Paused,
@@ -24,18 +30,34 @@ pub enum SimulationStatus {
 
 pub struct Simulation {
     status: Mutex<SimulationStatus>,
-    session: RwLock<Option<Session>>,
+    pub recorder: Recorder,
     target_cadence: Mutex<Option<u16>>,
     target_power: Mutex<Option<u16>>,
 }
 
 impl Simulation {
-    pub fn new() -> Self {
-        tokio::spawn(handle_notifications());
+    /// The simulation, created along with its loops on first use
+    pub fn get() -> &'static Simulation {
+        let simulation = SIMULATION.get_or_init(Simulation::new);
+
+        SIMULATION_LOOPS.call_once(|| {
+            tokio::spawn(handle_notifications());
+            tokio::spawn(handle_session_recording());
+        });
+
+        simulation
+    }
+
+    fn new() -> Self {
+        let settings = APP_USER
+            .get()
+            .and_then(|lock| lock.try_read().ok())
+            .map(|user| user.settings.clone())
+            .unwrap_or_default();
 
         Self {
             status: Mutex::new(SimulationStatus::Paused),
-            session: RwLock::new(None),
+            recorder: Recorder::new(&settings),
             target_cadence: Mutex::new(Some(0)),
             target_power: Mutex::new(Some(0)),
         }
@@ -55,35 +77,19 @@ impl Simulation {
         *self.status.lock().await = status;
     }
 
-    pub async fn start_session(&self) {
-        let mut session_guard = self.session.write().await;
-        let mut session = Session::new();
+    pub async fn handle_session_command(
+        &self,
+        command: SessionCommand,
+        workout_id: Option<String>,
+    ) -> Result<()> {
+        let workout = self.recorder.validate(command, workout_id).await?;
+        self.recorder.apply(command, workout).await?;
 
-        session.start_session();
-
-        *session_guard = Some(session);
-    }
-
-    pub async fn stop_session(&self, action: &str) {
-        let mut session_guard = self.session.write().await;
-        let Some(session) = session_guard.as_mut() else {
-            return;
-        };
-
-        match action {
-            "stop" => session.stop_session(),
-            "pause" => session.pause_session(),
-            _ => {}
-        }
+        Ok(())
     }
 
     pub async fn get_session_data(&self) -> Session {
-        let session_guard = self.session.read().await;
-        let Some(session) = session_guard.as_ref() else {
-            return Session::new();
-        };
-
-        session.get_session_data()
+        self.recorder.get_session_data().await
     }
 
     pub async fn set_targets(&self, power: u16, cadence: u16) {
@@ -93,21 +99,16 @@ impl Simulation {
 }
 
 async fn handle_notifications() {
+    let mut ticker = interval(Duration::from_secs(1));
+
     loop {
-        let Some(sim) = SIMULATION.get() else {
-            break;
-        };
+        ticker.tick().await;
 
-        let app_guard = TAURI_APP_HANDLE.lock().await;
-        let Some(app) = app_guard.as_ref() else {
+        let Some(sim) = SIMULATION.get() else {
             break;
         };
 
-        if let SimulationStatus::Stopped = *sim.status.lock().await {
-            break;
-        }
-
-        if let SimulationStatus::Paused = *sim.status.lock().await {
+        if !matches!(*sim.status.lock().await, SimulationStatus::Started) {
             continue;
         }
 
@@ -161,40 +162,38 @@ async fn handle_notifications() {
             power,
         };
 
-        let mut session_guard = sim.session.write().await;
-        if let Some(session) = session_guard.as_mut() {
-            if let SessionStatus::Started = session.status {
-                session.add_heart_rate_data(hr_data.bpm);
-
-                match (bike_data.cadence, bike_data.speed, bike_data.power) {
-                    (Some(cadence), Some(speed), Some(power)) => {
-                        session.add_indoor_bike_data(session::IndoorBikeData {
-                            cadence,
-                            speed,
-                            power,
-                        });
-                    }
-                    _ => {}
-                }
-
-                match (bike_data.speed, bike_data.distance) {
-                    (Some(speed), None) => {
-                        let distance = session.calculate_total_distance(speed);
-
-                        bike_data.distance = Some(distance);
-                    }
-                    (_, Some(distance)) => {
-                        session.set_total_distance(distance);
-                    }
-                    _ => {}
-                }
-            }
+        sim.recorder
+            .push_heart_rate_measurement(SensorSource::Simulation, &hr_data)
+            .await;
+        sim.recorder
+            .push_indoor_bike_data(SensorSource::Simulation, &mut bike_data)
+            .await;
+
+        if let Some(app) = TAURI_APP_HANDLE.lock().await.as_ref() {
+            app.emit_all("hrm_notification", hr_data.to_owned()).ok();
+            app.emit_all("indoor_bike_notification", bike_data.to_owned())
+                .ok();
         }
+    }
+}
 
-        app.emit_all("hrm_notification", hr_data.to_owned()).ok();
-        app.emit_all("indoor_bike_notification", bike_data.to_owned())
-            .ok();
+/// Resamples the simulated streams into the session once per second
+async fn handle_session_recording() {
+    let mut ticker = interval(Duration::from_secs(1));
+
+    loop {
+        ticker.tick().await;
 
-        sleep(Duration::from_millis(1000)).await;
+        let Some(sim) = SIMULATION.get() else {
+            break;
+        };
+
+        let Some(second) = sim.recorder.record().await else {
+            continue;
+        };
+
+        if let Some(app_handle) = TAURI_APP_HANDLE.lock().await.as_ref() {
+            second.emit(app_handle);
+        }
     }
 }
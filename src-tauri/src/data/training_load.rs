@@ -0,0 +1,298 @@
+use chrono::{Duration, Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::error::error_generic;
+use crate::prelude::*;
+use crate::system::user::UserSettings;
+use crate::workouts::activities::{Activity, WorkoutType, ACTIVITIES};
+
+use super::history::list_sessions;
+
+// Assumed intensity of the steps without a target power, as a fraction of FTP
+const FREE_RIDE_INTENSITY: f64 = 0.6;
+const MAX_EFFORT_INTENSITY: f64 = 1.5;
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TrainingLoadConfig {
+    // Days over which the training stress is averaged
+    pub chronic_time_constant: u16,
+    pub acute_time_constant: u16,
+}
+
+impl Default for TrainingLoadConfig {
+    fn default() -> Self {
+        Self {
+            chronic_time_constant: 42,
+            acute_time_constant: 7,
+        }
+    }
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrainingLoadDay {
+    pub date: NaiveDate,
+    // Sum of the TSS of the sessions on that day
+    pub training_stress_score: f64,
+    // Fitness
+    pub chronic_training_load: f64,
+    // Fatigue
+    pub acute_training_load: f64,
+    // Form, the balance of the previous day so that a hard day doesn't lower its own form
+    pub training_stress_balance: f64,
+}
+
+/// A workout the user plans to ride on a given day
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlannedWorkout {
+    pub activity_id: String,
+    pub date: NaiveDate,
+}
+
+/// Daily CTL, ATL and TSB between `from` and `to`, including both.
+///
+/// The loads are built up from the first saved session, so days before
+/// `from` still count towards the values in the range.
+pub fn load_training_load(
+    settings: &UserSettings,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<Vec<TrainingLoadDay>> {
+    if from > to {
+        return Err(error_generic("Start date is after the end date"));
+    }
+
+    let daily_stress = load_daily_stress(settings)?;
+    let days = calculate_training_load(&daily_stress, to, &settings.training_load);
+
+    Ok(days.into_iter().filter(|day| day.date >= from).collect())
+}
+
+/// Training load on `date` if the planned workouts are ridden as prescribed.
+///
+/// Planned workouts before today are ignored, those were either ridden or skipped.
+pub async fn project_form(
+    settings: &UserSettings,
+    planned: &[PlannedWorkout],
+    date: NaiveDate,
+) -> Result<TrainingLoadDay> {
+    let Some(lock) = ACTIVITIES.get() else {
+        return Err(error_generic("Activities are not loaded"));
+    };
+
+    let activities = lock.read().await;
+    let today = Local::now().date_naive();
+
+    let mut daily_stress = load_daily_stress(settings)?;
+
+    for workout in planned.iter().filter(|workout| workout.date >= today) {
+        let Some(activity) = activities
+            .iter()
+            .find(|activity| activity.id == workout.activity_id)
+        else {
+            return Err(error_generic("Planned workout not found"));
+        };
+
+        let Some(activity) = activity.relative_to_ftp(settings.ftp) else {
+            return Err(error_generic(
+                "Planned workout has targets in watts and there is no FTP",
+            ));
+        };
+
+        *daily_stress.entry(workout.date).or_default() += planned_training_stress_score(&activity);
+    }
+
+    calculate_training_load(&daily_stress, date, &settings.training_load)
+        .into_iter()
+        .find(|day| day.date == date)
+        .ok_or_else(|| error_generic("Projected date is before the first session"))
+}
+
+/// Runs the exponentially weighted averages day by day from the first day with stress until `to`
+pub fn calculate_training_load(
+    daily_stress: &BTreeMap<NaiveDate, f64>,
+    to: NaiveDate,
+    config: &TrainingLoadConfig,
+) -> Vec<TrainingLoadDay> {
+    let Some(first) = daily_stress.keys().next().copied() else {
+        return Vec::new();
+    };
+
+    let chronic_decay = decay(config.chronic_time_constant);
+    let acute_decay = decay(config.acute_time_constant);
+
+    let mut days = Vec::new();
+    let mut chronic_training_load = 0.0;
+    let mut acute_training_load = 0.0;
+    let mut date = first;
+
+    while date <= to {
+        let training_stress_score = daily_stress.get(&date).copied().unwrap_or(0.0);
+        let training_stress_balance = chronic_training_load - acute_training_load;
+
+        chronic_training_load += (training_stress_score - chronic_training_load) * chronic_decay;
+        acute_training_load += (training_stress_score - acute_training_load) * acute_decay;
+
+        days.push(TrainingLoadDay {
+            date,
+            training_stress_score,
+            chronic_training_load,
+            acute_training_load,
+            training_stress_balance,
+        });
+
+        date += Duration::days(1);
+    }
+
+    days
+}
+
+/// Estimated TSS of riding the workout exactly at the prescribed power.
+///
+/// Targets have to be fractions of FTP, see `Activity::relative_to_ftp`.
+pub fn planned_training_stress_score(activity: &Activity) -> f64 {
+    activity
+        .workouts
+        .iter()
+        .map(|workout| {
+            // Mean square of the intensity, ramps change linearly
+            let intensity_squared = match workout.workout_type {
+                WorkoutType::SteadyState => workout.power_steady.powi(2),
+                WorkoutType::FreeRide => FREE_RIDE_INTENSITY.powi(2),
+                WorkoutType::MaxEffort => MAX_EFFORT_INTENSITY.powi(2),
+                WorkoutType::Warmup | WorkoutType::Cooldown | WorkoutType::Ramp => {
+                    let (low, high) = (workout.power_low, workout.power_high);
+
+                    (low * low + low * high + high * high) / 3.0
+                }
+            };
+
+            workout.duration as f64 * intensity_squared / 3600.0 * 100.0
+        })
+        .sum()
+}
+
+fn load_daily_stress(settings: &UserSettings) -> Result<BTreeMap<NaiveDate, f64>> {
+    let mut daily_stress: BTreeMap<NaiveDate, f64> = BTreeMap::new();
+
+    for summary in list_sessions(settings)? {
+        if let (Some(date), Some(training_stress_score)) =
+            (summary.date, summary.training_stress_score)
+        {
+            *daily_stress.entry(date.date_naive()).or_default() += training_stress_score;
+        }
+    }
+
+    Ok(daily_stress)
+}
+
+// Fraction of the difference to today's stress that a load moves by each day
+fn decay(time_constant: u16) -> f64 {
+    1.0 - (-1.0 / time_constant.max(1) as f64).exp()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workouts::activities::{PowerUnit, Workout};
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2026, 3, day).unwrap()
+    }
+
+    fn activity(workouts: Vec<Workout>) -> Activity {
+        Activity {
+            id: "test".to_string(),
+            name: "Test".to_string(),
+            description: String::new(),
+            author: String::new(),
+            tags: Vec::new(),
+            workouts,
+            path: None,
+        }
+    }
+
+    #[test]
+    fn builds_up_and_decays_after_one_day_of_load() {
+        let daily_stress = BTreeMap::from([(date(1), 100.0)]);
+
+        let days = calculate_training_load(&daily_stress, date(3), &TrainingLoadConfig::default());
+
+        assert_eq!(days.len(), 3);
+
+        let chronic = 100.0 * (1.0 - (-1.0_f64 / 42.0).exp());
+        let acute = 100.0 * (1.0 - (-1.0_f64 / 7.0).exp());
+
+        assert!((days[0].chronic_training_load - chronic).abs() < 1e-9);
+        assert!((days[0].acute_training_load - acute).abs() < 1e-9);
+        // Form is from the day before
+        assert_eq!(days[0].training_stress_balance, 0.0);
+
+        // Without stress the loads decay by their time constant
+        let chronic = chronic * (-1.0_f64 / 42.0).exp();
+        let acute = acute * (-1.0_f64 / 7.0).exp();
+
+        assert_eq!(days[1].training_stress_score, 0.0);
+        assert!((days[1].chronic_training_load - chronic).abs() < 1e-9);
+        assert!((days[1].acute_training_load - acute).abs() < 1e-9);
+        assert!(
+            (days[1].training_stress_balance
+                - (days[0].chronic_training_load - days[0].acute_training_load))
+                .abs()
+                < 1e-9
+        );
+        assert!(days[2].chronic_training_load < days[1].chronic_training_load);
+    }
+
+    #[test]
+    fn starts_at_the_first_day_with_stress() {
+        let daily_stress = BTreeMap::from([(date(2), 50.0), (date(4), 80.0)]);
+
+        let days = calculate_training_load(&daily_stress, date(5), &TrainingLoadConfig::default());
+
+        assert_eq!(days.first().unwrap().date, date(2));
+        assert_eq!(days.last().unwrap().date, date(5));
+        assert_eq!(days[2].training_stress_score, 80.0);
+
+        assert!(calculate_training_load(&BTreeMap::new(), date(5), &Default::default()).is_empty());
+    }
+
+    #[test]
+    fn plans_the_stress_of_a_workout() {
+        // One hour at FTP
+        let steady = Workout {
+            workout_type: WorkoutType::SteadyState,
+            duration: 3600,
+            power_steady: 1.0,
+            ..Default::default()
+        };
+
+        assert!((planned_training_stress_score(&activity(vec![steady])) - 100.0).abs() < 1e-9);
+
+        // Ramp from 0 to 100% of FTP, a third of the square of the top
+        let ramp = Workout {
+            workout_type: WorkoutType::Ramp,
+            duration: 3600,
+            power_low: 0.0,
+            power_high: 1.0,
+            ..Default::default()
+        };
+
+        assert!((planned_training_stress_score(&activity(vec![ramp])) - 100.0 / 3.0).abs() < 1e-9);
+
+        // Half an hour at 250 W is an IF of 1 with an FTP of 250
+        let watts = activity(vec![Workout {
+            workout_type: WorkoutType::SteadyState,
+            duration: 1800,
+            power_steady: 250.0,
+            power_unit: PowerUnit::Watts,
+            ..Default::default()
+        }]);
+
+        let planned = planned_training_stress_score(&watts.relative_to_ftp(250).unwrap());
+        assert!((planned - 50.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,339 @@
+use chrono::{Datelike, Duration, Months, NaiveDate};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::error::error_generic;
+use crate::prelude::*;
+use crate::system::{directory, user::UserSettings};
+
+use super::{
+    history::list_sessions,
+    metrics::calculate_metrics,
+    zones::{time_in_zones, to_zone_times, ZoneTime, Zones},
+};
+
+const LOGGER_NAME: &str = "data::training_summary";
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SummaryPeriod {
+    // Monday to Sunday
+    Week,
+    Month,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeriodTotals {
+    pub rides: u32,
+    // Moving time in seconds
+    pub duration: u32,
+    // Meters
+    pub distance: u32,
+    // Kilojoules
+    pub work: f64,
+    pub training_stress_score: f64,
+    pub power_zones: Vec<ZoneTime>,
+    // Mean of the rides with an intensity factor
+    pub average_intensity_factor: Option<f64>,
+}
+
+/// Difference of the totals to the previous period, positive when they went up
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeriodChange {
+    pub rides: i64,
+    pub duration: i64,
+    pub distance: i64,
+    pub work: f64,
+    pub training_stress_score: f64,
+    pub average_intensity_factor: Option<f64>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeriodSummary {
+    pub start: NaiveDate,
+    // Last day of the period
+    pub end: NaiveDate,
+    pub totals: PeriodTotals,
+    pub previous: PeriodTotals,
+    pub change: PeriodChange,
+}
+
+/// One summary per week or month overlapping `from` to `to`, oldest first
+pub fn load_training_summaries(
+    settings: &UserSettings,
+    period: SummaryPeriod,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<Vec<PeriodSummary>> {
+    if from > to {
+        return Err(error_generic("Start date is after the end date"));
+    }
+
+    let zones = Zones::from_settings(settings);
+
+    let mut starts = vec![period_start(period, from)];
+
+    while let Some(next) = starts.last().map(|start| next_period(period, *start)) {
+        if next > to {
+            break;
+        }
+
+        starts.push(next);
+    }
+
+    // The first period is compared with the one before it
+    let first = previous_period(period, starts[0]);
+    let last = next_period(period, *starts.last().unwrap_or(&first));
+
+    let rides = load_rides(settings, &zones, first, last)?;
+
+    let mut previous = totals(&rides, &zones, first, starts[0]);
+    let mut summaries = Vec::with_capacity(starts.len());
+
+    for start in starts {
+        let end = next_period(period, start);
+        let current = totals(&rides, &zones, start, end);
+
+        summaries.push(PeriodSummary {
+            start,
+            end: end - Duration::days(1),
+            change: change(&current, &previous),
+            previous,
+            totals: current.clone(),
+        });
+
+        previous = current;
+    }
+
+    Ok(summaries)
+}
+
+// What a ride adds to the totals of its period
+struct Ride {
+    date: NaiveDate,
+    duration: u32,
+    distance: u32,
+    work: Option<f64>,
+    training_stress_score: Option<f64>,
+    intensity_factor: Option<f64>,
+    power_zones: Vec<ZoneTime>,
+}
+
+fn load_rides(
+    settings: &UserSettings,
+    zones: &Zones,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<Vec<Ride>> {
+    let mut rides = Vec::new();
+
+    // Summaries avoid loading the records of sessions outside of the range
+    for summary in list_sessions(settings)? {
+        let Some(date) = summary.date.map(|date| date.date_naive()) else {
+            continue;
+        };
+
+        if date < from || date >= to {
+            continue;
+        }
+
+        let session = match directory::load_session(&summary.id) {
+            Ok(session) => session,
+            Err(err) => {
+                warn!(
+                    "{}::load_rides: Skipping session {}: {}",
+                    LOGGER_NAME, summary.id, err
+                );
+
+                continue;
+            }
+        };
+
+        let metrics = match &session.metrics {
+            Some(metrics) => metrics.clone(),
+            None => calculate_metrics(&session, settings.ftp, settings.weight),
+        };
+
+        rides.push(Ride {
+            date,
+            duration: metrics.duration,
+            distance: metrics.distance,
+            work: metrics.work,
+            training_stress_score: metrics.training_stress_score,
+            intensity_factor: metrics.intensity_factor,
+            power_zones: time_in_zones(&session, zones).power,
+        });
+    }
+
+    Ok(rides)
+}
+
+fn totals(rides: &[Ride], zones: &Zones, start: NaiveDate, end: NaiveDate) -> PeriodTotals {
+    let mut totals = PeriodTotals {
+        rides: 0,
+        duration: 0,
+        distance: 0,
+        work: 0.0,
+        training_stress_score: 0.0,
+        power_zones: to_zone_times(&zones.power),
+        average_intensity_factor: None,
+    };
+
+    let mut intensity_factors = Vec::new();
+
+    for ride in rides
+        .iter()
+        .filter(|ride| ride.date >= start && ride.date < end)
+    {
+        totals.rides += 1;
+        totals.duration += ride.duration;
+        totals.distance += ride.distance;
+        totals.work += ride.work.unwrap_or(0.0);
+        totals.training_stress_score += ride.training_stress_score.unwrap_or(0.0);
+
+        for (total, zone) in totals.power_zones.iter_mut().zip(ride.power_zones.iter()) {
+            total.seconds += zone.seconds;
+        }
+
+        intensity_factors.extend(ride.intensity_factor);
+    }
+
+    if !intensity_factors.is_empty() {
+        totals.average_intensity_factor =
+            Some(intensity_factors.iter().sum::<f64>() / intensity_factors.len() as f64);
+    }
+
+    totals
+}
+
+fn change(current: &PeriodTotals, previous: &PeriodTotals) -> PeriodChange {
+    PeriodChange {
+        rides: current.rides as i64 - previous.rides as i64,
+        duration: current.duration as i64 - previous.duration as i64,
+        distance: current.distance as i64 - previous.distance as i64,
+        work: current.work - previous.work,
+        training_stress_score: current.training_stress_score - previous.training_stress_score,
+        average_intensity_factor: match (
+            current.average_intensity_factor,
+            previous.average_intensity_factor,
+        ) {
+            (Some(current), Some(previous)) => Some(current - previous),
+            _ => None,
+        },
+    }
+}
+
+fn period_start(period: SummaryPeriod, date: NaiveDate) -> NaiveDate {
+    match period {
+        SummaryPeriod::Week => date - Duration::days(date.weekday().num_days_from_monday() as i64),
+        SummaryPeriod::Month => date.with_day(1).unwrap_or(date),
+    }
+}
+
+fn next_period(period: SummaryPeriod, start: NaiveDate) -> NaiveDate {
+    match period {
+        SummaryPeriod::Week => start + Duration::days(7),
+        SummaryPeriod::Month => start + Months::new(1),
+    }
+}
+
+fn previous_period(period: SummaryPeriod, start: NaiveDate) -> NaiveDate {
+    match period {
+        SummaryPeriod::Week => start - Duration::days(7),
+        SummaryPeriod::Month => start - Months::new(1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2026, month, day).unwrap()
+    }
+
+    fn zones() -> Zones {
+        Zones::from_settings(&UserSettings {
+            ftp: 200,
+            ..Default::default()
+        })
+    }
+
+    fn ride(date: NaiveDate, intensity_factor: Option<f64>, zone: usize) -> Ride {
+        let mut power_zones = to_zone_times(&zones().power);
+        power_zones[zone].seconds = 3600;
+
+        Ride {
+            date,
+            duration: 3600,
+            distance: 30000,
+            work: Some(720.0),
+            training_stress_score: Some(80.0),
+            intensity_factor,
+            power_zones,
+        }
+    }
+
+    #[test]
+    fn finds_the_bounds_of_a_period() {
+        let wednesday = date(3, 4);
+
+        assert_eq!(period_start(SummaryPeriod::Week, wednesday), date(3, 2));
+        assert_eq!(period_start(SummaryPeriod::Month, wednesday), date(3, 1));
+        assert_eq!(next_period(SummaryPeriod::Month, date(1, 1)), date(2, 1));
+        assert_eq!(
+            previous_period(SummaryPeriod::Week, date(3, 2)),
+            date(2, 23)
+        );
+    }
+
+    #[test]
+    fn totals_the_rides_of_a_period() {
+        let zones = zones();
+        let rides = vec![
+            ride(date(3, 1), Some(0.7), 1),
+            ride(date(3, 2), Some(0.9), 2),
+            ride(date(3, 8), None, 2),
+            // Next week
+            ride(date(3, 9), Some(1.0), 3),
+        ];
+
+        let totals = totals(&rides, &zones, date(3, 2), date(3, 9));
+
+        assert_eq!(totals.rides, 2);
+        assert_eq!(totals.duration, 7200);
+        assert_eq!(totals.distance, 60000);
+        assert_eq!(totals.work, 1440.0);
+        assert_eq!(totals.training_stress_score, 160.0);
+        assert_eq!(totals.power_zones[2].seconds, 7200);
+        assert_eq!(totals.power_zones[1].seconds, 0);
+        // Rides without an intensity factor are left out of the average
+        assert_eq!(totals.average_intensity_factor, Some(0.9));
+    }
+
+    #[test]
+    fn compares_with_the_previous_period() {
+        let zones = zones();
+        let rides = vec![
+            ride(date(3, 1), Some(0.7), 1),
+            ride(date(3, 2), Some(0.9), 2),
+            ride(date(3, 3), Some(0.8), 2),
+        ];
+
+        let previous = totals(&rides, &zones, date(2, 23), date(3, 2));
+        let current = totals(&rides, &zones, date(3, 2), date(3, 9));
+        let difference = change(&current, &previous);
+
+        assert_eq!(difference.rides, 1);
+        assert_eq!(difference.duration, 3600);
+        assert_eq!(difference.training_stress_score, 80.0);
+        assert!((difference.average_intensity_factor.unwrap() - 0.15).abs() < 1e-9);
+
+        let empty = totals(&rides, &zones, date(4, 1), date(5, 1));
+        assert_eq!(change(&empty, &current).rides, -2);
+        assert_eq!(change(&empty, &current).average_intensity_factor, None);
+    }
+}
@@ -0,0 +1,163 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct WBalanceConfig {
+    // Watts
+    pub critical_power: u16,
+    // Joules
+    pub w_prime: u32,
+    // Fraction of W' below which a warning is sent, e.g. 0.25 for 25%
+    pub warning_threshold: f64,
+}
+
+impl Default for WBalanceConfig {
+    fn default() -> Self {
+        Self {
+            critical_power: 0,
+            w_prime: 0,
+            warning_threshold: 0.25,
+        }
+    }
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WBalanceWarning {
+    pub elapsed: u32,
+    // Joules
+    pub w_balance: u32,
+    pub w_prime: u32,
+}
+
+/// Skiba's W' balance, using the differential form so it can be updated every second.
+///
+/// Above CP the balance drops by the work done above CP. Below CP it recovers
+/// exponentially towards W' with a rate proportional to how far below CP the rider is.
+pub struct WBalance {
+    critical_power: f64,
+    w_prime: f64,
+    warning_threshold: f64,
+    balance: f64,
+    warned: bool,
+}
+
+impl WBalance {
+    /// Returns `None` when CP or W' are not set
+    pub fn new(config: &WBalanceConfig) -> Option<Self> {
+        if config.critical_power == 0 || config.w_prime == 0 {
+            return None;
+        }
+
+        let w_prime = config.w_prime as f64;
+
+        Some(Self {
+            critical_power: config.critical_power as f64,
+            w_prime,
+            warning_threshold: config.warning_threshold * w_prime,
+            balance: w_prime,
+            warned: false,
+        })
+    }
+
+    /// Adds one second of power and returns the balance in joules
+    pub fn update(&mut self, power: Option<u16>) -> u32 {
+        // Dropouts count as no power
+        let power = power.unwrap_or(0) as f64;
+
+        if power > self.critical_power {
+            self.balance -= power - self.critical_power;
+        } else {
+            let below_critical_power = self.critical_power - power;
+
+            self.balance = self.w_prime
+                - (self.w_prime - self.balance) * (-below_critical_power / self.w_prime).exp();
+        }
+
+        self.balance.max(0.0).round() as u32
+    }
+
+    /// True once when the balance drops below the warning threshold.
+    /// Fires again only after the balance has recovered above it.
+    pub fn should_warn(&mut self) -> bool {
+        if self.balance >= self.warning_threshold {
+            self.warned = false;
+            return false;
+        }
+
+        !std::mem::replace(&mut self.warned, true)
+    }
+
+    pub fn w_prime(&self) -> u32 {
+        self.w_prime as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn w_balance() -> WBalance {
+        WBalance::new(&WBalanceConfig {
+            critical_power: 250,
+            w_prime: 20000,
+            warning_threshold: 0.25,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn drains_by_the_work_above_critical_power() {
+        let mut w_balance = w_balance();
+
+        let balances: Vec<u32> = (0..10).map(|_| w_balance.update(Some(350))).collect();
+
+        assert_eq!(balances[0], 19900);
+        assert_eq!(balances[9], 19000);
+    }
+
+    #[test]
+    fn recovers_exponentially_below_critical_power() {
+        let mut w_balance = w_balance();
+
+        for _ in 0..10 {
+            w_balance.update(Some(350));
+        }
+
+        // 20000 - 1000 * e^(-100 / 20000)
+        assert_eq!(w_balance.update(Some(150)), 19005);
+
+        for _ in 0..58 {
+            w_balance.update(Some(150));
+        }
+
+        // 20000 - 1000 * e^(-60 * 100 / 20000)
+        assert_eq!(w_balance.update(Some(150)), 19259);
+    }
+
+    #[test]
+    fn warns_once_below_the_threshold() {
+        let mut w_balance = w_balance();
+        let mut warnings = 0;
+
+        // 150 seconds at 100 W above CP leaves 5000 J, the threshold
+        for _ in 0..160 {
+            w_balance.update(Some(350));
+
+            if w_balance.should_warn() {
+                warnings += 1;
+            }
+        }
+
+        assert_eq!(warnings, 1);
+        assert_eq!(w_balance.update(Some(350)), 3900);
+
+        // Dropouts count as no power, which is below CP
+        assert!(w_balance.update(None) > 3900);
+    }
+
+    #[test]
+    fn needs_critical_power_and_w_prime() {
+        assert!(WBalance::new(&WBalanceConfig::default()).is_none());
+    }
+}
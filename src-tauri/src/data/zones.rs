@@ -0,0 +1,334 @@
+use serde::{Deserialize, Serialize};
+
+use crate::system::user::UserSettings;
+
+use super::session::Session;
+
+// Lower bounds as a fraction of FTP
+const COGGAN_POWER_ZONES: [(&str, f64); 7] = [
+    ("Active Recovery", 0.0),
+    ("Endurance", 0.56),
+    ("Tempo", 0.76),
+    ("Threshold", 0.91),
+    ("VO2 Max", 1.06),
+    ("Anaerobic Capacity", 1.21),
+    ("Neuromuscular Power", 1.51),
+];
+
+// Lower bounds as a fraction of max heart rate
+const MAX_HEART_RATE_ZONES: [(&str, f64); 5] = [
+    ("Recovery", 0.0),
+    ("Endurance", 0.6),
+    ("Tempo", 0.7),
+    ("Threshold", 0.8),
+    ("Maximum", 0.9),
+];
+
+// Lower bounds as a fraction of lactate threshold heart rate (Friel)
+const LACTATE_THRESHOLD_ZONES: [(&str, f64); 7] = [
+    ("Recovery", 0.0),
+    ("Aerobic", 0.85),
+    ("Tempo", 0.9),
+    ("SubThreshold", 0.95),
+    ("SuperThreshold", 1.0),
+    ("Aerobic Capacity", 1.03),
+    ("Anaerobic Capacity", 1.06),
+];
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ZoneDefinition {
+    pub name: String,
+    // Lower bound as a fraction of the threshold
+    pub min: f64,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PowerZoneModel {
+    #[default]
+    Coggan,
+    Custom {
+        zones: Vec<ZoneDefinition>,
+    },
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HeartRateZoneModel {
+    #[default]
+    MaxHeartRate,
+    LactateThreshold,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ZoneSettings {
+    pub power_model: PowerZoneModel,
+    pub heart_rate_model: HeartRateZoneModel,
+    pub max_heart_rate: u16,
+    pub lactate_threshold_heart_rate: u16,
+}
+
+/// Zone with absolute bounds, `max` is exclusive and missing for the last zone
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Zone {
+    pub name: String,
+    pub min: u16,
+    pub max: Option<u16>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Zones {
+    pub power: Vec<Zone>,
+    pub heart_rate: Vec<Zone>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ZoneTime {
+    pub zone: Zone,
+    pub seconds: u32,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TimeInZones {
+    pub power: Vec<ZoneTime>,
+    pub heart_rate: Vec<ZoneTime>,
+    // Index of the zone of the latest record, only set for the live session
+    pub current_power_zone: Option<usize>,
+    pub current_heart_rate_zone: Option<usize>,
+}
+
+impl Zones {
+    /// Resolves the zone models of the user into absolute values.
+    /// A zone list is empty when its threshold is not set.
+    pub fn from_settings(settings: &UserSettings) -> Self {
+        let power = match &settings.zones.power_model {
+            PowerZoneModel::Coggan => to_definitions(&COGGAN_POWER_ZONES),
+            PowerZoneModel::Custom { zones } => zones.clone(),
+        };
+
+        let (heart_rate, heart_rate_threshold) = match settings.zones.heart_rate_model {
+            HeartRateZoneModel::MaxHeartRate => (
+                to_definitions(&MAX_HEART_RATE_ZONES),
+                settings.zones.max_heart_rate,
+            ),
+            HeartRateZoneModel::LactateThreshold => (
+                to_definitions(&LACTATE_THRESHOLD_ZONES),
+                settings.zones.lactate_threshold_heart_rate,
+            ),
+        };
+
+        Self {
+            power: resolve(power, settings.ftp),
+            heart_rate: resolve(heart_rate, heart_rate_threshold),
+        }
+    }
+}
+
+pub fn zone_index(zones: &[Zone], value: u16) -> Option<usize> {
+    zones
+        .iter()
+        .position(|zone| value >= zone.min && zone.max.is_none_or(|max| value < max))
+}
+
+pub fn time_in_zones(session: &Session, zones: &Zones) -> TimeInZones {
+    let mut power = to_zone_times(&zones.power);
+    let mut heart_rate = to_zone_times(&zones.heart_rate);
+
+    for record in session.records.iter() {
+        if let Some(index) = record.power.and_then(|p| zone_index(&zones.power, p)) {
+            power[index].seconds += 1;
+        }
+
+        if let Some(index) = record
+            .heart_rate
+            .and_then(|hr| zone_index(&zones.heart_rate, hr))
+        {
+            heart_rate[index].seconds += 1;
+        }
+    }
+
+    TimeInZones {
+        power,
+        heart_rate,
+        current_power_zone: None,
+        current_heart_rate_zone: None,
+    }
+}
+
+/// Time in zones with the zones of the latest record filled in
+pub fn live_time_in_zones(session: &Session, zones: &Zones) -> TimeInZones {
+    let mut time_in_zones = time_in_zones(session, zones);
+
+    if let Some(record) = session.records.last() {
+        time_in_zones.current_power_zone = record.power.and_then(|p| zone_index(&zones.power, p));
+        time_in_zones.current_heart_rate_zone = record
+            .heart_rate
+            .and_then(|hr| zone_index(&zones.heart_rate, hr));
+    }
+
+    time_in_zones
+}
+
+fn to_definitions(zones: &[(&str, f64)]) -> Vec<ZoneDefinition> {
+    zones
+        .iter()
+        .map(|(name, min)| ZoneDefinition {
+            name: name.to_string(),
+            min: *min,
+        })
+        .collect()
+}
+
+fn resolve(mut definitions: Vec<ZoneDefinition>, threshold: u16) -> Vec<Zone> {
+    if threshold == 0 {
+        return Vec::new();
+    }
+
+    definitions.sort_by(|a, b| a.min.total_cmp(&b.min));
+
+    let bounds: Vec<u16> = definitions
+        .iter()
+        .map(|zone| (zone.min * threshold as f64).round() as u16)
+        .collect();
+
+    definitions
+        .into_iter()
+        .enumerate()
+        .map(|(index, zone)| Zone {
+            name: zone.name,
+            // The lowest zone covers everything below the next one
+            min: if index == 0 { 0 } else { bounds[index] },
+            max: bounds.get(index + 1).copied(),
+        })
+        .collect()
+}
+
+pub fn to_zone_times(zones: &[Zone]) -> Vec<ZoneTime> {
+    zones
+        .iter()
+        .map(|zone| ZoneTime {
+            zone: zone.clone(),
+            seconds: 0,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::pipeline::Record;
+
+    fn settings(ftp: u16) -> UserSettings {
+        UserSettings {
+            ftp,
+            zones: ZoneSettings {
+                max_heart_rate: 200,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn resolves_coggan_zones_from_ftp() {
+        let zones = Zones::from_settings(&settings(200));
+
+        let bounds: Vec<(u16, Option<u16>)> = zones
+            .power
+            .iter()
+            .map(|zone| (zone.min, zone.max))
+            .collect();
+
+        assert_eq!(
+            bounds,
+            vec![
+                (0, Some(112)),
+                (112, Some(152)),
+                (152, Some(182)),
+                (182, Some(212)),
+                (212, Some(242)),
+                (242, Some(302)),
+                (302, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn lower_bound_belongs_to_the_zone() {
+        let zones = Zones::from_settings(&settings(200));
+
+        assert_eq!(zone_index(&zones.power, 0), Some(0));
+        assert_eq!(zone_index(&zones.power, 111), Some(0));
+        assert_eq!(zone_index(&zones.power, 112), Some(1));
+        assert_eq!(zone_index(&zones.power, 211), Some(3));
+        assert_eq!(zone_index(&zones.power, 212), Some(4));
+        assert_eq!(zone_index(&zones.power, 302), Some(6));
+        assert_eq!(zone_index(&zones.power, u16::MAX), Some(6));
+
+        assert_eq!(zone_index(&zones.heart_rate, 119), Some(0));
+        assert_eq!(zone_index(&zones.heart_rate, 120), Some(1));
+        assert_eq!(zone_index(&zones.heart_rate, 180), Some(4));
+    }
+
+    #[test]
+    fn sorts_custom_zones() {
+        let mut settings = settings(300);
+        settings.zones.power_model = PowerZoneModel::Custom {
+            zones: vec![
+                ZoneDefinition {
+                    name: "Hard".to_string(),
+                    min: 0.9,
+                },
+                ZoneDefinition {
+                    name: "Easy".to_string(),
+                    min: 0.0,
+                },
+            ],
+        };
+
+        let zones = Zones::from_settings(&settings);
+
+        assert_eq!(zones.power[0].name, "Easy");
+        assert_eq!((zones.power[0].min, zones.power[0].max), (0, Some(270)));
+        assert_eq!((zones.power[1].min, zones.power[1].max), (270, None));
+    }
+
+    #[test]
+    fn has_no_zones_without_threshold() {
+        let zones = Zones::from_settings(&settings(0));
+
+        assert!(zones.power.is_empty());
+        assert_eq!(zone_index(&zones.power, 200), None);
+    }
+
+    #[test]
+    fn counts_seconds_per_zone() {
+        let zones = Zones::from_settings(&settings(200));
+        let mut session = Session::new();
+
+        session.records = [100, 111, 112, 400]
+            .iter()
+            .map(|power| Record {
+                power: Some(*power),
+                ..Default::default()
+            })
+            .collect();
+
+        let time_in_zones = live_time_in_zones(&session, &zones);
+        let seconds: Vec<u32> = time_in_zones
+            .power
+            .iter()
+            .map(|zone| zone.seconds)
+            .collect();
+
+        assert_eq!(seconds, vec![2, 1, 0, 0, 0, 0, 1]);
+        assert_eq!(time_in_zones.current_power_zone, Some(6));
+        assert_eq!(time_in_zones.current_heart_rate_zone, None);
+    }
+}
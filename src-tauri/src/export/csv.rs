@@ -0,0 +1,133 @@
+use chrono::Duration;
+
+use crate::data::session::Session;
+use crate::prelude::*;
+
+use super::{lap_ranges, record_distances, Exporter};
+
+const HEADER: &str = "elapsed,time,lap,heart_rate,power,cadence,speed,distance,w_balance";
+
+pub struct CsvExporter;
+
+impl Exporter for CsvExporter {
+    fn extension(&self) -> &'static str {
+        "csv"
+    }
+
+    fn export(&self, session: &Session) -> Result<Vec<u8>> {
+        Ok(encode_session(session).into_bytes())
+    }
+}
+
+/// One row per second of the session, missing values are left empty.
+///
+/// `time` is only filled for sessions with a start time.
+pub fn encode_session(session: &Session) -> String {
+    let distances = record_distances(session);
+    let laps = lap_ranges(session);
+
+    let mut csv = String::from(HEADER);
+    csv.push('\n');
+
+    for (index, (record, distance)) in session.records.iter().zip(distances.iter()).enumerate() {
+        let time = session
+            .start_time
+            .map(|start_time| {
+                let since_start = session.time_since_start(record.elapsed);
+
+                (start_time + Duration::seconds(since_start as i64)).to_rfc3339()
+            })
+            .unwrap_or_default();
+
+        let lap = laps
+            .iter()
+            .position(|(start, end)| index >= *start && index < *end)
+            .unwrap_or(0)
+            + 1;
+
+        let row = [
+            record.elapsed.to_string(),
+            time,
+            lap.to_string(),
+            optional(record.heart_rate),
+            optional(record.power),
+            optional(record.cadence),
+            optional(record.speed),
+            format!("{:.2}", distance),
+            optional(record.w_balance),
+        ];
+
+        csv.push_str(&row.join(","));
+        csv.push('\n');
+    }
+
+    csv
+}
+
+fn optional<T: ToString>(value: Option<T>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::pipeline::Record;
+    use crate::data::session::Pause;
+    use chrono::{Local, TimeZone};
+
+    fn ride() -> Session {
+        let mut session = Session::new();
+
+        session.start_time = Some(Local.with_ymd_and_hms(2024, 3, 1, 7, 30, 0).unwrap());
+        session.pauses = vec![Pause {
+            elapsed: 2,
+            duration: 10,
+        }];
+        session.laps = vec![2];
+        session.total_distance = 30;
+        session.records = (0..3)
+            .map(|elapsed| Record {
+                elapsed,
+                power: Some(200),
+                speed: Some(36),
+                ..Default::default()
+            })
+            .collect();
+
+        session
+    }
+
+    #[test]
+    fn writes_a_row_per_second() {
+        let session = ride();
+        let start_time = session.start_time.unwrap();
+
+        let csv = encode_session(&session);
+        let rows: Vec<&str> = csv.lines().collect();
+
+        assert_eq!(rows.len(), 4);
+        assert_eq!(rows[0], HEADER);
+        assert_eq!(
+            rows[1],
+            format!("0,{},1,,200,,36,10.00,", start_time.to_rfc3339())
+        );
+        // After the pause and in the second lap
+        assert_eq!(
+            rows[3],
+            format!(
+                "2,{},2,,200,,36,30.00,",
+                (start_time + Duration::seconds(12)).to_rfc3339()
+            )
+        );
+    }
+
+    #[test]
+    fn leaves_the_time_empty_without_start_time() {
+        let mut session = ride();
+        session.start_time = None;
+
+        let csv = encode_session(&session);
+
+        assert!(csv.lines().nth(1).unwrap().starts_with("0,,1,"));
+    }
+}
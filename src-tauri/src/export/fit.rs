@@ -0,0 +1,573 @@
+use chrono::Duration;
+
+use crate::data::{
+    metrics::calculate_metrics,
+    pipeline::{Record, SensorSource},
+    session::Session,
+};
+use crate::error::error_generic;
+use crate::prelude::*;
+use crate::utils::fit::{
+    to_fit_timestamp, FitWriter, Message, Value, MESSAGE_INDEX_FIELD, TIMESTAMP_FIELD,
+};
+
+use super::{lap_ranges, record_distances, summarize, Exporter, KPH_TO_MPS};
+
+// Global message numbers
+pub const FILE_ID: u16 = 0;
+pub const SESSION: u16 = 18;
+pub const LAP: u16 = 19;
+pub const RECORD: u16 = 20;
+pub const EVENT: u16 = 21;
+pub const DEVICE_INFO: u16 = 23;
+pub const ACTIVITY: u16 = 34;
+
+// Profile values
+const FILE_TYPE_ACTIVITY: u8 = 4;
+const MANUFACTURER_DEVELOPMENT: u16 = 255;
+const SPORT_CYCLING: u8 = 2;
+const SUB_SPORT_INDOOR_CYCLING: u8 = 6;
+const EVENT_TIMER: u8 = 0;
+const EVENT_SESSION: u8 = 8;
+const EVENT_LAP: u8 = 9;
+const EVENT_ACTIVITY: u8 = 26;
+const EVENT_TYPE_START: u8 = 0;
+const EVENT_TYPE_STOP: u8 = 1;
+const EVENT_TYPE_STOP_ALL: u8 = 4;
+const ACTIVITY_TYPE_MANUAL: u8 = 0;
+const DEVICE_TYPE_FITNESS_EQUIPMENT: u8 = 17;
+const DEVICE_TYPE_HEART_RATE: u8 = 120;
+const SOURCE_TYPE_BLUETOOTH_LOW_ENERGY: u8 = 3;
+const SOURCE_TYPE_LOCAL: u8 = 5;
+
+const PRODUCT_NAME_SIZE: u8 = 20;
+
+pub struct FitExporter;
+
+impl Exporter for FitExporter {
+    fn extension(&self) -> &'static str {
+        "fit"
+    }
+
+    fn export(&self, session: &Session) -> Result<Vec<u8>> {
+        encode_session(session)
+    }
+}
+
+/// Encodes a session as a FIT activity file
+pub fn encode_session(session: &Session) -> Result<Vec<u8>> {
+    let Some(start_time) = session.start_time else {
+        return Err(error_generic("Session has no start time"));
+    };
+
+    let records = &session.records;
+    let distances = record_distances(session);
+
+    let start = to_fit_timestamp(start_time);
+    let moving_time = records.len() as u32;
+    let elapsed_time = match session.end_time {
+        Some(end_time) => (end_time - start_time).num_seconds().max(0) as u32,
+        None => moving_time + session.paused_time,
+    }
+    .max(session.time_since_start(moving_time));
+
+    let end = start + elapsed_time;
+
+    let mut writer = FitWriter::new();
+
+    writer.write(&Message::new(
+        FILE_ID,
+        vec![
+            (0, Value::Enum(Some(FILE_TYPE_ACTIVITY))),
+            (1, Value::UInt16(Some(MANUFACTURER_DEVELOPMENT))),
+            (2, Value::UInt16(Some(0))),
+            (4, Value::UInt32(Some(start))),
+        ],
+    ));
+
+    for message in device_infos(records, start) {
+        writer.write(&message);
+    }
+
+    writer.write(&timer_event(start, EVENT_TYPE_START));
+
+    for (record, distance) in records.iter().zip(distances.iter()) {
+        let timestamp = start + session.time_since_start(record.elapsed);
+
+        // The timer is stopped for the pause taken before this record
+        if let Some(pause) = session
+            .pauses
+            .iter()
+            .find(|pause| pause.elapsed == record.elapsed)
+        {
+            writer.write(&timer_event(timestamp - pause.duration, EVENT_TYPE_STOP));
+            writer.write(&timer_event(timestamp, EVENT_TYPE_START));
+        }
+
+        writer.write(&Message::new(
+            RECORD,
+            vec![
+                (TIMESTAMP_FIELD, Value::UInt32(Some(timestamp))),
+                (3, Value::UInt8(record.heart_rate.map(to_u8))),
+                (4, Value::UInt8(record.cadence.map(to_u8))),
+                (5, Value::UInt32(Some((distance * 100.0).round() as u32))),
+                (
+                    6,
+                    Value::UInt16(record.speed.map(|s| to_fit_speed(s as f64))),
+                ),
+                (7, Value::UInt16(record.power)),
+            ],
+        ));
+    }
+
+    writer.write(&timer_event(end, EVENT_TYPE_STOP_ALL));
+
+    let laps = lap_ranges(session);
+
+    for (index, (lap_start, lap_end)) in laps.iter().enumerate() {
+        let summary = summarize(&records[*lap_start..*lap_end], &distances, *lap_start);
+        let lap_start_time = start + session.time_since_start(summary.start);
+
+        // Laps are back to back, so each one covers the pauses taken during it
+        let lap_end_time = match laps.get(index + 1) {
+            Some((next_start, _)) => start + session.time_since_start(*next_start as u32),
+            None => end,
+        };
+        let lap_elapsed_time = lap_end_time.saturating_sub(lap_start_time);
+
+        writer.write(&Message::new(
+            LAP,
+            vec![
+                (TIMESTAMP_FIELD, Value::UInt32(Some(lap_end_time))),
+                (MESSAGE_INDEX_FIELD, Value::UInt16(Some(index as u16))),
+                (0, Value::Enum(Some(EVENT_LAP))),
+                (1, Value::Enum(Some(EVENT_TYPE_STOP))),
+                (2, Value::UInt32(Some(lap_start_time))),
+                (7, Value::UInt32(Some(lap_elapsed_time * 1000))),
+                (8, Value::UInt32(Some(summary.duration * 1000))),
+                (
+                    9,
+                    Value::UInt32(Some((summary.distance * 100.0).round() as u32)),
+                ),
+                (13, Value::UInt16(summary.average_speed.map(to_fit_speed))),
+                (14, Value::UInt16(summary.max_speed.map(to_fit_speed))),
+                (15, Value::UInt8(summary.average_heart_rate.map(to_u8))),
+                (16, Value::UInt8(summary.max_heart_rate.map(to_u8))),
+                (17, Value::UInt8(summary.average_cadence.map(to_u8))),
+                (18, Value::UInt8(summary.max_cadence.map(to_u8))),
+                (19, Value::UInt16(summary.average_power)),
+                (20, Value::UInt16(summary.max_power)),
+                (25, Value::Enum(Some(SPORT_CYCLING))),
+                (39, Value::Enum(Some(SUB_SPORT_INDOOR_CYCLING))),
+            ],
+        ));
+    }
+
+    let summary = summarize(records, &distances, 0);
+    // FTP based metrics are only known for sessions saved with them
+    let metrics = match &session.metrics {
+        Some(metrics) => metrics.clone(),
+        None => calculate_metrics(session, 0, 0.0),
+    };
+
+    writer.write(&Message::new(
+        SESSION,
+        vec![
+            (TIMESTAMP_FIELD, Value::UInt32(Some(end))),
+            (MESSAGE_INDEX_FIELD, Value::UInt16(Some(0))),
+            (0, Value::Enum(Some(EVENT_SESSION))),
+            (1, Value::Enum(Some(EVENT_TYPE_STOP))),
+            (2, Value::UInt32(Some(start))),
+            (5, Value::Enum(Some(SPORT_CYCLING))),
+            (6, Value::Enum(Some(SUB_SPORT_INDOOR_CYCLING))),
+            (7, Value::UInt32(Some(elapsed_time * 1000))),
+            (8, Value::UInt32(Some(moving_time * 1000))),
+            (9, Value::UInt32(Some(session.total_distance * 100))),
+            (14, Value::UInt16(summary.average_speed.map(to_fit_speed))),
+            (15, Value::UInt16(summary.max_speed.map(to_fit_speed))),
+            (16, Value::UInt8(summary.average_heart_rate.map(to_u8))),
+            (17, Value::UInt8(summary.max_heart_rate.map(to_u8))),
+            (18, Value::UInt8(summary.average_cadence.map(to_u8))),
+            (19, Value::UInt8(summary.max_cadence.map(to_u8))),
+            (20, Value::UInt16(summary.average_power)),
+            (21, Value::UInt16(summary.max_power)),
+            (25, Value::UInt16(Some(0))),
+            (26, Value::UInt16(Some(laps.len() as u16))),
+            (34, Value::UInt16(metrics.normalized_power)),
+            (
+                35,
+                Value::UInt16(
+                    metrics
+                        .training_stress_score
+                        .map(|tss| (tss * 10.0).round() as u16),
+                ),
+            ),
+            (
+                36,
+                Value::UInt16(
+                    metrics
+                        .intensity_factor
+                        .map(|intensity_factor| (intensity_factor * 1000.0).round() as u16),
+                ),
+            ),
+        ],
+    ));
+
+    // The offset in effect when the ride started, not when it is exported
+    let local_offset = start_time.offset().local_minus_utc() as i64;
+
+    writer.write(&Message::new(
+        ACTIVITY,
+        vec![
+            (TIMESTAMP_FIELD, Value::UInt32(Some(end))),
+            (0, Value::UInt32(Some(moving_time * 1000))),
+            (1, Value::UInt16(Some(1))),
+            (2, Value::Enum(Some(ACTIVITY_TYPE_MANUAL))),
+            (3, Value::Enum(Some(EVENT_ACTIVITY))),
+            (4, Value::Enum(Some(EVENT_TYPE_STOP))),
+            (
+                5,
+                Value::UInt32(Some(to_fit_timestamp(
+                    start_time + Duration::seconds(elapsed_time as i64 + local_offset),
+                ))),
+            ),
+        ],
+    ));
+
+    Ok(writer.finish())
+}
+
+/// One device for this app and one for each sensor that recorded data
+fn device_infos(records: &[Record], timestamp: u32) -> Vec<Message> {
+    let mut sources: Vec<SensorSource> = Vec::new();
+
+    for record in records {
+        let attributions = [
+            record.sources.heart_rate,
+            record.sources.power,
+            record.sources.cadence,
+            record.sources.speed,
+        ];
+
+        for attribution in attributions.iter().flatten() {
+            // Calculated by the app, not a sensor
+            if attribution.source == SensorSource::VirtualSpeed {
+                continue;
+            }
+
+            if !sources.contains(&attribution.source) {
+                sources.push(attribution.source);
+            }
+        }
+    }
+
+    let creator = device_info(timestamp, 0, None, SOURCE_TYPE_LOCAL, "Cycling Trainer");
+
+    let sensors = sources.iter().enumerate().map(|(index, source)| {
+        let device_index = index as u8 + 1;
+
+        match source {
+            SensorSource::HeartRateMonitor => device_info(
+                timestamp,
+                device_index,
+                Some(DEVICE_TYPE_HEART_RATE),
+                SOURCE_TYPE_BLUETOOTH_LOW_ENERGY,
+                "Heart Rate Monitor",
+            ),
+            SensorSource::SmartTrainer => device_info(
+                timestamp,
+                device_index,
+                Some(DEVICE_TYPE_FITNESS_EQUIPMENT),
+                SOURCE_TYPE_BLUETOOTH_LOW_ENERGY,
+                "Smart Trainer",
+            ),
+            SensorSource::Simulation | SensorSource::VirtualSpeed => device_info(
+                timestamp,
+                device_index,
+                Some(DEVICE_TYPE_FITNESS_EQUIPMENT),
+                SOURCE_TYPE_LOCAL,
+                "Simulation",
+            ),
+            SensorSource::Imported => device_info(
+                timestamp,
+                device_index,
+                None,
+                SOURCE_TYPE_LOCAL,
+                "Imported",
+            ),
+        }
+    });
+
+    std::iter::once(creator).chain(sensors).collect()
+}
+
+fn device_info(
+    timestamp: u32,
+    device_index: u8,
+    device_type: Option<u8>,
+    source_type: u8,
+    product_name: &str,
+) -> Message {
+    Message::new(
+        DEVICE_INFO,
+        vec![
+            (TIMESTAMP_FIELD, Value::UInt32(Some(timestamp))),
+            (0, Value::UInt8(Some(device_index))),
+            (1, Value::UInt8(device_type)),
+            (2, Value::UInt16(Some(MANUFACTURER_DEVELOPMENT))),
+            (25, Value::Enum(Some(source_type))),
+            (
+                27,
+                Value::String(product_name.to_string(), PRODUCT_NAME_SIZE),
+            ),
+        ],
+    )
+}
+
+fn timer_event(timestamp: u32, event_type: u8) -> Message {
+    Message::new(
+        EVENT,
+        vec![
+            (TIMESTAMP_FIELD, Value::UInt32(Some(timestamp))),
+            (0, Value::Enum(Some(EVENT_TIMER))),
+            (1, Value::Enum(Some(event_type))),
+        ],
+    )
+}
+
+// km/h to the FIT speed unit, mm/s
+fn to_fit_speed(speed: f64) -> u16 {
+    (speed * KPH_TO_MPS * 1000.0).round() as u16
+}
+
+fn to_u8(value: u16) -> u8 {
+    value.min(u8::MAX as u16 - 1) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::pipeline::{Attribution, Sources};
+    use crate::data::session::{Pause, SessionStatus};
+    use crate::import::{self, to_session};
+    use crate::utils::fit::{crc, decode};
+    use chrono::{DateTime, Local, TimeZone};
+
+    fn ride(seconds: u32) -> Session {
+        let mut session = Session::new();
+        let start_time = Local.with_ymd_and_hms(2024, 3, 1, 7, 30, 0).unwrap();
+
+        let trainer = Some(Attribution {
+            source: SensorSource::SmartTrainer,
+            fill: None,
+        });
+        let heart_rate_monitor = Some(Attribution {
+            source: SensorSource::HeartRateMonitor,
+            fill: None,
+        });
+
+        session.status = SessionStatus::Finished;
+        session.start_time = Some(start_time);
+        session.end_time = Some(start_time + Duration::seconds(seconds as i64 + 30));
+        session.paused_time = 30;
+        session.laps = vec![seconds / 2];
+        session.total_distance = seconds * 10;
+        session.records = (0..seconds)
+            .map(|elapsed| Record {
+                elapsed,
+                heart_rate: Some(140),
+                power: Some(200 + (elapsed % 2) as u16 * 100),
+                cadence: Some(90),
+                speed: Some(36),
+                sources: Sources {
+                    heart_rate: heart_rate_monitor,
+                    power: trainer,
+                    cadence: trainer,
+                    speed: trainer,
+                },
+                ..Default::default()
+            })
+            .collect();
+
+        session
+    }
+
+    fn messages_of(messages: &[Message], global: u16) -> Vec<&Message> {
+        messages.iter().filter(|m| m.global == global).collect()
+    }
+
+    #[test]
+    fn encodes_a_valid_file() {
+        let bytes = encode_session(&ride(60)).unwrap();
+
+        assert_eq!(&bytes[8..12], b".FIT");
+        assert_eq!(crc(&bytes), 0, "file CRC should check out");
+
+        let messages = decode(&bytes).unwrap();
+
+        assert_eq!(messages[0].global, FILE_ID);
+        assert_eq!(messages[0].integer(0), Some(FILE_TYPE_ACTIVITY as i64));
+        assert_eq!(messages.last().unwrap().global, ACTIVITY);
+    }
+
+    #[test]
+    fn records_match_the_session() {
+        let session = ride(60);
+        let messages = decode(&encode_session(&session).unwrap()).unwrap();
+        let records = messages_of(&messages, RECORD);
+
+        assert_eq!(records.len(), 60);
+
+        let start = session.start_time.unwrap();
+
+        assert_eq!(records[0].timestamp(), Some(start));
+        assert_eq!(records[59].timestamp(), Some(start + Duration::seconds(59)));
+        assert_eq!(records[1].integer(7), Some(300));
+        assert_eq!(records[1].integer(3), Some(140));
+        assert_eq!(records[1].integer(4), Some(90));
+        // 36 km/h in mm/s
+        assert_eq!(records[1].integer(6), Some(10000));
+        // Scaled to the 600 m of the session, in cm
+        assert_eq!(records[59].integer(5), Some(60000));
+    }
+
+    #[test]
+    fn summarizes_laps_and_session() {
+        let messages = decode(&encode_session(&ride(60)).unwrap()).unwrap();
+
+        let laps = messages_of(&messages, LAP);
+        assert_eq!(laps.len(), 2);
+        assert_eq!(laps[0].integer(8), Some(30_000));
+        assert_eq!(laps[0].integer(19), Some(250));
+        // The pause is added to the elapsed time of the last lap
+        assert_eq!(laps[1].integer(7), Some(60_000));
+
+        let sessions = messages_of(&messages, SESSION);
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].integer(7), Some(90_000));
+        assert_eq!(sessions[0].integer(8), Some(60_000));
+        assert_eq!(sessions[0].integer(9), Some(60_000));
+        assert_eq!(sessions[0].integer(20), Some(250));
+        assert_eq!(sessions[0].integer(21), Some(300));
+        assert_eq!(sessions[0].integer(16), Some(140));
+        assert_eq!(sessions[0].integer(26), Some(2));
+    }
+
+    #[test]
+    fn lists_a_device_for_each_sensor() {
+        let messages = decode(&encode_session(&ride(10)).unwrap()).unwrap();
+        let devices = messages_of(&messages, DEVICE_INFO);
+
+        assert_eq!(devices.len(), 3);
+        assert_eq!(devices[0].string(27), Some("Cycling Trainer"));
+        assert_eq!(devices[1].integer(1), Some(DEVICE_TYPE_HEART_RATE as i64));
+        assert_eq!(
+            devices[2].integer(1),
+            Some(DEVICE_TYPE_FITNESS_EQUIPMENT as i64)
+        );
+    }
+
+    #[test]
+    fn missing_values_are_invalid() {
+        let mut session = ride(10);
+        session.records[3].heart_rate = None;
+
+        let messages = decode(&encode_session(&session).unwrap()).unwrap();
+        let records = messages_of(&messages, RECORD);
+
+        assert_eq!(records[3].integer(3), None);
+        assert_eq!(records[4].integer(3), Some(140));
+    }
+
+    #[test]
+    fn keeps_pauses_between_records() {
+        let mut session = ride(60);
+        session.pauses = vec![Pause {
+            elapsed: 20,
+            duration: 30,
+        }];
+
+        let messages = decode(&encode_session(&session).unwrap()).unwrap();
+        let start = session.start_time.unwrap();
+
+        let records = messages_of(&messages, RECORD);
+        assert_eq!(records[19].timestamp(), Some(start + Duration::seconds(19)));
+        assert_eq!(records[20].timestamp(), Some(start + Duration::seconds(50)));
+        assert_eq!(records[59].timestamp(), Some(start + Duration::seconds(89)));
+
+        let timer_events: Vec<(Option<DateTime<Local>>, Option<i64>)> =
+            messages_of(&messages, EVENT)
+                .iter()
+                .map(|event| (event.timestamp(), event.integer(1)))
+                .collect();
+        assert_eq!(
+            timer_events[1..3],
+            [
+                (
+                    Some(start + Duration::seconds(20)),
+                    Some(EVENT_TYPE_STOP as i64)
+                ),
+                (
+                    Some(start + Duration::seconds(50)),
+                    Some(EVENT_TYPE_START as i64)
+                ),
+            ]
+        );
+
+        // The first lap covers the pause, the second one starts after it
+        let laps = messages_of(&messages, LAP);
+        assert_eq!(laps[0].integer(7), Some(60_000));
+        assert_eq!(laps[0].integer(8), Some(30_000));
+        assert_eq!(
+            laps[1].integer(2),
+            Some(to_fit_timestamp(start + Duration::seconds(60)) as i64)
+        );
+        assert_eq!(laps[1].integer(7), Some(30_000));
+    }
+
+    #[test]
+    fn local_timestamp_uses_the_offset_of_the_start() {
+        let session = ride(60);
+        let start_time = session.start_time.unwrap();
+
+        let messages = decode(&encode_session(&session).unwrap()).unwrap();
+        let activity = messages_of(&messages, ACTIVITY)[0];
+
+        let offset = start_time.offset().local_minus_utc() as i64;
+        let expected = start_time + Duration::seconds(90 + offset);
+
+        assert_eq!(activity.integer(5), Some(to_fit_timestamp(expected) as i64));
+    }
+
+    #[test]
+    fn round_trips_through_the_importer() {
+        let mut session = ride(60);
+        session.pauses = vec![Pause {
+            elapsed: 20,
+            duration: 30,
+        }];
+
+        let bytes = encode_session(&session).unwrap();
+        let imported = to_session(import::fit::decode(&bytes).unwrap()).unwrap();
+
+        assert_eq!(imported.start_time, session.start_time);
+        assert_eq!(imported.records.len(), 60);
+        assert_eq!(imported.pauses, session.pauses);
+        assert_eq!(imported.paused_time, 30);
+        assert_eq!(imported.laps, vec![30]);
+        assert_eq!(imported.total_distance, 600);
+
+        for (imported, record) in imported.records.iter().zip(session.records.iter()) {
+            assert_eq!(imported.elapsed, record.elapsed);
+            assert_eq!(imported.power, record.power);
+            assert_eq!(imported.heart_rate, record.heart_rate);
+            assert_eq!(imported.cadence, record.cadence);
+            assert_eq!(imported.speed, record.speed);
+        }
+    }
+
+    #[test]
+    fn rejects_sessions_without_start_time() {
+        assert!(encode_session(&Session::new()).is_err());
+    }
+}
@@ -0,0 +1,154 @@
+use serde::Deserialize;
+use std::path::PathBuf;
+
+use crate::data::{pipeline::Record, session::Session};
+use crate::prelude::*;
+use crate::system::directory;
+
+pub mod csv;
+pub mod fit;
+pub mod tcx;
+
+const KPH_TO_MPS: f64 = 1.0 / 3.6;
+
+/// Converts a session into a file format, implement it to add a new format
+pub trait Exporter {
+    fn extension(&self) -> &'static str;
+    fn export(&self, session: &Session) -> Result<Vec<u8>>;
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    Fit,
+    Tcx,
+    Csv,
+}
+
+impl ExportFormat {
+    pub fn exporter(&self) -> Box<dyn Exporter + Send + Sync> {
+        match self {
+            ExportFormat::Fit => Box::new(fit::FitExporter),
+            ExportFormat::Tcx => Box::new(tcx::TcxExporter),
+            ExportFormat::Csv => Box::new(csv::CsvExporter),
+        }
+    }
+}
+
+/// Exports a saved session into the exports directory
+pub fn export_session(session_id: &str, format: ExportFormat) -> Result<PathBuf> {
+    let session = directory::load_session(session_id)?;
+    let exporter = format.exporter();
+
+    let data = exporter.export(&session)?;
+
+    directory::save_export(&data, format!("{}.{}", session_id, exporter.extension()))
+}
+
+/// Totals of a range of records
+struct Summary {
+    start: u32,
+    duration: u32,
+    // Meters
+    distance: f64,
+    // km/h
+    average_speed: Option<f64>,
+    max_speed: Option<f64>,
+    average_heart_rate: Option<u16>,
+    max_heart_rate: Option<u16>,
+    average_cadence: Option<u16>,
+    max_cadence: Option<u16>,
+    average_power: Option<u16>,
+    max_power: Option<u16>,
+}
+
+/// Distance in meters at every record.
+///
+/// Records only hold the rounded speed, so the integrated distance is scaled
+/// to match the distance recorded with the session.
+fn record_distances(session: &Session) -> Vec<f64> {
+    let mut total = 0.0;
+
+    let mut distances: Vec<f64> = session
+        .records
+        .iter()
+        .map(|record| {
+            total += record.speed.unwrap_or(0) as f64 * KPH_TO_MPS;
+            total
+        })
+        .collect();
+
+    if total > 0.0 && session.total_distance > 0 {
+        let scale = session.total_distance as f64 / total;
+
+        for distance in distances.iter_mut() {
+            *distance *= scale;
+        }
+    }
+
+    distances
+}
+
+/// Start and end record indexes of each lap
+fn lap_ranges(session: &Session) -> Vec<(usize, usize)> {
+    let length = session.records.len();
+    let mut boundaries: Vec<usize> = vec![0];
+
+    boundaries.extend(
+        session
+            .laps
+            .iter()
+            .map(|lap| *lap as usize)
+            .filter(|lap| *lap > 0 && *lap < length),
+    );
+    boundaries.push(length);
+
+    boundaries
+        .windows(2)
+        .map(|window| (window[0], window[1]))
+        .collect()
+}
+
+fn summarize(records: &[Record], distances: &[f64], offset: usize) -> Summary {
+    let speed: Vec<u16> = records.iter().filter_map(|r| r.speed).collect();
+    let heart_rate: Vec<u16> = records.iter().filter_map(|r| r.heart_rate).collect();
+    let power: Vec<u16> = records.iter().filter_map(|r| r.power).collect();
+    let cadence: Vec<u16> = records
+        .iter()
+        .filter_map(|r| r.cadence)
+        .filter(|c| *c > 0)
+        .collect();
+
+    let end_distance = match records.len() {
+        0 => 0.0,
+        length => distances[offset + length - 1],
+    };
+    let start_distance = match offset {
+        0 => 0.0,
+        offset => distances[offset - 1],
+    };
+
+    Summary {
+        start: offset as u32,
+        duration: records.len() as u32,
+        distance: end_distance - start_distance,
+        average_speed: average(&speed),
+        max_speed: speed.iter().max().map(|s| *s as f64),
+        average_heart_rate: average(&heart_rate).map(|hr| hr.round() as u16),
+        max_heart_rate: heart_rate.iter().max().copied(),
+        average_cadence: average(&cadence).map(|c| c.round() as u16),
+        max_cadence: cadence.iter().max().copied(),
+        average_power: average(&power).map(|p| p.round() as u16),
+        max_power: power.iter().max().copied(),
+    }
+}
+
+fn average(values: &[u16]) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+
+    let sum: f64 = values.iter().map(|v| *v as f64).sum();
+
+    Some(sum / values.len() as f64)
+}
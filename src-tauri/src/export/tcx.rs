@@ -0,0 +1,254 @@
+use chrono::{DateTime, Duration, Local, Utc};
+use std::fmt::Display;
+
+use crate::data::session::Session;
+use crate::error::error_generic;
+use crate::prelude::*;
+
+use super::{lap_ranges, record_distances, summarize, Exporter, KPH_TO_MPS};
+
+const TCX_NAMESPACE: &str = "http://www.garmin.com/xmlschemas/TrainingCenterDatabase/v2";
+const ACTIVITY_EXTENSION_NAMESPACE: &str = "http://www.garmin.com/xmlschemas/ActivityExtension/v2";
+
+// Cadence values above this are invalid in TCX
+const MAX_CADENCE: u16 = 254;
+
+pub struct TcxExporter;
+
+impl Exporter for TcxExporter {
+    fn extension(&self) -> &'static str {
+        "tcx"
+    }
+
+    fn export(&self, session: &Session) -> Result<Vec<u8>> {
+        encode_session(session).map(String::into_bytes)
+    }
+}
+
+/// Encodes a session as a Garmin Training Center activity,
+/// with power and speed in the ActivityExtension namespace
+pub fn encode_session(session: &Session) -> Result<String> {
+    let Some(start_time) = session.start_time else {
+        return Err(error_generic("Session has no start time"));
+    };
+
+    let records = &session.records;
+    let distances = record_distances(session);
+
+    let mut tcx = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+
+    tcx.push_str(&format!(
+        "<TrainingCenterDatabase xmlns=\"{}\" xmlns:ns3=\"{}\">\n",
+        TCX_NAMESPACE, ACTIVITY_EXTENSION_NAMESPACE
+    ));
+    tcx.push_str("<Activities>\n<Activity Sport=\"Biking\">\n");
+    element(&mut tcx, "Id", format_time(start_time));
+
+    for (lap_start, lap_end) in lap_ranges(session) {
+        let summary = summarize(&records[lap_start..lap_end], &distances, lap_start);
+        let lap_start_time =
+            start_time + Duration::seconds(session.time_since_start(summary.start) as i64);
+
+        tcx.push_str(&format!(
+            "<Lap StartTime=\"{}\">\n",
+            format_time(lap_start_time)
+        ));
+
+        // The order of the elements is defined by the schema
+        element(&mut tcx, "TotalTimeSeconds", summary.duration);
+        element(
+            &mut tcx,
+            "DistanceMeters",
+            format!("{:.2}", summary.distance),
+        );
+
+        if let Some(speed) = summary.max_speed {
+            element(&mut tcx, "MaximumSpeed", format_speed(speed));
+        }
+
+        element(&mut tcx, "Calories", 0);
+
+        if let Some(heart_rate) = summary.average_heart_rate {
+            heart_rate_element(&mut tcx, "AverageHeartRateBpm", heart_rate);
+        }
+
+        if let Some(heart_rate) = summary.max_heart_rate {
+            heart_rate_element(&mut tcx, "MaximumHeartRateBpm", heart_rate);
+        }
+
+        element(&mut tcx, "Intensity", "Active");
+
+        if let Some(cadence) = summary.average_cadence {
+            element(&mut tcx, "Cadence", cadence.min(MAX_CADENCE));
+        }
+
+        element(&mut tcx, "TriggerMethod", "Manual");
+        tcx.push_str("<Track>\n");
+
+        for (record, distance) in records[lap_start..lap_end]
+            .iter()
+            .zip(distances[lap_start..lap_end].iter())
+        {
+            let time =
+                start_time + Duration::seconds(session.time_since_start(record.elapsed) as i64);
+
+            tcx.push_str("<Trackpoint>\n");
+            element(&mut tcx, "Time", format_time(time));
+            element(&mut tcx, "DistanceMeters", format!("{:.2}", distance));
+
+            if let Some(heart_rate) = record.heart_rate {
+                heart_rate_element(&mut tcx, "HeartRateBpm", heart_rate);
+            }
+
+            if let Some(cadence) = record.cadence {
+                element(&mut tcx, "Cadence", cadence.min(MAX_CADENCE));
+            }
+
+            if record.speed.is_some() || record.power.is_some() {
+                tcx.push_str("<Extensions>\n<ns3:TPX>\n");
+
+                if let Some(speed) = record.speed {
+                    element(&mut tcx, "ns3:Speed", format_speed(speed as f64));
+                }
+
+                if let Some(power) = record.power {
+                    element(&mut tcx, "ns3:Watts", power);
+                }
+
+                tcx.push_str("</ns3:TPX>\n</Extensions>\n");
+            }
+
+            tcx.push_str("</Trackpoint>\n");
+        }
+
+        tcx.push_str("</Track>\n");
+
+        if summary.average_speed.is_some() || summary.average_power.is_some() {
+            tcx.push_str("<Extensions>\n<ns3:LX>\n");
+
+            if let Some(speed) = summary.average_speed {
+                element(&mut tcx, "ns3:AvgSpeed", format_speed(speed));
+            }
+
+            if let Some(power) = summary.average_power {
+                element(&mut tcx, "ns3:AvgWatts", power);
+            }
+
+            if let Some(power) = summary.max_power {
+                element(&mut tcx, "ns3:MaxWatts", power);
+            }
+
+            tcx.push_str("</ns3:LX>\n</Extensions>\n");
+        }
+
+        tcx.push_str("</Lap>\n");
+    }
+
+    tcx.push_str("</Activity>\n</Activities>\n</TrainingCenterDatabase>\n");
+
+    Ok(tcx)
+}
+
+fn element(tcx: &mut String, name: &str, value: impl Display) {
+    tcx.push_str(&format!("<{}>{}</{}>\n", name, value, name));
+}
+
+fn heart_rate_element(tcx: &mut String, name: &str, heart_rate: u16) {
+    tcx.push_str(&format!(
+        "<{}><Value>{}</Value></{}>\n",
+        name, heart_rate, name
+    ));
+}
+
+fn format_time(date: DateTime<Local>) -> String {
+    date.with_timezone(&Utc)
+        .format("%Y-%m-%dT%H:%M:%SZ")
+        .to_string()
+}
+
+// km/h to m/s
+fn format_speed(speed: f64) -> String {
+    format!("{:.3}", speed * KPH_TO_MPS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::pipeline::Record;
+    use crate::data::session::{Pause, SessionStatus};
+    use crate::import::{self, to_session};
+    use chrono::TimeZone;
+
+    fn ride(seconds: u32) -> Session {
+        let mut session = Session::new();
+        let start_time = Utc
+            .with_ymd_and_hms(2024, 3, 1, 7, 30, 0)
+            .unwrap()
+            .with_timezone(&Local);
+
+        session.status = SessionStatus::Finished;
+        session.start_time = Some(start_time);
+        session.end_time = Some(start_time + Duration::seconds(seconds as i64 + 30));
+        session.paused_time = 30;
+        session.pauses = vec![Pause {
+            elapsed: 20,
+            duration: 30,
+        }];
+        session.laps = vec![seconds / 2];
+        session.total_distance = seconds * 10;
+        session.records = (0..seconds)
+            .map(|elapsed| Record {
+                elapsed,
+                heart_rate: Some(140),
+                power: Some(200 + (elapsed % 2) as u16 * 100),
+                cadence: Some(90),
+                speed: Some(36),
+                ..Default::default()
+            })
+            .collect();
+
+        session
+    }
+
+    #[test]
+    fn writes_laps_and_trackpoints() {
+        let tcx = encode_session(&ride(60)).unwrap();
+
+        assert_eq!(tcx.matches("<Lap ").count(), 2);
+        assert_eq!(tcx.matches("<Trackpoint>").count(), 60);
+        // The second lap starts after the pause
+        assert!(tcx.contains("<Lap StartTime=\"2024-03-01T07:31:00Z\">"));
+        assert!(tcx.contains("<ns3:AvgWatts>250</ns3:AvgWatts>"));
+        assert!(tcx.contains("<HeartRateBpm><Value>140</Value></HeartRateBpm>"));
+        // 36 km/h
+        assert!(tcx.contains("<ns3:Speed>10.000</ns3:Speed>"));
+    }
+
+    #[test]
+    fn round_trips_through_the_importer() {
+        let session = ride(60);
+
+        let tcx = encode_session(&session).unwrap();
+        let imported = to_session(import::tcx::decode(&tcx).unwrap()).unwrap();
+
+        assert_eq!(imported.start_time, session.start_time);
+        assert_eq!(imported.records.len(), 60);
+        assert_eq!(imported.pauses, session.pauses);
+        assert_eq!(imported.paused_time, 30);
+        assert_eq!(imported.laps, vec![30]);
+        assert_eq!(imported.total_distance, 600);
+
+        for (imported, record) in imported.records.iter().zip(session.records.iter()) {
+            assert_eq!(imported.elapsed, record.elapsed);
+            assert_eq!(imported.power, record.power);
+            assert_eq!(imported.heart_rate, record.heart_rate);
+            assert_eq!(imported.cadence, record.cadence);
+            assert_eq!(imported.speed, record.speed);
+        }
+    }
+
+    #[test]
+    fn rejects_sessions_without_start_time() {
+        assert!(encode_session(&Session::new()).is_err());
+    }
+}
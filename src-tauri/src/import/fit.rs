@@ -0,0 +1,152 @@
+use crate::error::error_generic;
+use crate::export::fit::{FILE_ID, LAP, RECORD};
+use crate::prelude::*;
+use crate::utils::fit::{decode as decode_fit, from_fit_timestamp};
+
+use super::{Activity, Sample};
+
+const FILE_TYPE_ACTIVITY: i64 = 4;
+
+/// Reads the records and laps of a FIT activity file
+pub fn decode(bytes: &[u8]) -> Result<Activity> {
+    let messages = decode_fit(bytes)?;
+
+    let file_type = messages
+        .iter()
+        .find(|message| message.global == FILE_ID)
+        .and_then(|message| message.integer(0));
+
+    if file_type != Some(FILE_TYPE_ACTIVITY) {
+        return Err(error_generic("FIT file is not an activity"));
+    }
+
+    let samples = messages
+        .iter()
+        .filter(|message| message.global == RECORD)
+        .filter_map(|message| {
+            // Enhanced speed replaces speed on newer devices, both in mm/s
+            let speed = message.integer(73).or(message.integer(6));
+
+            Some(Sample {
+                time: message.timestamp()?,
+                heart_rate: message.integer(3).map(|hr| hr as u16),
+                power: message.integer(7).map(|p| p as u16),
+                cadence: message.integer(4).map(|c| c as u16),
+                speed: speed.map(|s| s as f64 / 1000.0 * 3.6),
+                // Centimeters
+                distance: message.integer(5).map(|d| d as f64 / 100.0),
+            })
+        })
+        .collect();
+
+    let laps = messages
+        .iter()
+        .filter(|message| message.global == LAP)
+        .filter_map(|message| from_fit_timestamp(message.integer(2)? as u32))
+        .collect();
+
+    Ok(Activity { samples, laps })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::fit::{FitWriter, Message, Value, TIMESTAMP_FIELD};
+
+    // 2024-03-01 07:30:00 UTC
+    const START: u32 = 1_078_126_200;
+
+    fn file_id(file_type: u8) -> Message {
+        Message::new(FILE_ID, vec![(0, Value::Enum(Some(file_type)))])
+    }
+
+    fn record(
+        timestamp: u32,
+        power: Option<u16>,
+        speed: u16,
+        enhanced_speed: Option<u32>,
+    ) -> Message {
+        Message::new(
+            RECORD,
+            vec![
+                (TIMESTAMP_FIELD, Value::UInt32(Some(timestamp))),
+                (3, Value::UInt8(Some(140))),
+                (4, Value::UInt8(Some(90))),
+                (5, Value::UInt32(Some((timestamp - START) * 1000))),
+                (6, Value::UInt16(Some(speed))),
+                (7, Value::UInt16(power)),
+                (73, Value::UInt32(enhanced_speed)),
+            ],
+        )
+    }
+
+    fn lap(start: u32) -> Message {
+        Message::new(
+            LAP,
+            vec![
+                (TIMESTAMP_FIELD, Value::UInt32(Some(start + 60))),
+                (2, Value::UInt32(Some(start))),
+            ],
+        )
+    }
+
+    fn encode(messages: &[Message]) -> Vec<u8> {
+        let mut writer = FitWriter::new();
+
+        for message in messages {
+            writer.write(message);
+        }
+
+        writer.finish()
+    }
+
+    #[test]
+    fn reads_records_and_laps() {
+        let bytes = encode(&[
+            file_id(4),
+            record(START, Some(200), 10000, None),
+            record(START + 1, None, 10000, None),
+            lap(START),
+            lap(START + 1),
+        ]);
+
+        let activity = decode(&bytes).unwrap();
+
+        assert_eq!(activity.samples.len(), 2);
+
+        let sample = &activity.samples[0];
+        assert_eq!(sample.time, from_fit_timestamp(START).unwrap());
+        assert_eq!(sample.power, Some(200));
+        assert_eq!(sample.heart_rate, Some(140));
+        assert_eq!(sample.cadence, Some(90));
+        // 10 m/s
+        assert_eq!(sample.speed, Some(36.0));
+        assert_eq!(activity.samples[1].distance, Some(10.0));
+        assert_eq!(activity.samples[1].power, None);
+
+        assert_eq!(
+            activity.laps,
+            vec![
+                from_fit_timestamp(START).unwrap(),
+                from_fit_timestamp(START + 1).unwrap()
+            ]
+        );
+    }
+
+    #[test]
+    fn prefers_the_enhanced_speed() {
+        let bytes = encode(&[file_id(4), record(START, None, 10000, Some(5000))]);
+
+        let activity = decode(&bytes).unwrap();
+
+        assert_eq!(activity.samples[0].speed, Some(18.0));
+    }
+
+    #[test]
+    fn rejects_files_that_are_not_activities() {
+        // Workout file
+        let bytes = encode(&[file_id(5), record(START, Some(200), 10000, None)]);
+
+        assert!(decode(&bytes).is_err());
+    }
+}
@@ -0,0 +1,288 @@
+use chrono::{DateTime, Local};
+use log::info;
+use serde::Serialize;
+use std::{fs, path::Path};
+
+use crate::data::{
+    aerobic::analyze_aerobic,
+    metrics::calculate_metrics,
+    pipeline::{Attribution, GapFill, Record, SensorSource, Sources},
+    power_curve::mean_max_power,
+    session::{Session, SessionStatus},
+};
+use crate::error::error_generic;
+use crate::prelude::*;
+use crate::system::{directory, user::get_user_settings};
+
+pub mod fit;
+pub mod tcx;
+
+const LOGGER_NAME: &str = "import";
+
+// Longer gaps between samples are treated as pauses, in seconds
+const MAX_GAP: i64 = 5;
+
+/// One sample of an imported activity
+pub struct Sample {
+    pub time: DateTime<Local>,
+    pub heart_rate: Option<u16>,
+    pub power: Option<u16>,
+    pub cadence: Option<u16>,
+    // km/h
+    pub speed: Option<f64>,
+    // Meters since the start
+    pub distance: Option<f64>,
+}
+
+pub struct Activity {
+    pub samples: Vec<Sample>,
+    // Start time of each lap
+    pub laps: Vec<DateTime<Local>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportResult {
+    pub session_id: String,
+    // A session with the same start time was already saved
+    pub duplicate: bool,
+}
+
+/// Imports a FIT or TCX activity into the saved sessions
+pub async fn import_activity(path: &Path) -> Result<ImportResult> {
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    let activity = match extension.as_str() {
+        "fit" => fit::decode(&fs::read(path)?)?,
+        "tcx" => tcx::decode(&fs::read_to_string(path)?)?,
+        _ => return Err(error_generic("Unsupported file type")),
+    };
+
+    let mut session = to_session(activity)?;
+    let start_time = session.start_time.unwrap_or_else(Local::now);
+
+    // Compared in whole seconds, FIT timestamps have no fractions
+    if let Some(session_id) = directory::find_session_by_start_time(start_time)? {
+        info!(
+            "{}::import_activity: {} was already imported as {}",
+            LOGGER_NAME,
+            path.display(),
+            session_id
+        );
+
+        return Ok(ImportResult {
+            session_id,
+            duplicate: true,
+        });
+    }
+
+    let settings = get_user_settings().await;
+    session.metrics = Some(calculate_metrics(&session, settings.ftp, settings.weight));
+    session.power_curve = Some(mean_max_power(&session.records));
+    session.aerobic = Some(analyze_aerobic(&session));
+
+    let session_id = start_time.format("%Y%m%d%H%M%S").to_string();
+    directory::save_session(&session, &session_id)?;
+
+    Ok(ImportResult {
+        session_id,
+        duplicate: false,
+    })
+}
+
+/// Resamples the activity into the 1 Hz timeline of a finished session.
+///
+/// Short gaps hold the last sample, longer ones count as paused time.
+pub fn to_session(activity: Activity) -> Result<Session> {
+    let mut samples = activity.samples;
+    samples.sort_by_key(|sample| sample.time);
+    samples.dedup_by_key(|sample| sample.time.timestamp());
+
+    let (Some(first), Some(last)) = (samples.first(), samples.last()) else {
+        return Err(error_generic("Activity has no records"));
+    };
+
+    let mut session = Session::new();
+    session.status = SessionStatus::Finished;
+    session.start_time = Some(first.time);
+    session.end_time = Some(last.time);
+
+    // Time of the sample each record was made from
+    let mut times: Vec<DateTime<Local>> = Vec::new();
+    let mut distance = 0.0;
+
+    for (index, sample) in samples.iter().enumerate() {
+        let gap = match samples.get(index + 1) {
+            Some(next) => (next.time - sample.time).num_seconds(),
+            None => 1,
+        };
+
+        let repeat = if gap <= MAX_GAP { gap.max(1) } else { 1 };
+
+        for second in 0..repeat {
+            let fill = (second > 0).then_some(GapFill::HoldLast);
+
+            session
+                .records
+                .push(to_record(session.records.len() as u32, sample, fill));
+            times.push(sample.time);
+
+            distance += sample.speed.unwrap_or(0.0) / 3.6;
+        }
+
+        if gap > MAX_GAP {
+            session.add_pause((gap - 1) as u32);
+        }
+    }
+
+    // Prefer the distance recorded by the device
+    let recorded_distance = samples.iter().rev().find_map(|sample| sample.distance);
+    session.total_distance = recorded_distance.unwrap_or(distance).round() as u32;
+
+    let mut laps: Vec<u32> = activity
+        .laps
+        .iter()
+        .filter_map(|lap| times.iter().position(|time| time >= lap))
+        .filter(|elapsed| *elapsed > 0)
+        .map(|elapsed| elapsed as u32)
+        .collect();
+
+    laps.sort();
+    laps.dedup();
+    session.laps = laps;
+
+    Ok(session)
+}
+
+fn to_record(elapsed: u32, sample: &Sample, fill: Option<GapFill>) -> Record {
+    let attribution = |value: bool| {
+        value.then_some(Attribution {
+            source: SensorSource::Imported,
+            fill,
+        })
+    };
+
+    Record {
+        elapsed,
+        heart_rate: sample.heart_rate,
+        power: sample.power,
+        cadence: sample.cadence,
+        speed: sample.speed.map(|s| s.round() as u16),
+        sources: Sources {
+            heart_rate: attribution(sample.heart_rate.is_some()),
+            power: attribution(sample.power.is_some()),
+            cadence: attribution(sample.cadence.is_some()),
+            speed: attribution(sample.speed.is_some()),
+        },
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::session::Pause;
+    use crate::export::fit::encode_session;
+    use crate::storage::{sqlite::SqliteRepository, SessionRepository};
+    use chrono::{Duration, TimeZone};
+
+    fn sample(start: DateTime<Local>, seconds: i64, power: u16) -> Sample {
+        Sample {
+            time: start + Duration::seconds(seconds),
+            heart_rate: Some(140),
+            power: Some(power),
+            cadence: Some(90),
+            speed: Some(36.0),
+            distance: None,
+        }
+    }
+
+    fn start() -> DateTime<Local> {
+        Local.with_ymd_and_hms(2024, 3, 1, 7, 30, 0).unwrap()
+    }
+
+    #[test]
+    fn holds_short_gaps_and_pauses_on_long_ones() {
+        let start = start();
+        let activity = Activity {
+            samples: vec![
+                sample(start, 0, 100),
+                // Out of order and duplicated samples
+                sample(start, 3, 200),
+                sample(start, 1, 110),
+                sample(start, 1, 110),
+                sample(start, 20, 300),
+            ],
+            laps: vec![start, start + Duration::seconds(3)],
+        };
+
+        let session = to_session(activity).unwrap();
+
+        let power: Vec<Option<u16>> = session.records.iter().map(|r| r.power).collect();
+        assert_eq!(
+            power,
+            vec![Some(100), Some(110), Some(110), Some(200), Some(300)]
+        );
+
+        // The second of the gap holds the last sample
+        assert_eq!(session.records[1].sources.power.unwrap().fill, None);
+        assert_eq!(
+            session.records[2].sources.power.unwrap().fill,
+            Some(GapFill::HoldLast)
+        );
+
+        assert_eq!(
+            session.pauses,
+            vec![Pause {
+                elapsed: 4,
+                duration: 16
+            }]
+        );
+        assert_eq!(session.paused_time, 16);
+        assert_eq!(session.laps, vec![3]);
+        assert_eq!(session.start_time, Some(start));
+        assert_eq!(session.end_time, Some(start + Duration::seconds(20)));
+        // Integrated from the speed without a device distance
+        assert_eq!(session.total_distance, 50);
+    }
+
+    #[test]
+    fn rejects_activities_without_samples() {
+        let activity = Activity {
+            samples: Vec::new(),
+            laps: Vec::new(),
+        };
+
+        assert!(to_session(activity).is_err());
+    }
+
+    #[test]
+    fn detects_a_ride_that_was_exported_and_imported_again() {
+        let repository = SqliteRepository::open(Path::new(":memory:")).unwrap();
+
+        // Recorded sessions start at a fraction of a second, FIT files don't
+        let mut recorded = to_session(Activity {
+            samples: (0..10).map(|second| sample(start(), second, 200)).collect(),
+            laps: Vec::new(),
+        })
+        .unwrap();
+        recorded.start_time = Some(start() + Duration::milliseconds(400));
+        repository.insert_session("recorded", &recorded).unwrap();
+
+        let bytes = encode_session(&recorded).unwrap();
+        let imported = to_session(fit::decode(&bytes).unwrap()).unwrap();
+
+        assert_eq!(
+            repository
+                .find_session_by_start_time(imported.start_time.unwrap())
+                .unwrap(),
+            Some("recorded".to_string())
+        );
+
+        let later = imported.start_time.unwrap() + Duration::seconds(1);
+        assert_eq!(repository.find_session_by_start_time(later).unwrap(), None);
+    }
+}
@@ -0,0 +1,192 @@
+use chrono::{DateTime, Local};
+use serde::Deserialize;
+
+use crate::error::error_generic;
+use crate::prelude::*;
+
+use super::{Activity, Sample};
+
+const MPS_TO_KPH: f64 = 3.6;
+
+#[derive(Deserialize)]
+struct TrainingCenterDatabase {
+    #[serde(rename = "Activities")]
+    activities: Activities,
+}
+
+#[derive(Deserialize)]
+struct Activities {
+    #[serde(rename = "Activity", default)]
+    activities: Vec<TcxActivity>,
+}
+
+#[derive(Deserialize)]
+struct TcxActivity {
+    #[serde(rename = "Lap", default)]
+    laps: Vec<Lap>,
+}
+
+#[derive(Deserialize)]
+struct Lap {
+    #[serde(rename = "@StartTime")]
+    start_time: String,
+    #[serde(rename = "Track", default)]
+    tracks: Vec<Track>,
+}
+
+#[derive(Deserialize)]
+struct Track {
+    #[serde(rename = "Trackpoint", default)]
+    trackpoints: Vec<Trackpoint>,
+}
+
+#[derive(Deserialize)]
+struct Trackpoint {
+    #[serde(rename = "Time")]
+    time: String,
+    #[serde(rename = "DistanceMeters")]
+    distance: Option<f64>,
+    #[serde(rename = "HeartRateBpm")]
+    heart_rate: Option<HeartRate>,
+    #[serde(rename = "Cadence")]
+    cadence: Option<u16>,
+    #[serde(rename = "Extensions")]
+    extensions: Option<Extensions>,
+}
+
+#[derive(Deserialize)]
+struct HeartRate {
+    #[serde(rename = "Value")]
+    value: u16,
+}
+
+// Garmin ActivityExtension, namespace prefixes are ignored
+#[derive(Deserialize)]
+struct Extensions {
+    #[serde(rename = "TPX")]
+    tpx: Option<Tpx>,
+}
+
+#[derive(Deserialize)]
+struct Tpx {
+    // m/s
+    #[serde(rename = "Speed")]
+    speed: Option<f64>,
+    #[serde(rename = "Watts")]
+    watts: Option<u16>,
+}
+
+/// Reads the first activity of a Garmin Training Center file
+pub fn decode(xml: &str) -> Result<Activity> {
+    let database: TrainingCenterDatabase = quick_xml::de::from_str(xml)
+        .map_err(|err| error_generic(&format!("Invalid TCX file: {}", err)))?;
+
+    let Some(activity) = database.activities.activities.into_iter().next() else {
+        return Err(error_generic("TCX file has no activity"));
+    };
+
+    let mut samples = Vec::new();
+    let mut laps = Vec::new();
+
+    for lap in activity.laps {
+        laps.push(parse_time(&lap.start_time)?);
+
+        for trackpoint in lap.tracks.into_iter().flat_map(|t| t.trackpoints) {
+            let tpx = trackpoint.extensions.and_then(|e| e.tpx);
+
+            samples.push(Sample {
+                time: parse_time(&trackpoint.time)?,
+                heart_rate: trackpoint.heart_rate.map(|hr| hr.value),
+                power: tpx.as_ref().and_then(|tpx| tpx.watts),
+                cadence: trackpoint.cadence,
+                speed: tpx.and_then(|tpx| tpx.speed).map(|s| s * MPS_TO_KPH),
+                distance: trackpoint.distance,
+            });
+        }
+    }
+
+    Ok(Activity { samples, laps })
+}
+
+fn parse_time(time: &str) -> Result<DateTime<Local>> {
+    DateTime::parse_from_rfc3339(time)
+        .map(|date| date.with_timezone(&Local))
+        .map_err(|_| error_generic(&format!("Invalid TCX time: {}", time)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ACTIVITY: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<TrainingCenterDatabase xmlns="http://www.garmin.com/xmlschemas/TrainingCenterDatabase/v2" xmlns:ns3="http://www.garmin.com/xmlschemas/ActivityExtension/v2">
+<Activities>
+<Activity Sport="Biking">
+<Id>2024-03-01T07:30:00Z</Id>
+<Lap StartTime="2024-03-01T07:30:00Z">
+<TotalTimeSeconds>2</TotalTimeSeconds>
+<Track>
+<Trackpoint>
+<Time>2024-03-01T07:30:00Z</Time>
+<DistanceMeters>0.00</DistanceMeters>
+<HeartRateBpm><Value>140</Value></HeartRateBpm>
+<Cadence>90</Cadence>
+<Extensions><ns3:TPX><ns3:Speed>10.000</ns3:Speed><ns3:Watts>200</ns3:Watts></ns3:TPX></Extensions>
+</Trackpoint>
+<Trackpoint>
+<Time>2024-03-01T07:30:01Z</Time>
+<DistanceMeters>10.00</DistanceMeters>
+</Trackpoint>
+</Track>
+</Lap>
+<Lap StartTime="2024-03-01T07:30:02Z">
+<Track>
+<Trackpoint>
+<Time>2024-03-01T07:30:02Z</Time>
+<DistanceMeters>20.00</DistanceMeters>
+</Trackpoint>
+</Track>
+</Lap>
+</Activity>
+</Activities>
+</TrainingCenterDatabase>
+"#;
+
+    #[test]
+    fn reads_trackpoints_and_laps() {
+        let activity = decode(ACTIVITY).unwrap();
+
+        assert_eq!(activity.samples.len(), 3);
+
+        let sample = &activity.samples[0];
+        assert_eq!(sample.time, parse_time("2024-03-01T07:30:00Z").unwrap());
+        assert_eq!(sample.heart_rate, Some(140));
+        assert_eq!(sample.cadence, Some(90));
+        assert_eq!(sample.power, Some(200));
+        assert_eq!(sample.speed, Some(36.0));
+        assert_eq!(sample.distance, Some(0.0));
+
+        let sample = &activity.samples[1];
+        assert_eq!(sample.heart_rate, None);
+        assert_eq!(sample.power, None);
+        assert_eq!(sample.distance, Some(10.0));
+
+        assert_eq!(
+            activity.laps,
+            vec![
+                parse_time("2024-03-01T07:30:00Z").unwrap(),
+                parse_time("2024-03-01T07:30:02Z").unwrap()
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_files() {
+        assert!(decode("<TrainingCenterDatabase>").is_err());
+        assert!(decode(&ACTIVITY.replace("2024-03-01T07:30:01Z", "yesterday")).is_err());
+        assert!(decode(
+            "<TrainingCenterDatabase><Activities></Activities></TrainingCenterDatabase>"
+        )
+        .is_err());
+    }
+}
@@ -7,7 +7,10 @@ extern crate lazy_static;
 mod ble;
 mod data;
 mod error;
+mod export;
+mod import;
 mod prelude;
+mod storage;
 mod system;
 mod utils;
 mod workouts;
@@ -15,21 +18,40 @@ mod workouts;
 use crate::prelude::*;
 
 use ble::bluetooth::{Bluetooth, Connection, DeviceType, BLUETOOTH};
-use chrono::Local;
+use chrono::NaiveDate;
 use data::{
-    session::Session,
-    simulation::{Simulation, SIMULATION},
+    aerobic::{analyze_aerobic, load_aerobic_trend, AerobicAnalysis, AerobicTrendEntry},
+    comparison::{self, Alignment, SessionComparison},
+    ftp::{self, CriticalPowerModel, FtpEstimate, FtpTest},
+    history::{self, SessionSummary},
+    journal::{self, RecoverableSession},
+    metrics::{calculate_metrics, Metrics},
+    power_curve::{load_personal_records, session_power_curve, PersonalRecords},
+    session::{Session, SessionCommand},
+    simulation::Simulation,
+    training_load::{self, PlannedWorkout, TrainingLoadDay},
+    training_summary::{load_training_summaries, PeriodSummary, SummaryPeriod},
+    zones::{time_in_zones, TimeInZones, Zones},
 };
 use error::error_generic;
+use export::ExportFormat;
+use import::ImportResult;
 use log::{error, warn};
+use std::path::{Path, PathBuf};
 use system::{
     directory,
-    user::{User, APP_USER},
+    user::{get_user_settings, User, UserSettings, APP_USER},
 };
 use tauri::Manager;
 use tauri_plugin_log::{self, LogTarget};
 use tokio::sync::Mutex;
-use workouts::activities::{self, Activity, ACTIVITIES};
+use workouts::{
+    activities::{self, Activity, ACTIVITIES},
+    editor, fit,
+    library::{self, LibraryEntry},
+    reader,
+    validation::{WorkoutFileReport, WorkoutIssue},
+};
 
 lazy_static! {
     pub static ref TAURI_APP_HANDLE: Mutex<Option<tauri::AppHandle>> = Default::default();
@@ -123,6 +145,46 @@ async fn get_activities() -> Result<Vec<Activity>> {
     Ok(activities.clone())
 }
 
+#[tauri::command(async)]
+async fn create_workout(activity: Activity) -> Result<Activity> {
+    editor::create_workout(activity).await
+}
+
+#[tauri::command(async)]
+async fn update_workout(activity: Activity) -> Result<Activity> {
+    editor::update_workout(activity).await
+}
+
+#[tauri::command(async)]
+async fn duplicate_workout(activity_id: &str) -> Result<Activity> {
+    editor::duplicate_workout(activity_id).await
+}
+
+#[tauri::command(async)]
+async fn delete_workout(activity_id: &str) -> Result<()> {
+    editor::delete_workout(activity_id).await
+}
+
+#[tauri::command(async)]
+async fn validate_workout(activity: Activity) -> Result<Vec<WorkoutIssue>> {
+    Ok(editor::validate(&activity))
+}
+
+#[tauri::command(async)]
+async fn get_workout_library() -> Result<Vec<LibraryEntry>> {
+    library::get_library().await
+}
+
+#[tauri::command(async)]
+async fn export_workout(activity_id: &str) -> Result<PathBuf> {
+    fit::export_workout(activity_id).await
+}
+
+#[tauri::command(async)]
+async fn get_workout_load_report() -> Result<Vec<WorkoutFileReport>> {
+    Ok(reader::get_load_report())
+}
+
 #[tauri::command(async)]
 async fn get_app_user() -> Result<User> {
     let Some(lock) = APP_USER.get() else {
@@ -162,134 +224,295 @@ async fn request_spin_down() -> Result<()> {
 }
 
 #[tauri::command(async)]
-async fn start_session() -> Result<()> {
+async fn session_command(command: SessionCommand, workout_id: Option<String>) -> Result<()> {
     let bluetooth_guard = &BLUETOOTH.read().await;
     let Some(bt) = bluetooth_guard.as_ref() else {
-        warn!("main::start_session: Bluetooth not found.");
+        warn!("main::session_command: Bluetooth not found.");
         return Ok(());
     };
 
-    bt.start_session().await?;
+    bt.handle_session_command(command, workout_id).await?;
 
     Ok(())
 }
 
 #[tauri::command(async)]
-async fn stop_session(action: &str) -> Result<()> {
+async fn get_session_data() -> Result<Option<Session>> {
     let bluetooth_guard = &BLUETOOTH.read().await;
     let Some(bt) = bluetooth_guard.as_ref() else {
-        warn!("main::stop_session: Bluetooth not found.");
-        return Ok(());
+        warn!("main::get_session_data: Bluetooth not found.");
+        return Ok(None);
     };
 
-    bt.stop_session(action).await?;
+    let session_data = bt.get_session_data().await;
 
-    Ok(())
+    Ok(Some(session_data))
 }
 
 #[tauri::command(async)]
-async fn get_session_data() -> Result<Option<Session>> {
+async fn get_session_metrics() -> Result<Option<Metrics>> {
     let bluetooth_guard = &BLUETOOTH.read().await;
     let Some(bt) = bluetooth_guard.as_ref() else {
-        warn!("main::get_session_data: Bluetooth not found.");
+        warn!("main::get_session_metrics: Bluetooth not found.");
         return Ok(None);
     };
 
-    let session_data = bt.get_session_data().await?;
+    let metrics = bt.recorder.get_metrics().await;
 
-    Ok(Some(session_data))
+    Ok(Some(metrics))
 }
 
 #[tauri::command(async)]
-async fn start_simulation() -> Result<()> {
-    SIMULATION.set(Simulation::new()).ok();
+async fn list_sessions() -> Result<Vec<SessionSummary>> {
+    let settings = get_user_settings().await;
 
-    let Some(simulation) = SIMULATION.get() else {
-        return Ok(());
-    };
+    history::list_sessions(&settings)
+}
 
-    simulation.start().await;
+#[tauri::command(async)]
+async fn get_saved_session(session_id: &str) -> Result<Session> {
+    directory::load_session(session_id)
+}
 
-    Ok(())
+#[tauri::command(async)]
+async fn rename_session(session_id: &str, name: &str) -> Result<()> {
+    history::rename_session(session_id, name)
 }
 
 #[tauri::command(async)]
-async fn start_simulated_session() -> Result<()> {
-    SIMULATION.set(Simulation::new()).ok();
+async fn annotate_session(session_id: &str, notes: Option<String>, rpe: Option<u8>) -> Result<()> {
+    history::annotate_session(session_id, notes, rpe)
+}
 
-    let Some(simulation) = SIMULATION.get() else {
-        return Ok(());
-    };
+#[tauri::command(async)]
+async fn delete_session(session_id: &str) -> Result<()> {
+    history::delete_session(session_id)
+}
+
+#[tauri::command(async)]
+async fn list_recoverable_sessions() -> Result<Vec<RecoverableSession>> {
+    journal::list_recoverable_sessions()
+}
 
-    simulation.start_session().await;
+#[tauri::command(async)]
+async fn recover_session(session_id: &str) -> Result<String> {
+    journal::recover_session(session_id).await
+}
 
-    Ok(())
+#[tauri::command(async)]
+async fn discard_recoverable_session(session_id: &str) -> Result<()> {
+    journal::discard_recoverable_session(session_id)
 }
 
 #[tauri::command(async)]
-async fn stop_simulation(action: &str) -> Result<()> {
-    SIMULATION.set(Simulation::new()).ok();
+async fn get_saved_session_metrics(session_id: &str) -> Result<Metrics> {
+    let session = directory::load_session(session_id)?;
 
-    let Some(simulation) = SIMULATION.get() else {
-        return Ok(());
+    if let Some(metrics) = session.metrics {
+        return Ok(metrics);
+    }
+
+    let settings = get_user_settings().await;
+
+    Ok(calculate_metrics(&session, settings.ftp, settings.weight))
+}
+
+#[tauri::command(async)]
+async fn get_session_time_in_zones() -> Result<Option<TimeInZones>> {
+    let bluetooth_guard = &BLUETOOTH.read().await;
+    let Some(bt) = bluetooth_guard.as_ref() else {
+        warn!("main::get_session_time_in_zones: Bluetooth not found.");
+        return Ok(None);
     };
 
-    simulation.stop(action).await;
+    let time_in_zones = bt.recorder.get_time_in_zones().await;
+
+    Ok(Some(time_in_zones))
+}
+
+#[tauri::command(async)]
+async fn get_saved_session_time_in_zones(session_id: &str) -> Result<TimeInZones> {
+    let session = directory::load_session(session_id)?;
+    let settings = get_user_settings().await;
+
+    Ok(time_in_zones(&session, &Zones::from_settings(&settings)))
+}
+
+#[tauri::command(async)]
+async fn get_zones() -> Result<Zones> {
+    let settings = get_user_settings().await;
+
+    Ok(Zones::from_settings(&settings))
+}
+
+#[tauri::command(async)]
+async fn get_saved_session_power_curve(session_id: &str) -> Result<Vec<u16>> {
+    let session = directory::load_session(session_id)?;
+
+    Ok(session_power_curve(&session))
+}
+
+#[tauri::command(async)]
+async fn get_saved_session_aerobic_analysis(session_id: &str) -> Result<AerobicAnalysis> {
+    let session = directory::load_session(session_id)?;
+
+    Ok(analyze_aerobic(&session))
+}
+
+#[tauri::command(async)]
+async fn get_aerobic_trend() -> Result<Vec<AerobicTrendEntry>> {
+    load_aerobic_trend()
+}
+
+#[tauri::command(async)]
+async fn export_session(session_id: &str, format: ExportFormat) -> Result<PathBuf> {
+    export::export_session(session_id, format)
+}
+
+#[tauri::command(async)]
+async fn import_activity(path: &str) -> Result<ImportResult> {
+    import::import_activity(Path::new(path)).await
+}
+
+#[tauri::command(async)]
+async fn compare_sessions(
+    session_ids: Vec<String>,
+    alignment: Alignment,
+) -> Result<SessionComparison> {
+    comparison::compare_sessions(&session_ids, alignment).await
+}
+
+#[tauri::command(async)]
+async fn estimate_ftp(session_id: &str, test: FtpTest) -> Result<FtpEstimate> {
+    ftp::estimate_ftp(session_id, test).await
+}
+
+#[tauri::command(async)]
+async fn accept_ftp_estimate(session_id: &str, test: FtpTest) -> Result<UserSettings> {
+    ftp::accept_ftp_estimate(session_id, test).await
+}
+
+#[tauri::command(async)]
+async fn get_critical_power_model() -> Result<CriticalPowerModel> {
+    ftp::fit_critical_power()
+}
+
+#[tauri::command(async)]
+async fn get_training_load(from: NaiveDate, to: NaiveDate) -> Result<Vec<TrainingLoadDay>> {
+    let settings = get_user_settings().await;
+
+    training_load::load_training_load(&settings, from, to)
+}
+
+#[tauri::command(async)]
+async fn get_projected_form(
+    planned: Vec<PlannedWorkout>,
+    date: NaiveDate,
+) -> Result<TrainingLoadDay> {
+    let settings = get_user_settings().await;
+
+    training_load::project_form(&settings, &planned, date).await
+}
+
+#[tauri::command(async)]
+async fn get_training_summaries(
+    period: SummaryPeriod,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<Vec<PeriodSummary>> {
+    let settings = get_user_settings().await;
+
+    load_training_summaries(&settings, period, from, to)
+}
+
+#[tauri::command(async)]
+async fn get_personal_records() -> Result<PersonalRecords> {
+    load_personal_records()
+}
+
+#[tauri::command(async)]
+async fn start_simulation() -> Result<()> {
+    let simulation = Simulation::get();
+
+    simulation.start().await;
 
     Ok(())
 }
 
 #[tauri::command(async)]
-async fn stop_simulated_session(action: &str) -> Result<()> {
-    SIMULATION.set(Simulation::new()).ok();
+async fn simulated_session_command(
+    command: SessionCommand,
+    workout_id: Option<String>,
+) -> Result<()> {
+    let simulation = Simulation::get();
+
+    simulation
+        .handle_session_command(command, workout_id)
+        .await?;
 
-    let Some(simulation) = SIMULATION.get() else {
-        return Ok(());
-    };
+    Ok(())
+}
 
-    simulation.stop_session(action).await;
+#[tauri::command(async)]
+async fn stop_simulation(action: &str) -> Result<()> {
+    let simulation = Simulation::get();
+
+    simulation.stop(action).await;
 
     Ok(())
 }
 
 #[tauri::command(async)]
 async fn get_simulated_session_data() -> Result<Option<Session>> {
-    SIMULATION.set(Simulation::new()).ok();
-
-    let Some(simulation) = SIMULATION.get() else {
-        return Ok(None);
-    };
+    let simulation = Simulation::get();
 
     let session_data = simulation.get_session_data().await;
 
     Ok(Some(session_data))
 }
 
+#[tauri::command(async)]
+async fn get_simulated_session_metrics() -> Result<Option<Metrics>> {
+    let simulation = Simulation::get();
+
+    let metrics = simulation.recorder.get_metrics().await;
+
+    Ok(Some(metrics))
+}
+
+#[tauri::command(async)]
+async fn get_simulated_session_time_in_zones() -> Result<Option<TimeInZones>> {
+    let simulation = Simulation::get();
+
+    let time_in_zones = simulation.recorder.get_time_in_zones().await;
+
+    Ok(Some(time_in_zones))
+}
+
 #[tauri::command(async)]
 async fn save_current_session(simulation: bool) -> Result<()> {
-    let session = if simulation {
-        get_simulated_session_data().await?
-    } else {
-        get_session_data().await?
-    };
+    if simulation {
+        let simulation = Simulation::get();
 
-    if let Some(session) = session {
-        let now = Local::now();
-        let filename = now.format("%Y%m%d%H%M%S.json").to_string();
+        simulation.recorder.save().await?;
 
-        let _ = directory::save_session(&session, filename);
+        return Ok(());
     }
 
+    let bluetooth_guard = &BLUETOOTH.read().await;
+    let Some(bt) = bluetooth_guard.as_ref() else {
+        warn!("main::save_current_session: Bluetooth not found.");
+        return Ok(());
+    };
+
+    bt.recorder.save().await?;
+
     Ok(())
 }
 
 #[tauri::command(async)]
 async fn set_simulation_targets(power: usize, cadence: usize) -> Result<()> {
-    SIMULATION.set(Simulation::new()).ok();
-
-    let Some(simulation) = SIMULATION.get() else {
-        return Ok(());
-    };
+    let simulation = Simulation::get();
 
     simulation.set_targets(power as u16, cadence as u16).await;
 
@@ -301,7 +524,9 @@ async fn initialize_app(app_handle: tauri::AppHandle) {
 
     system::directory::initialize();
     system::user::load_app_user();
-    activities::load_activities();
+    storage::initialize();
+    journal::initialize();
+    activities::load_activities().await;
 
     Bluetooth::init().await;
 }
@@ -333,21 +558,55 @@ fn main() {
             get_connected_devices,
             // Files command
             get_activities,
+            get_workout_load_report,
+            get_workout_library,
+            export_workout,
+            create_workout,
+            update_workout,
+            duplicate_workout,
+            delete_workout,
+            validate_workout,
             get_app_user,
             // Indoor bike machine control commands
             execute_workout,
             request_spin_down,
             // Session Commands
-            start_session,
-            stop_session,
+            session_command,
             get_session_data,
+            get_session_metrics,
+            get_saved_session_metrics,
+            list_sessions,
+            get_saved_session,
+            rename_session,
+            annotate_session,
+            delete_session,
+            get_session_time_in_zones,
+            get_saved_session_time_in_zones,
+            get_zones,
+            get_saved_session_power_curve,
+            get_saved_session_aerobic_analysis,
+            get_aerobic_trend,
+            compare_sessions,
+            estimate_ftp,
+            accept_ftp_estimate,
+            get_critical_power_model,
+            get_training_load,
+            get_projected_form,
+            get_training_summaries,
+            get_personal_records,
+            export_session,
+            import_activity,
             save_current_session,
+            list_recoverable_sessions,
+            recover_session,
+            discard_recoverable_session,
             // Simulation commands
             start_simulation,
-            start_simulated_session,
             stop_simulation,
-            stop_simulated_session,
+            simulated_session_command,
             get_simulated_session_data,
+            get_simulated_session_metrics,
+            get_simulated_session_time_in_zones,
             set_simulation_targets,
         ])
         .run(tauri::generate_context!())
@@ -0,0 +1,100 @@
+use chrono::{Duration, Local, NaiveDateTime, TimeZone};
+use serde::Deserialize;
+
+use crate::data::{
+    pipeline::{Attribution, Record, SensorSource},
+    session::{Session, SessionStatus},
+};
+use crate::error::error_generic;
+use crate::prelude::*;
+
+#[derive(Deserialize)]
+enum LegacyStatus {
+    Started,
+    Paused,
+    Stopped,
+}
+
+impl From<LegacyStatus> for SessionStatus {
+    fn from(status: LegacyStatus) -> Self {
+        match status {
+            LegacyStatus::Started => SessionStatus::Running,
+            LegacyStatus::Paused => SessionStatus::Paused,
+            LegacyStatus::Stopped => SessionStatus::Finished,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct LegacyIndoorBikeData {
+    cadence: u16,
+    speed: u16,
+    power: u16,
+}
+
+/// Session file saved before the sensor streams were resampled,
+/// with one entry per notification and no timestamps
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LegacySession {
+    status: LegacyStatus,
+    indoor_bike_data: Vec<LegacyIndoorBikeData>,
+    heart_rate_data: Vec<u16>,
+    // Meters
+    total_distance: u32,
+}
+
+/// Reads a session file in the older format.
+///
+/// The sensors notified about once a second, so each entry becomes a second of the timeline.
+/// Files were named after the time they were saved, which is taken as the end of the session.
+pub fn parse_session(json: &str, session_id: &str) -> Result<Session> {
+    let legacy: LegacySession =
+        serde_json::from_str(json).map_err(|err| error_generic(&err.to_string()))?;
+
+    let end_time = NaiveDateTime::parse_from_str(session_id, "%Y%m%d%H%M%S")
+        .ok()
+        .and_then(|time| Local.from_local_datetime(&time).earliest());
+
+    let measured =
+        |value: u16, source: SensorSource| (Some(value), Some(Attribution { source, fill: None }));
+
+    let length = legacy
+        .indoor_bike_data
+        .len()
+        .max(legacy.heart_rate_data.len());
+
+    let records: Vec<Record> = (0..length)
+        .map(|elapsed| {
+            let mut record = Record {
+                elapsed: elapsed as u32,
+                ..Default::default()
+            };
+
+            if let Some(bpm) = legacy.heart_rate_data.get(elapsed) {
+                (record.heart_rate, record.sources.heart_rate) =
+                    measured(*bpm, SensorSource::HeartRateMonitor);
+            }
+
+            if let Some(data) = legacy.indoor_bike_data.get(elapsed) {
+                (record.power, record.sources.power) =
+                    measured(data.power, SensorSource::SmartTrainer);
+                (record.cadence, record.sources.cadence) =
+                    measured(data.cadence, SensorSource::SmartTrainer);
+                (record.speed, record.sources.speed) =
+                    measured(data.speed, SensorSource::SmartTrainer);
+            }
+
+            record
+        })
+        .collect();
+
+    let mut session = Session::new();
+    session.status = legacy.status.into();
+    session.end_time = end_time;
+    session.start_time = end_time.map(|time| time - Duration::seconds(records.len() as i64));
+    session.total_distance = legacy.total_distance;
+    session.records = records;
+
+    Ok(session)
+}
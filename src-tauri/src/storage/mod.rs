@@ -0,0 +1,164 @@
+use chrono::{DateTime, Local};
+use log::{info, warn};
+use std::{fs, path::Path, sync::OnceLock};
+
+use crate::data::{aerobic::analyze_aerobic, power_curve::mean_max_power, session::Session};
+use crate::error::error_generic;
+use crate::prelude::*;
+use crate::system::directory;
+
+mod legacy;
+pub mod sqlite;
+
+const LOGGER_NAME: &str = "storage";
+
+// Set once all the JSON files of the sessions directory were copied into the repository
+const JSON_IMPORTED_KEY: &str = "json_sessions_imported";
+
+static REPOSITORY: OnceLock<Box<dyn SessionRepository>> = OnceLock::new();
+
+/// Persistence of saved sessions
+pub trait SessionRepository: Send + Sync {
+    /// Returns false without overwriting if a session with the same id exists
+    fn insert_session(&self, session_id: &str, session: &Session) -> Result<bool>;
+    /// Sets the name given after the ride, without rewriting the records
+    fn rename_session(&self, session_id: &str, name: Option<&str>) -> Result<()>;
+    /// Sets the notes and RPE given after the ride, without rewriting the records
+    fn annotate_session(
+        &self,
+        session_id: &str,
+        notes: Option<&str>,
+        rpe: Option<u8>,
+    ) -> Result<()>;
+    fn load_session(&self, session_id: &str) -> Result<Session>;
+    fn delete_session(&self, session_id: &str) -> Result<()>;
+    /// Id of the session that started within the same second as `start_time`
+    fn find_session_by_start_time(&self, start_time: DateTime<Local>) -> Result<Option<String>>;
+    /// All sessions with what is calculated when they are saved,
+    /// but without their records, laps and pauses
+    fn list_session_headers(&self) -> Result<Vec<(String, Session)>>;
+    /// Best power of all sessions for every duration of their power curves,
+    /// index 0 holds the best 1 second power
+    fn best_power_curve(&self) -> Result<Vec<u16>>;
+
+    fn get_setting(&self, key: &str) -> Result<Option<String>>;
+    fn set_setting(&self, key: &str, value: &str) -> Result<()>;
+}
+
+/// Opens the database and brings in the sessions saved as JSON by older versions
+pub fn initialize() {
+    let repository = match directory::get_database_file()
+        .and_then(|path| sqlite::SqliteRepository::open(&path))
+    {
+        Ok(repository) => repository,
+        Err(err) => {
+            warn!(
+                "{}::initialize: Unable to open the database: {}",
+                LOGGER_NAME, err
+            );
+            return;
+        }
+    };
+
+    let imported = directory::get_sessions_directory()
+        .and_then(|sessions_directory| import_json_sessions(&repository, &sessions_directory));
+
+    if let Err(err) = imported {
+        warn!(
+            "{}::initialize: Unable to import JSON sessions: {}",
+            LOGGER_NAME, err
+        );
+    }
+
+    if REPOSITORY.set(Box::new(repository)).is_err() {
+        warn!("{}::initialize: Repository already set.", LOGGER_NAME);
+    }
+}
+
+pub fn repository() -> Result<&'static dyn SessionRepository> {
+    match REPOSITORY.get() {
+        Some(repository) => Ok(repository.as_ref()),
+        None => Err(error_generic("Session storage is not initialized")),
+    }
+}
+
+/// One-time copy of the `*.json` session files into the repository.
+///
+/// Runs again on the next start if a file could not be read.
+pub(crate) fn import_json_sessions(
+    repository: &dyn SessionRepository,
+    sessions_directory: &Path,
+) -> Result<()> {
+    if repository.get_setting(JSON_IMPORTED_KEY)?.is_some() {
+        return Ok(());
+    }
+
+    let mut imported = 0;
+    let mut failed = 0;
+
+    for entry in fs::read_dir(sessions_directory)? {
+        let file_path = entry?.path();
+
+        if file_path
+            .extension()
+            .is_none_or(|extension| extension != "json")
+        {
+            continue;
+        }
+
+        let Some(session_id) = file_path
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+        else {
+            continue;
+        };
+
+        let mut session = match read_json_session(&file_path, &session_id) {
+            Ok(session) => session,
+            Err(err) => {
+                warn!(
+                    "{}::import_json_sessions: Skipping {}: {}",
+                    LOGGER_NAME,
+                    file_path.display(),
+                    err
+                );
+
+                failed += 1;
+                continue;
+            }
+        };
+
+        // Older versions didn't calculate these on save
+        if session.power_curve.is_none() {
+            session.power_curve = Some(mean_max_power(&session.records));
+        }
+
+        if session.aerobic.is_none() {
+            session.aerobic = Some(analyze_aerobic(&session));
+        }
+
+        if repository.insert_session(&session_id, &session)? {
+            imported += 1;
+        }
+    }
+
+    info!(
+        "{}::import_json_sessions: Imported {} sessions, {} failed.",
+        LOGGER_NAME, imported, failed
+    );
+
+    if failed == 0 {
+        repository.set_setting(JSON_IMPORTED_KEY, &imported.to_string())?;
+    }
+
+    Ok(())
+}
+
+/// Reads a session file in the current format, or in the one saved before the 1 Hz timeline
+fn read_json_session(file_path: &Path, session_id: &str) -> Result<Session> {
+    let json = fs::read_to_string(file_path)?;
+
+    serde_json::from_str::<Session>(&json).or_else(|err| {
+        legacy::parse_session(&json, session_id).map_err(|_| error_generic(&err.to_string()))
+    })
+}
@@ -0,0 +1,837 @@
+use chrono::{DateTime, Duration, Local, Timelike};
+use rusqlite::{params, Connection, OptionalExtension, Row, Transaction};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{path::Path, sync::Mutex};
+
+use crate::data::{
+    metrics::Metrics,
+    pipeline::Record,
+    session::{Pause, Session},
+};
+use crate::error::error_generic;
+use crate::prelude::*;
+
+use super::SessionRepository;
+
+/// Schema changes, applied in order. `PRAGMA user_version` holds how many were applied.
+/// Only ever append to this list.
+const MIGRATIONS: &[&str] = &[
+    r#"
+    CREATE TABLE sessions (
+        id TEXT PRIMARY KEY,
+        status TEXT NOT NULL,
+        start_time TEXT,
+        end_time TEXT,
+        paused_time INTEGER NOT NULL,
+        total_distance INTEGER NOT NULL,
+        power_curve TEXT,
+        name TEXT,
+        workout_name TEXT,
+        notes TEXT,
+        rpe INTEGER,
+        updated_at INTEGER NOT NULL
+    );
+
+    CREATE INDEX sessions_start_time ON sessions (start_time);
+
+    CREATE TABLE records (
+        session_id TEXT NOT NULL REFERENCES sessions (id) ON DELETE CASCADE,
+        elapsed INTEGER NOT NULL,
+        heart_rate INTEGER,
+        power INTEGER,
+        cadence INTEGER,
+        speed INTEGER,
+        w_balance INTEGER,
+        sources TEXT NOT NULL,
+        dropouts TEXT NOT NULL,
+        PRIMARY KEY (session_id, elapsed)
+    );
+
+    CREATE TABLE laps (
+        session_id TEXT NOT NULL REFERENCES sessions (id) ON DELETE CASCADE,
+        lap_index INTEGER NOT NULL,
+        start_elapsed INTEGER NOT NULL,
+        PRIMARY KEY (session_id, lap_index)
+    );
+
+    CREATE TABLE metrics (
+        session_id TEXT PRIMARY KEY REFERENCES sessions (id) ON DELETE CASCADE,
+        duration INTEGER NOT NULL,
+        distance INTEGER NOT NULL,
+        average_power INTEGER,
+        max_power INTEGER,
+        normalized_power INTEGER,
+        intensity_factor REAL,
+        training_stress_score REAL,
+        variability_index REAL,
+        work REAL,
+        watts_per_kg REAL,
+        average_heart_rate INTEGER,
+        max_heart_rate INTEGER,
+        average_cadence INTEGER,
+        max_cadence INTEGER
+    );
+
+    CREATE TABLE settings (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );
+"#,
+    r#"
+    ALTER TABLE sessions ADD COLUMN workout_id TEXT;
+    ALTER TABLE sessions ADD COLUMN workout_version TEXT;
+
+    CREATE INDEX sessions_workout_id ON sessions (workout_id);
+"#,
+    r#"
+    CREATE TABLE pauses (
+        session_id TEXT NOT NULL REFERENCES sessions (id) ON DELETE CASCADE,
+        start_elapsed INTEGER NOT NULL,
+        duration INTEGER NOT NULL,
+        PRIMARY KEY (session_id, start_elapsed)
+    );
+"#,
+    r#"
+    ALTER TABLE sessions ADD COLUMN aerobic TEXT;
+"#,
+];
+
+const SESSION_COLUMNS: &str = "status, start_time, end_time, paused_time, total_distance, \
+    power_curve, name, workout_name, notes, rpe, workout_id, workout_version, aerobic";
+
+pub struct SqliteRepository {
+    connection: Mutex<Connection>,
+}
+
+impl SqliteRepository {
+    pub fn open(path: &Path) -> Result<Self> {
+        Self::from_connection(Connection::open(path)?)
+    }
+
+    fn from_connection(mut connection: Connection) -> Result<Self> {
+        connection.execute_batch("PRAGMA foreign_keys = ON; PRAGMA journal_mode = WAL;")?;
+        migrate(&mut connection)?;
+
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    fn with_connection<T>(&self, f: impl FnOnce(&mut Connection) -> Result<T>) -> Result<T> {
+        let mut connection = self
+            .connection
+            .lock()
+            .map_err(|_| error_generic("Database lock poisoned"))?;
+
+        f(&mut connection)
+    }
+}
+
+impl SessionRepository for SqliteRepository {
+    fn insert_session(&self, session_id: &str, session: &Session) -> Result<bool> {
+        self.with_connection(|connection| {
+            let transaction = connection.transaction()?;
+
+            let exists = transaction
+                .query_row("SELECT 1 FROM sessions WHERE id = ?1", [session_id], |_| {
+                    Ok(())
+                })
+                .optional()?
+                .is_some();
+
+            if exists {
+                return Ok(false);
+            }
+
+            write_session(&transaction, session_id, session)?;
+            transaction.commit()?;
+
+            Ok(true)
+        })
+    }
+
+    fn rename_session(&self, session_id: &str, name: Option<&str>) -> Result<()> {
+        self.with_connection(|connection| {
+            let updated = connection.execute(
+                "UPDATE sessions SET name = ?2, updated_at = ?3 WHERE id = ?1",
+                params![session_id, name, Local::now().timestamp()],
+            )?;
+
+            match updated {
+                0 => Err(error_generic("Session not found")),
+                _ => Ok(()),
+            }
+        })
+    }
+
+    fn annotate_session(
+        &self,
+        session_id: &str,
+        notes: Option<&str>,
+        rpe: Option<u8>,
+    ) -> Result<()> {
+        self.with_connection(|connection| {
+            let updated = connection.execute(
+                "UPDATE sessions SET notes = ?2, rpe = ?3, updated_at = ?4 WHERE id = ?1",
+                params![session_id, notes, rpe, Local::now().timestamp()],
+            )?;
+
+            match updated {
+                0 => Err(error_generic("Session not found")),
+                _ => Ok(()),
+            }
+        })
+    }
+
+    fn load_session(&self, session_id: &str) -> Result<Session> {
+        self.with_connection(|connection| {
+            let row = connection
+                .query_row(
+                    &format!("SELECT {} FROM sessions WHERE id = ?1", SESSION_COLUMNS),
+                    [session_id],
+                    read_session,
+                )
+                .optional()?
+                .ok_or(error_generic("Session not found"))?;
+
+            let mut session = decode_session(row)?;
+
+            let mut statement = connection.prepare(
+                "SELECT elapsed, heart_rate, power, cadence, speed, w_balance, sources, dropouts \
+                 FROM records WHERE session_id = ?1 ORDER BY elapsed",
+            )?;
+
+            let rows = statement
+                .query_map([session_id], read_record)?
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+
+            for (mut record, sources, dropouts) in rows {
+                record.sources = from_json(&sources)?;
+                record.dropouts = from_json(&dropouts)?;
+
+                session.records.push(record);
+            }
+
+            let mut statement = connection.prepare(
+                "SELECT start_elapsed FROM laps WHERE session_id = ?1 ORDER BY lap_index",
+            )?;
+
+            session.laps = statement
+                .query_map([session_id], |row| row.get(0))?
+                .collect::<std::result::Result<Vec<u32>, _>>()?;
+
+            let mut statement = connection.prepare(
+                "SELECT start_elapsed, duration FROM pauses WHERE session_id = ?1 \
+                 ORDER BY start_elapsed",
+            )?;
+
+            session.pauses = statement
+                .query_map([session_id], |row| {
+                    Ok(Pause {
+                        elapsed: row.get(0)?,
+                        duration: row.get(1)?,
+                    })
+                })?
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+
+            session.metrics = load_metrics(connection, session_id)?;
+
+            Ok(session)
+        })
+    }
+
+    fn delete_session(&self, session_id: &str) -> Result<()> {
+        self.with_connection(|connection| {
+            let deleted = connection.execute("DELETE FROM sessions WHERE id = ?1", [session_id])?;
+
+            if deleted == 0 {
+                return Err(error_generic("Session not found"));
+            }
+
+            Ok(())
+        })
+    }
+
+    fn find_session_by_start_time(&self, start_time: DateTime<Local>) -> Result<Option<String>> {
+        // Start times are stored as UTC text, which sorts in time order
+        let second = start_time.with_nanosecond(0).unwrap_or(start_time);
+
+        self.with_connection(|connection| {
+            let session_id = connection
+                .query_row(
+                    "SELECT id FROM sessions WHERE start_time >= ?1 AND start_time < ?2 \
+                     ORDER BY start_time LIMIT 1",
+                    params![second, second + Duration::seconds(1)],
+                    |row| row.get(0),
+                )
+                .optional()?;
+
+            Ok(session_id)
+        })
+    }
+
+    fn list_session_headers(&self) -> Result<Vec<(String, Session)>> {
+        self.with_connection(|connection| {
+            let mut statement =
+                connection.prepare(&format!("SELECT {}, id FROM sessions", SESSION_COLUMNS))?;
+
+            let rows = statement
+                .query_map([], |row| {
+                    Ok((row.get::<_, String>(13)?, read_session(row)?))
+                })?
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+
+            rows.into_iter()
+                .map(|(session_id, row)| {
+                    let mut session = decode_session(row)?;
+                    session.metrics = load_metrics(connection, &session_id)?;
+
+                    Ok((session_id, session))
+                })
+                .collect()
+        })
+    }
+
+    fn best_power_curve(&self) -> Result<Vec<u16>> {
+        self.with_connection(|connection| {
+            // Power curves are stored as JSON arrays, indexed by duration
+            let mut statement = connection.prepare(
+                "SELECT MAX(curve.value) FROM sessions, json_each(sessions.power_curve) AS curve \
+                 GROUP BY curve.key ORDER BY curve.key",
+            )?;
+
+            let power_curve = statement
+                .query_map([], |row| row.get(0))?
+                .collect::<std::result::Result<Vec<u16>, _>>()?;
+
+            Ok(power_curve)
+        })
+    }
+
+    fn get_setting(&self, key: &str) -> Result<Option<String>> {
+        self.with_connection(|connection| {
+            let value = connection
+                .query_row("SELECT value FROM settings WHERE key = ?1", [key], |row| {
+                    row.get(0)
+                })
+                .optional()?;
+
+            Ok(value)
+        })
+    }
+
+    fn set_setting(&self, key: &str, value: &str) -> Result<()> {
+        self.with_connection(|connection| {
+            connection.execute(
+                "INSERT INTO settings (key, value) VALUES (?1, ?2) \
+                 ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+                [key, value],
+            )?;
+
+            Ok(())
+        })
+    }
+}
+
+fn migrate(connection: &mut Connection) -> Result<()> {
+    let version: usize = connection.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+
+    if version > MIGRATIONS.len() {
+        return Err(error_generic(
+            "Database was created by a newer version of the app",
+        ));
+    }
+
+    let transaction = connection.transaction()?;
+
+    for migration in MIGRATIONS.iter().skip(version) {
+        transaction.execute_batch(migration)?;
+    }
+
+    transaction.pragma_update(None, "user_version", MIGRATIONS.len())?;
+    transaction.commit()?;
+
+    Ok(())
+}
+
+fn write_session(transaction: &Transaction, session_id: &str, session: &Session) -> Result<()> {
+    let power_curve = session.power_curve.as_ref().map(to_json).transpose()?;
+    let aerobic = session.aerobic.as_ref().map(to_json).transpose()?;
+
+    transaction.execute(
+        "INSERT INTO sessions (id, status, start_time, end_time, paused_time, total_distance, \
+         power_curve, name, workout_name, notes, rpe, updated_at, workout_id, workout_version, \
+         aerobic) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
+        params![
+            session_id,
+            to_json(&session.status)?,
+            session.start_time,
+            session.end_time,
+            session.paused_time,
+            session.total_distance,
+            power_curve,
+            session.name,
+            session.workout_name,
+            session.notes,
+            session.rpe,
+            Local::now().timestamp(),
+            session.workout_id,
+            session.workout_version,
+            aerobic,
+        ],
+    )?;
+
+    let mut statement = transaction.prepare(
+        "INSERT INTO records (session_id, elapsed, heart_rate, power, cadence, speed, \
+         w_balance, sources, dropouts) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+    )?;
+
+    for record in session.records.iter() {
+        statement.execute(params![
+            session_id,
+            record.elapsed,
+            record.heart_rate,
+            record.power,
+            record.cadence,
+            record.speed,
+            record.w_balance,
+            to_json(&record.sources)?,
+            to_json(&record.dropouts)?,
+        ])?;
+    }
+
+    let mut statement = transaction
+        .prepare("INSERT INTO laps (session_id, lap_index, start_elapsed) VALUES (?1, ?2, ?3)")?;
+
+    for (index, lap) in session.laps.iter().enumerate() {
+        statement.execute(params![session_id, index, lap])?;
+    }
+
+    let mut statement = transaction
+        .prepare("INSERT INTO pauses (session_id, start_elapsed, duration) VALUES (?1, ?2, ?3)")?;
+
+    for pause in session.pauses.iter() {
+        statement.execute(params![session_id, pause.elapsed, pause.duration])?;
+    }
+
+    if let Some(metrics) = &session.metrics {
+        transaction.execute(
+            "INSERT INTO metrics (session_id, duration, distance, average_power, max_power, \
+             normalized_power, intensity_factor, training_stress_score, variability_index, \
+             work, watts_per_kg, average_heart_rate, max_heart_rate, average_cadence, \
+             max_cadence) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
+            params![
+                session_id,
+                metrics.duration,
+                metrics.distance,
+                metrics.average_power,
+                metrics.max_power,
+                metrics.normalized_power,
+                metrics.intensity_factor,
+                metrics.training_stress_score,
+                metrics.variability_index,
+                metrics.work,
+                metrics.watts_per_kg,
+                metrics.average_heart_rate,
+                metrics.max_heart_rate,
+                metrics.average_cadence,
+                metrics.max_cadence,
+            ],
+        )?;
+    }
+
+    Ok(())
+}
+
+// JSON columns are returned as text and decoded by the caller
+
+// With the status, power curve and aerobic analysis as JSON
+type SessionRow = (Session, String, Option<String>, Option<String>);
+
+fn read_session(row: &Row) -> rusqlite::Result<SessionRow> {
+    let mut session = Session::new();
+    session.start_time = row.get::<_, Option<DateTime<Local>>>(1)?;
+    session.end_time = row.get::<_, Option<DateTime<Local>>>(2)?;
+    session.paused_time = row.get(3)?;
+    session.total_distance = row.get(4)?;
+    session.name = row.get(6)?;
+    session.workout_name = row.get(7)?;
+    session.notes = row.get(8)?;
+    session.rpe = row.get(9)?;
+    session.workout_id = row.get(10)?;
+    session.workout_version = row.get(11)?;
+
+    Ok((session, row.get(0)?, row.get(5)?, row.get(12)?))
+}
+
+fn decode_session((mut session, status, power_curve, aerobic): SessionRow) -> Result<Session> {
+    session.status = from_json(&status)?;
+    session.power_curve = power_curve.as_deref().map(from_json).transpose()?;
+    session.aerobic = aerobic.as_deref().map(from_json).transpose()?;
+
+    Ok(session)
+}
+
+fn read_record(row: &Row) -> rusqlite::Result<(Record, String, String)> {
+    let record = Record {
+        elapsed: row.get(0)?,
+        heart_rate: row.get(1)?,
+        power: row.get(2)?,
+        cadence: row.get(3)?,
+        speed: row.get(4)?,
+        w_balance: row.get(5)?,
+        ..Default::default()
+    };
+
+    Ok((record, row.get(6)?, row.get(7)?))
+}
+
+fn load_metrics(connection: &Connection, session_id: &str) -> Result<Option<Metrics>> {
+    let metrics = connection
+        .query_row(
+            "SELECT duration, distance, average_power, max_power, normalized_power, \
+             intensity_factor, training_stress_score, variability_index, work, watts_per_kg, \
+             average_heart_rate, max_heart_rate, average_cadence, max_cadence \
+             FROM metrics WHERE session_id = ?1",
+            [session_id],
+            read_metrics,
+        )
+        .optional()?;
+
+    Ok(metrics)
+}
+
+fn read_metrics(row: &Row) -> rusqlite::Result<Metrics> {
+    Ok(Metrics {
+        duration: row.get(0)?,
+        distance: row.get(1)?,
+        average_power: row.get(2)?,
+        max_power: row.get(3)?,
+        normalized_power: row.get(4)?,
+        intensity_factor: row.get(5)?,
+        training_stress_score: row.get(6)?,
+        variability_index: row.get(7)?,
+        work: row.get(8)?,
+        watts_per_kg: row.get(9)?,
+        average_heart_rate: row.get(10)?,
+        max_heart_rate: row.get(11)?,
+        average_cadence: row.get(12)?,
+        max_cadence: row.get(13)?,
+    })
+}
+
+fn to_json<T: Serialize + ?Sized>(value: &T) -> Result<String> {
+    serde_json::to_string(value).map_err(|err| error_generic(&err.to_string()))
+}
+
+fn from_json<T: DeserializeOwned>(value: &str) -> Result<T> {
+    serde_json::from_str(value).map_err(|err| error_generic(&err.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{
+        aerobic::analyze_aerobic,
+        metrics::calculate_metrics,
+        pipeline::{Attribution, Field, GapFill, SensorSource, Sources},
+        power_curve::mean_max_power,
+        session::SessionStatus,
+    };
+    use crate::storage::{import_json_sessions, JSON_IMPORTED_KEY};
+    use chrono::TimeZone;
+    use std::fs;
+
+    fn repository() -> SqliteRepository {
+        SqliteRepository::from_connection(Connection::open_in_memory().unwrap()).unwrap()
+    }
+
+    fn ride() -> Session {
+        let start_time = Local.with_ymd_and_hms(2026, 3, 1, 7, 0, 0).unwrap();
+        let trainer = Some(Attribution {
+            source: SensorSource::SmartTrainer,
+            fill: None,
+        });
+
+        let mut session = Session::new();
+        session.status = SessionStatus::Finished;
+        session.start_time = Some(start_time + Duration::milliseconds(250));
+        session.end_time = Some(start_time + Duration::seconds(700));
+        session.paused_time = 40;
+        session.pauses = vec![Pause {
+            elapsed: 300,
+            duration: 40,
+        }];
+        session.laps = vec![200, 400];
+        session.total_distance = 5000;
+        session.records = (0..660)
+            .map(|elapsed| Record {
+                elapsed,
+                heart_rate: Some(120 + (elapsed / 60) as u16),
+                power: Some(200),
+                cadence: Some(90),
+                speed: None,
+                sources: Sources {
+                    power: trainer,
+                    cadence: Some(Attribution {
+                        source: SensorSource::SmartTrainer,
+                        fill: Some(GapFill::HoldLast),
+                    }),
+                    ..Default::default()
+                },
+                dropouts: vec![Field::Speed],
+                w_balance: Some(20000),
+            })
+            .collect();
+        session.metrics = Some(calculate_metrics(&session, 250, 70.0));
+        session.power_curve = Some(mean_max_power(&session.records));
+        session.aerobic = Some(analyze_aerobic(&session));
+        session.name = Some("Morning ride".to_string());
+        session.workout_id = Some("sweet-spot".to_string());
+        session.workout_version = Some("abc123".to_string());
+        session.rpe = Some(6);
+
+        session
+    }
+
+    fn user_version(repository: &SqliteRepository) -> usize {
+        repository
+            .with_connection(|connection| {
+                Ok(connection.query_row("PRAGMA user_version", [], |row| row.get(0))?)
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn migrates_an_empty_database_to_the_latest_version() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        let version: usize = connection
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, 0);
+
+        migrate(&mut connection).unwrap();
+        // Applied migrations are skipped
+        migrate(&mut connection).unwrap();
+
+        let repository = SqliteRepository {
+            connection: Mutex::new(connection),
+        };
+
+        assert_eq!(user_version(&repository), MIGRATIONS.len());
+        assert!(repository.insert_session("ride", &ride()).unwrap());
+    }
+
+    #[test]
+    fn round_trips_a_session() {
+        let repository = repository();
+        let session = ride();
+
+        assert!(repository.insert_session("ride", &session).unwrap());
+        // Existing sessions are kept
+        assert!(!repository.insert_session("ride", &Session::new()).unwrap());
+
+        let loaded = repository.load_session("ride").unwrap();
+
+        assert_eq!(
+            serde_json::to_value(&loaded).unwrap(),
+            serde_json::to_value(&session).unwrap()
+        );
+    }
+
+    #[test]
+    fn updates_and_deletes_a_session() {
+        let repository = repository();
+        repository.insert_session("ride", &ride()).unwrap();
+
+        repository
+            .annotate_session("ride", Some("Legs felt good"), Some(6))
+            .unwrap();
+        repository.rename_session("ride", Some("Tempo")).unwrap();
+
+        let loaded = repository.load_session("ride").unwrap();
+        assert_eq!(loaded.name.as_deref(), Some("Tempo"));
+        assert_eq!(loaded.notes.as_deref(), Some("Legs felt good"));
+        assert_eq!(loaded.rpe, Some(6));
+        assert_eq!(loaded.records.len(), 660);
+        assert_eq!(loaded.laps, vec![200, 400]);
+
+        repository.rename_session("ride", None).unwrap();
+        assert_eq!(repository.load_session("ride").unwrap().name, None);
+        assert!(repository.rename_session("missing", None).is_err());
+
+        repository.delete_session("ride").unwrap();
+
+        assert!(repository.load_session("ride").is_err());
+        assert!(repository.delete_session("ride").is_err());
+    }
+
+    #[test]
+    fn finds_a_session_by_its_start_second() {
+        let repository = repository();
+        let session = ride();
+        repository.insert_session("ride", &session).unwrap();
+
+        let start_time = Local.with_ymd_and_hms(2026, 3, 1, 7, 0, 0).unwrap();
+
+        assert_eq!(
+            repository.find_session_by_start_time(start_time).unwrap(),
+            Some("ride".to_string())
+        );
+        assert_eq!(
+            repository
+                .find_session_by_start_time(start_time + Duration::milliseconds(900))
+                .unwrap(),
+            Some("ride".to_string())
+        );
+        assert_eq!(
+            repository
+                .find_session_by_start_time(start_time + Duration::seconds(1))
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn lists_headers_without_records() {
+        let repository = repository();
+        repository.insert_session("ride", &ride()).unwrap();
+
+        let headers = repository.list_session_headers().unwrap();
+
+        assert_eq!(headers.len(), 1);
+
+        let (session_id, header) = &headers[0];
+
+        assert_eq!(session_id, "ride");
+        assert!(header.records.is_empty());
+        assert_eq!(header.metrics.as_ref().unwrap().duration, 660);
+        assert_eq!(header.power_curve.as_ref().unwrap()[0], 200);
+        assert!(header.aerobic.is_some());
+        assert_eq!(header.name.as_deref(), Some("Morning ride"));
+    }
+
+    #[test]
+    fn finds_the_best_power_of_all_sessions() {
+        let repository = repository();
+
+        assert!(repository.best_power_curve().unwrap().is_empty());
+
+        let mut first = ride();
+        first.power_curve = Some(vec![400, 300]);
+        let mut second = ride();
+        second.power_curve = Some(vec![350, 320, 250]);
+        let mut without = ride();
+        without.power_curve = None;
+
+        repository.insert_session("first", &first).unwrap();
+        repository.insert_session("second", &second).unwrap();
+        repository.insert_session("without", &without).unwrap();
+
+        assert_eq!(repository.best_power_curve().unwrap(), vec![400, 320, 250]);
+    }
+
+    #[test]
+    fn imports_json_sessions_until_all_were_read() {
+        let directory =
+            std::env::temp_dir().join(format!("cycling_trainer_sessions_{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+
+        let mut session = ride();
+        session.power_curve = None;
+        session.aerobic = None;
+
+        let write = |session_id: &str| {
+            fs::write(
+                directory.join(format!("{}.json", session_id)),
+                serde_json::to_string(&session).unwrap(),
+            )
+            .unwrap();
+        };
+
+        write("20260301070000");
+        fs::write(directory.join("broken.json"), "{").unwrap();
+
+        let repository = repository();
+        import_json_sessions(&repository, &directory).unwrap();
+
+        // Calculated for sessions saved without them
+        let imported = repository.load_session("20260301070000").unwrap();
+        assert!(imported.power_curve.is_some());
+        assert!(imported.aerobic.is_some());
+
+        // Tried again while a file can't be read
+        assert_eq!(repository.get_setting(JSON_IMPORTED_KEY).unwrap(), None);
+
+        fs::remove_file(directory.join("broken.json")).unwrap();
+        write("20260302070000");
+        import_json_sessions(&repository, &directory).unwrap();
+
+        assert_eq!(
+            repository
+                .get_setting(JSON_IMPORTED_KEY)
+                .unwrap()
+                .as_deref(),
+            Some("1")
+        );
+
+        // Files added afterwards are not imported
+        write("20260303070000");
+        import_json_sessions(&repository, &directory).unwrap();
+
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(repository.list_session_headers().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn imports_sessions_saved_before_the_timeline() {
+        let directory = std::env::temp_dir().join(format!(
+            "cycling_trainer_legacy_sessions_{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(
+            directory.join("20230501183000.json"),
+            include_str!("../../fixtures/legacy_session.json"),
+        )
+        .unwrap();
+
+        let repository = repository();
+        import_json_sessions(&repository, &directory).unwrap();
+
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert!(repository.get_setting(JSON_IMPORTED_KEY).unwrap().is_some());
+
+        let session = repository.load_session("20230501183000").unwrap();
+        let end_time = Local.with_ymd_and_hms(2023, 5, 1, 18, 30, 0).unwrap();
+
+        assert_eq!(session.status, SessionStatus::Finished);
+        assert_eq!(session.end_time, Some(end_time));
+        assert_eq!(session.start_time, Some(end_time - Duration::seconds(6)));
+        assert_eq!(session.total_distance, 50);
+        assert_eq!(session.records.len(), 6);
+
+        let power: Vec<u16> = session.records.iter().filter_map(|r| r.power).collect();
+        assert_eq!(power, vec![180, 185, 190, 195, 200, 205]);
+        assert_eq!(session.records[0].cadence, Some(85));
+        assert_eq!(session.records[0].speed, Some(30));
+        assert_eq!(
+            session.records[0].sources.power,
+            Some(Attribution {
+                source: SensorSource::SmartTrainer,
+                fill: None
+            })
+        );
+
+        // Heart rate stopped before the trainer did
+        assert_eq!(session.records[4].heart_rate, Some(124));
+        assert_eq!(session.records[5].heart_rate, None);
+        assert_eq!(session.records[5].sources.heart_rate, None);
+
+        assert_eq!(session.power_curve.as_ref().unwrap()[0], 205);
+        assert!(session.aerobic.is_some());
+    }
+}
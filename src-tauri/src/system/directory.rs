@@ -1,8 +1,14 @@
+use chrono::{DateTime, Local};
 use std::{fs, path::PathBuf};
 
-use crate::{data::session::Session, error::error_generic, prelude::*};
+use crate::{
+    data::session::Session,
+    error::error_generic,
+    prelude::*,
+    storage::{self, SessionRepository},
+};
 
-use log::{error, info};
+use log::{error, info, warn};
 
 use super::user::{User, UserSettings};
 
@@ -27,6 +33,12 @@ pub fn initialize() {
             // Sessions directory
             let _ = get_or_create_directory("sessions", &app_folder);
 
+            // Exported files directory
+            let _ = get_or_create_directory("exports", &app_folder);
+
+            // Journals of sessions that were not saved yet
+            let _ = get_or_create_directory("journal", &app_folder);
+
             // User settings
             let _ = get_or_create_file("user_settings.json", &app_folder);
         }
@@ -87,7 +99,7 @@ fn get_or_create_file(filename: &str, dir: &PathBuf) -> Result<PathBuf> {
     if !file.exists() {
         let default_user = User {
             username: "".to_string(),
-            settings: UserSettings { ftp: 0 },
+            settings: UserSettings::default(),
         };
 
         if let Err(err) = serde_json::to_writer_pretty(
@@ -106,36 +118,169 @@ fn get_or_create_file(filename: &str, dir: &PathBuf) -> Result<PathBuf> {
     Ok(file)
 }
 
-pub fn save_session(session: &Session, filename: String) -> Result<PathBuf> {
-    let path = match dirs::document_dir() {
-        Some(dir) => dir.join("Cycling Trainer").join("sessions"),
+pub fn get_sessions_directory() -> Result<PathBuf> {
+    match dirs::document_dir() {
+        Some(dir) => Ok(dir.join("Cycling Trainer").join("sessions")),
         None => {
             error!(
-                "{}:save_sessions: Unable to retrieve sessions directory.",
+                "{}:get_sessions_directory: Unable to retrieve sessions directory.",
                 LOGGER_NAME
             );
 
-            return Err(error_generic("Error retrieving session directory"));
+            Err(error_generic("Error retrieving session directory"))
         }
-    };
+    }
+}
 
-    let file = path.join(filename);
+pub fn get_exports_directory() -> Result<PathBuf> {
+    match dirs::document_dir() {
+        Some(dir) => Ok(dir.join("Cycling Trainer").join("exports")),
+        None => {
+            error!(
+                "{}:get_exports_directory: Unable to retrieve exports directory.",
+                LOGGER_NAME
+            );
+
+            Err(error_generic("Error retrieving exports directory"))
+        }
+    }
+}
+
+pub fn get_workouts_directory() -> Result<PathBuf> {
+    match dirs::document_dir() {
+        Some(dir) => Ok(dir.join("Cycling Trainer").join("workouts")),
+        None => {
+            error!(
+                "{}:get_workouts_directory: Unable to retrieve workouts directory.",
+                LOGGER_NAME
+            );
 
-    if file.exists() {
-        return Ok(file);
+            Err(error_generic("Error retrieving workouts directory"))
+        }
     }
+}
 
-    if let Err(err) = serde_json::to_writer_pretty(
-        fs::File::create(&file).expect("Error creating session file."),
-        session,
-    ) {
-        error!(
-            "{}:save_session: Error writing session file: {}",
-            LOGGER_NAME, err
-        );
+pub fn get_journal_directory() -> Result<PathBuf> {
+    match dirs::document_dir() {
+        Some(dir) => Ok(dir.join("Cycling Trainer").join("journal")),
+        None => {
+            error!(
+                "{}:get_journal_directory: Unable to retrieve journal directory.",
+                LOGGER_NAME
+            );
 
-        return Err(error_generic("Error writing session file"));
+            Err(error_generic("Error retrieving journal directory"))
+        }
     }
+}
+
+/// Writes an exported file, replacing an older export of the same name
+pub fn save_export(data: &[u8], filename: String) -> Result<PathBuf> {
+    let file = get_exports_directory()?.join(filename);
+
+    fs::write(&file, data)?;
 
     Ok(file)
 }
+
+/// Saves a new session, keeping the existing one if the id is taken
+pub fn save_session(session: &Session, session_id: &str) -> Result<()> {
+    storage::repository()?.insert_session(session_id, session)?;
+
+    Ok(())
+}
+
+/// Loads a saved session by its id
+pub fn load_session(session_id: &str) -> Result<Session> {
+    storage::repository()?.load_session(session_id)
+}
+
+pub fn rename_session(session_id: &str, name: Option<&str>) -> Result<()> {
+    storage::repository()?.rename_session(session_id, name)
+}
+
+pub fn annotate_session(session_id: &str, notes: Option<&str>, rpe: Option<u8>) -> Result<()> {
+    storage::repository()?.annotate_session(session_id, notes, rpe)
+}
+
+pub fn delete_session(session_id: &str) -> Result<()> {
+    storage::repository()?.delete_session(session_id)
+}
+
+/// Id of the saved session that started within the same second as `start_time`
+pub fn find_session_by_start_time(start_time: DateTime<Local>) -> Result<Option<String>> {
+    storage::repository()?.find_session_by_start_time(start_time)
+}
+
+/// Best power of all saved sessions for every duration, see `SessionRepository::best_power_curve`
+pub fn best_power_curve() -> Result<Vec<u16>> {
+    storage::repository()?.best_power_curve()
+}
+
+/// Saved sessions without their records.
+///
+/// Sessions that `is_complete` rejects are loaded in full, e.g. the ones
+/// saved before a value was calculated on save. Sessions that can't be read are skipped.
+pub fn list_session_headers(
+    is_complete: impl Fn(&Session) -> bool,
+) -> Result<Vec<(String, Session)>> {
+    list_repository_headers(storage::repository()?, is_complete)
+}
+
+/// Same as `list_session_headers`, for the sessions of `repository`
+pub fn list_repository_headers(
+    repository: &dyn SessionRepository,
+    is_complete: impl Fn(&Session) -> bool,
+) -> Result<Vec<(String, Session)>> {
+    let sessions = repository
+        .list_session_headers()?
+        .into_iter()
+        .filter_map(|(session_id, header)| {
+            if is_complete(&header) {
+                return Some((session_id, header));
+            }
+
+            match repository.load_session(&session_id) {
+                Ok(session) => Some((session_id, session)),
+                Err(err) => {
+                    warn!(
+                        "{}:list_session_headers: Skipping session {}: {}",
+                        LOGGER_NAME, session_id, err
+                    );
+
+                    None
+                }
+            }
+        })
+        .collect();
+
+    Ok(sessions)
+}
+
+pub fn get_database_file() -> Result<PathBuf> {
+    match dirs::document_dir() {
+        Some(dir) => Ok(dir.join("Cycling Trainer").join("cycling_trainer.db")),
+        None => {
+            error!(
+                "{}:get_database_file: Unable to retrieve root directory.",
+                LOGGER_NAME
+            );
+
+            Err(error_generic("Error retrieving database file"))
+        }
+    }
+}
+
+pub fn get_workout_library_file() -> Result<PathBuf> {
+    match dirs::document_dir() {
+        Some(dir) => Ok(dir.join("Cycling Trainer").join("workout_library.json")),
+        None => {
+            error!(
+                "{}:get_workout_library_file: Unable to retrieve root directory.",
+                LOGGER_NAME
+            );
+
+            Err(error_generic("Error retrieving workout library file"))
+        }
+    }
+}
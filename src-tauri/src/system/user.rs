@@ -3,6 +3,14 @@ use serde::{Deserialize, Serialize};
 use std::{fs, sync::OnceLock};
 use tokio::sync::RwLock;
 
+use crate::data::{
+    ftp::FtpHistoryEntry, physics::VirtualSpeedConfig, pipeline::PipelineConfig,
+    training_load::TrainingLoadConfig, w_balance::WBalanceConfig, zones::ZoneSettings,
+};
+
+use crate::error::error_generic;
+use crate::prelude::*;
+
 use super::directory::get_user_settings_file;
 
 pub static APP_USER: OnceLock<RwLock<User>> = OnceLock::new();
@@ -13,9 +21,33 @@ pub struct User {
     pub settings: UserSettings,
 }
 
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
 pub struct UserSettings {
-    pub ftp: u8,
+    pub ftp: u16,
+
+    // Kilograms
+    #[serde(default)]
+    pub weight: f64,
+
+    #[serde(default)]
+    pub recording: PipelineConfig,
+
+    #[serde(default)]
+    pub virtual_speed: VirtualSpeedConfig,
+
+    #[serde(default)]
+    pub zones: ZoneSettings,
+
+    #[serde(default)]
+    pub w_balance: WBalanceConfig,
+
+    #[serde(default)]
+    pub training_load: TrainingLoadConfig,
+
+    // Previous FTP values, oldest first
+    #[serde(default)]
+    pub ftp_history: Vec<FtpHistoryEntry>,
 }
 
 pub fn load_app_user() {
@@ -44,3 +76,24 @@ pub fn load_app_user() {
         warn!("Unable to load user settings.");
     }
 }
+
+pub async fn get_user_settings() -> UserSettings {
+    match APP_USER.get() {
+        Some(lock) => lock.read().await.settings.clone(),
+        None => UserSettings::default(),
+    }
+}
+
+/// Replaces the settings of the user and writes them to the settings file
+pub async fn save_user_settings(settings: UserSettings) -> Result<()> {
+    let Some(lock) = APP_USER.get() else {
+        return Err(error_generic("User settings are not loaded"));
+    };
+
+    let mut user = lock.write().await;
+    user.settings = settings;
+
+    let file = fs::File::create(get_user_settings_file()?)?;
+
+    serde_json::to_writer_pretty(file, &*user).map_err(|err| error_generic(&err.to_string()))
+}
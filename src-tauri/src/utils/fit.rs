@@ -0,0 +1,465 @@
+use chrono::{DateTime, Local, TimeZone};
+use std::collections::HashMap;
+
+use crate::error::error_generic;
+use crate::prelude::*;
+
+// Seconds between the unix epoch and the FIT epoch, 1989-12-31T00:00:00Z
+const FIT_EPOCH_OFFSET: i64 = 631065600;
+
+const HEADER_SIZE: u8 = 14;
+const PROTOCOL_VERSION: u8 = 0x20;
+const PROFILE_VERSION: u16 = 2132;
+
+const DEFINITION_HEADER: u8 = 0x40;
+const DEVELOPER_DATA_FLAG: u8 = 0x20;
+const COMPRESSED_TIMESTAMP_HEADER: u8 = 0x80;
+const LOCAL_TYPE_MASK: u8 = 0x0F;
+
+pub const TIMESTAMP_FIELD: u8 = 253;
+pub const MESSAGE_INDEX_FIELD: u8 = 254;
+
+const CRC_TABLE: [u16; 16] = [
+    0x0000, 0xCC01, 0xD801, 0x1400, 0xF001, 0x3C00, 0x2800, 0xE401, 0xA001, 0x6C00, 0x7800, 0xB401,
+    0x5000, 0x9C01, 0x8801, 0x4400,
+];
+
+mod base_type {
+    pub const ENUM: u8 = 0x00;
+    pub const SINT8: u8 = 0x01;
+    pub const UINT8: u8 = 0x02;
+    pub const SINT16: u8 = 0x83;
+    pub const UINT16: u8 = 0x84;
+    pub const SINT32: u8 = 0x85;
+    pub const UINT32: u8 = 0x86;
+    pub const STRING: u8 = 0x07;
+    pub const UINT8Z: u8 = 0x0A;
+    pub const UINT16Z: u8 = 0x8B;
+    pub const UINT32Z: u8 = 0x8C;
+    pub const BYTE: u8 = 0x0D;
+}
+
+/// Field value, `None` is written as the invalid value of the type
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Enum(Option<u8>),
+    UInt8(Option<u8>),
+    UInt16(Option<u16>),
+    UInt32(Option<u32>),
+    SInt8(Option<i8>),
+    SInt16(Option<i16>),
+    SInt32(Option<i32>),
+    // Null terminated, padded to the given size in bytes
+    String(String, u8),
+    // Arrays and types without a dedicated variant
+    Bytes(Vec<u8>),
+}
+
+impl Value {
+    fn base_type(&self) -> u8 {
+        match self {
+            Value::Enum(_) => base_type::ENUM,
+            Value::UInt8(_) => base_type::UINT8,
+            Value::UInt16(_) => base_type::UINT16,
+            Value::UInt32(_) => base_type::UINT32,
+            Value::SInt8(_) => base_type::SINT8,
+            Value::SInt16(_) => base_type::SINT16,
+            Value::SInt32(_) => base_type::SINT32,
+            Value::String(_, _) => base_type::STRING,
+            Value::Bytes(_) => base_type::BYTE,
+        }
+    }
+
+    fn size(&self) -> u8 {
+        match self {
+            Value::Enum(_) | Value::UInt8(_) | Value::SInt8(_) => 1,
+            Value::UInt16(_) | Value::SInt16(_) => 2,
+            Value::UInt32(_) | Value::SInt32(_) => 4,
+            Value::String(_, size) => *size,
+            Value::Bytes(bytes) => bytes.len() as u8,
+        }
+    }
+
+    fn write(&self, data: &mut Vec<u8>) {
+        match self {
+            Value::Enum(value) | Value::UInt8(value) => data.push(value.unwrap_or(u8::MAX)),
+            Value::UInt16(value) => data.extend(value.unwrap_or(u16::MAX).to_le_bytes()),
+            Value::UInt32(value) => data.extend(value.unwrap_or(u32::MAX).to_le_bytes()),
+            Value::SInt8(value) => data.extend(value.unwrap_or(i8::MAX).to_le_bytes()),
+            Value::SInt16(value) => data.extend(value.unwrap_or(i16::MAX).to_le_bytes()),
+            Value::SInt32(value) => data.extend(value.unwrap_or(i32::MAX).to_le_bytes()),
+            Value::String(value, size) => {
+                // Leave room for the null terminator
+                let mut bytes: Vec<u8> = value
+                    .bytes()
+                    .take((*size as usize).saturating_sub(1))
+                    .collect();
+
+                bytes.resize(*size as usize, 0);
+                data.extend(bytes);
+            }
+            Value::Bytes(bytes) => data.extend(bytes),
+        }
+    }
+
+    fn read(base_type: u8, bytes: &[u8], big_endian: bool) -> Self {
+        macro_rules! number {
+            ($type:ty, $variant:ident, $invalid:expr) => {{
+                let Ok(array) = bytes.try_into() else {
+                    return Value::Bytes(bytes.to_vec());
+                };
+
+                let value = if big_endian {
+                    <$type>::from_be_bytes(array)
+                } else {
+                    <$type>::from_le_bytes(array)
+                };
+
+                Value::$variant((value != $invalid).then_some(value))
+            }};
+        }
+
+        match base_type {
+            base_type::ENUM => number!(u8, Enum, u8::MAX),
+            base_type::UINT8 => number!(u8, UInt8, u8::MAX),
+            base_type::UINT8Z => number!(u8, UInt8, 0),
+            base_type::UINT16 => number!(u16, UInt16, u16::MAX),
+            base_type::UINT16Z => number!(u16, UInt16, 0),
+            base_type::UINT32 => number!(u32, UInt32, u32::MAX),
+            base_type::UINT32Z => number!(u32, UInt32, 0),
+            base_type::SINT8 => number!(i8, SInt8, i8::MAX),
+            base_type::SINT16 => number!(i16, SInt16, i16::MAX),
+            base_type::SINT32 => number!(i32, SInt32, i32::MAX),
+            base_type::STRING => {
+                let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+
+                Value::String(
+                    String::from_utf8_lossy(&bytes[..end]).to_string(),
+                    bytes.len() as u8,
+                )
+            }
+            _ => Value::Bytes(bytes.to_vec()),
+        }
+    }
+
+    /// Integer value, `None` for invalid values and non integer types
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Value::Enum(value) | Value::UInt8(value) => value.map(|v| v as i64),
+            Value::UInt16(value) => value.map(|v| v as i64),
+            Value::UInt32(value) => value.map(|v| v as i64),
+            Value::SInt8(value) => value.map(|v| v as i64),
+            Value::SInt16(value) => value.map(|v| v as i64),
+            Value::SInt32(value) => value.map(|v| v as i64),
+            Value::String(_, _) | Value::Bytes(_) => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Message {
+    pub global: u16,
+    pub fields: Vec<(u8, Value)>,
+}
+
+impl Message {
+    pub fn new(global: u16, fields: Vec<(u8, Value)>) -> Self {
+        Self { global, fields }
+    }
+
+    pub fn get(&self, field: u8) -> Option<&Value> {
+        self.fields
+            .iter()
+            .find(|(number, _)| *number == field)
+            .map(|(_, value)| value)
+    }
+
+    pub fn integer(&self, field: u8) -> Option<i64> {
+        self.get(field)?.as_i64()
+    }
+
+    pub fn string(&self, field: u8) -> Option<&str> {
+        match self.get(field)? {
+            Value::String(value, _) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn timestamp(&self) -> Option<DateTime<Local>> {
+        from_fit_timestamp(self.integer(TIMESTAMP_FIELD)? as u32)
+    }
+}
+
+#[derive(Clone, PartialEq)]
+struct Definition {
+    global: u16,
+    big_endian: bool,
+    // Field number, size and base type
+    fields: Vec<(u8, u8, u8)>,
+    // Sizes of the developer fields, which are skipped
+    developer_fields: Vec<u8>,
+}
+
+/// Builds a FIT file, writing a definition message whenever a message layout changes
+#[derive(Default)]
+pub struct FitWriter {
+    data: Vec<u8>,
+    definitions: HashMap<u8, Definition>,
+    local_types: Vec<u16>,
+}
+
+impl FitWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn write(&mut self, message: &Message) {
+        let local_type = self.local_type(message.global);
+
+        let definition = Definition {
+            global: message.global,
+            big_endian: false,
+            fields: message
+                .fields
+                .iter()
+                .map(|(number, value)| (*number, value.size(), value.base_type()))
+                .collect(),
+            developer_fields: Vec::new(),
+        };
+
+        if self.definitions.get(&local_type) != Some(&definition) {
+            self.data.push(DEFINITION_HEADER | local_type);
+            // Reserved, then little endian architecture
+            self.data.extend([0, 0]);
+            self.data.extend(definition.global.to_le_bytes());
+            self.data.push(definition.fields.len() as u8);
+
+            for (number, size, base_type) in definition.fields.iter() {
+                self.data.extend([*number, *size, *base_type]);
+            }
+
+            self.definitions.insert(local_type, definition);
+        }
+
+        self.data.push(local_type);
+
+        for (_, value) in message.fields.iter() {
+            value.write(&mut self.data);
+        }
+    }
+
+    /// Adds the file header and CRC
+    pub fn finish(self) -> Vec<u8> {
+        let mut file = Vec::with_capacity(self.data.len() + HEADER_SIZE as usize + 2);
+
+        file.push(HEADER_SIZE);
+        file.push(PROTOCOL_VERSION);
+        file.extend(PROFILE_VERSION.to_le_bytes());
+        file.extend((self.data.len() as u32).to_le_bytes());
+        file.extend(b".FIT");
+
+        let header_crc = crc(&file);
+        file.extend(header_crc.to_le_bytes());
+
+        file.extend(self.data);
+
+        let file_crc = crc(&file);
+        file.extend(file_crc.to_le_bytes());
+
+        file
+    }
+
+    // One local type per global message, reused round robin past the 16 available
+    fn local_type(&mut self, global: u16) -> u8 {
+        let index = match self.local_types.iter().position(|g| *g == global) {
+            Some(index) => index,
+            None => {
+                self.local_types.push(global);
+                self.local_types.len() - 1
+            }
+        };
+
+        (index % (LOCAL_TYPE_MASK as usize + 1)) as u8
+    }
+}
+
+/// Decodes all data messages of a FIT file, checking the header and CRC
+pub fn decode(bytes: &[u8]) -> Result<Vec<Message>> {
+    let header_size = *bytes.first().ok_or(error_generic("Empty FIT file"))? as usize;
+
+    if bytes.len() < header_size || header_size < 12 || &bytes[8..12] != b".FIT" {
+        return Err(error_generic("Invalid FIT file header"));
+    }
+
+    let data_size = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]) as usize;
+    let end = header_size + data_size;
+
+    if bytes.len() < end + 2 {
+        return Err(error_generic("Truncated FIT file"));
+    }
+
+    let expected_crc = u16::from_le_bytes([bytes[end], bytes[end + 1]]);
+
+    // A CRC of 0 means it was not calculated
+    if expected_crc != 0 && crc(&bytes[..end]) != expected_crc {
+        return Err(error_generic("Invalid FIT file CRC"));
+    }
+
+    let mut messages = Vec::new();
+    let mut definitions: HashMap<u8, Definition> = HashMap::new();
+    let mut last_timestamp: u32 = 0;
+    let mut position = header_size;
+
+    while position < end {
+        let header = bytes[position];
+        position += 1;
+
+        if header & COMPRESSED_TIMESTAMP_HEADER == 0 && header & DEFINITION_HEADER != 0 {
+            let definition = read_definition(bytes, &mut position, header, end)?;
+            definitions.insert(header & LOCAL_TYPE_MASK, definition);
+
+            continue;
+        }
+
+        // Compressed timestamp headers hold the local type in bits 5-6 and a time offset in bits 0-4
+        let (local_type, time_offset) = if header & COMPRESSED_TIMESTAMP_HEADER != 0 {
+            ((header >> 5) & 0x03, Some((header & 0x1F) as u32))
+        } else {
+            (header & LOCAL_TYPE_MASK, None)
+        };
+
+        let Some(definition) = definitions.get(&local_type) else {
+            return Err(error_generic("FIT data message without a definition"));
+        };
+
+        let mut fields = Vec::with_capacity(definition.fields.len() + 1);
+
+        for (number, size, base_type) in definition.fields.iter() {
+            let field_end = position + *size as usize;
+
+            if field_end > end {
+                return Err(error_generic("Truncated FIT data message"));
+            }
+
+            let value = Value::read(
+                *base_type,
+                &bytes[position..field_end],
+                definition.big_endian,
+            );
+            position = field_end;
+
+            if *number == TIMESTAMP_FIELD {
+                if let Some(timestamp) = value.as_i64() {
+                    last_timestamp = timestamp as u32;
+                }
+            }
+
+            fields.push((*number, value));
+        }
+
+        position += definition
+            .developer_fields
+            .iter()
+            .map(|size| *size as usize)
+            .sum::<usize>();
+
+        if let Some(offset) = time_offset {
+            let mut timestamp = (last_timestamp & !0x1F) + offset;
+
+            if offset < last_timestamp & 0x1F {
+                timestamp += 0x20;
+            }
+
+            last_timestamp = timestamp;
+            fields.push((TIMESTAMP_FIELD, Value::UInt32(Some(timestamp))));
+        }
+
+        messages.push(Message::new(definition.global, fields));
+    }
+
+    Ok(messages)
+}
+
+fn read_definition(
+    bytes: &[u8],
+    position: &mut usize,
+    header: u8,
+    end: usize,
+) -> Result<Definition> {
+    if *position + 5 > end {
+        return Err(error_generic("Truncated FIT definition message"));
+    }
+
+    let big_endian = bytes[*position + 1] == 1;
+    let global_bytes = [bytes[*position + 2], bytes[*position + 3]];
+    let global = if big_endian {
+        u16::from_be_bytes(global_bytes)
+    } else {
+        u16::from_le_bytes(global_bytes)
+    };
+
+    let field_count = bytes[*position + 4] as usize;
+    *position += 5;
+
+    if *position + field_count * 3 > end {
+        return Err(error_generic("Truncated FIT definition message"));
+    }
+
+    let fields = bytes[*position..*position + field_count * 3]
+        .chunks(3)
+        .map(|field| (field[0], field[1], field[2]))
+        .collect();
+
+    *position += field_count * 3;
+
+    let mut developer_fields = Vec::new();
+
+    if header & DEVELOPER_DATA_FLAG != 0 {
+        let count = *bytes
+            .get(*position)
+            .ok_or(error_generic("Truncated FIT file"))? as usize;
+        *position += 1;
+
+        if *position + count * 3 > end {
+            return Err(error_generic("Truncated FIT definition message"));
+        }
+
+        developer_fields = bytes[*position..*position + count * 3]
+            .chunks(3)
+            .map(|field| field[1])
+            .collect();
+
+        *position += count * 3;
+    }
+
+    Ok(Definition {
+        global,
+        big_endian,
+        fields,
+        developer_fields,
+    })
+}
+
+pub fn crc(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0, |crc, byte| {
+        let mut crc = crc;
+
+        let tmp = CRC_TABLE[(crc & 0xF) as usize];
+        crc = (crc >> 4) & 0x0FFF;
+        crc = crc ^ tmp ^ CRC_TABLE[(byte & 0xF) as usize];
+
+        let tmp = CRC_TABLE[(crc & 0xF) as usize];
+        crc = (crc >> 4) & 0x0FFF;
+        crc ^ tmp ^ CRC_TABLE[((byte >> 4) & 0xF) as usize]
+    })
+}
+
+pub fn to_fit_timestamp(date: DateTime<Local>) -> u32 {
+    (date.timestamp() - FIT_EPOCH_OFFSET).max(0) as u32
+}
+
+pub fn from_fit_timestamp(timestamp: u32) -> Option<DateTime<Local>> {
+    Local
+        .timestamp_opt(timestamp as i64 + FIT_EPOCH_OFFSET, 0)
+        .single()
+}
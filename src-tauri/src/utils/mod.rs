@@ -1,3 +1,4 @@
 pub mod bluetooth_utils;
 pub mod byte;
 pub mod code_values;
+pub mod fit;
@@ -1,113 +1,352 @@
 use log::warn;
-use serde::Serialize;
-use std::sync::OnceLock;
+use serde::{Deserialize, Serialize};
+use std::{path::PathBuf, sync::OnceLock};
 use tokio::sync::RwLock;
-use uuid::Uuid;
+
+use crate::system::user::get_user_settings;
 
 use super::{
+    library,
+    protocols::test_protocols,
     reader::get_workouts_from_file,
     zwo::{self, WorkoutFile},
 };
 
 pub static ACTIVITIES: OnceLock<RwLock<Vec<Activity>>> = OnceLock::new();
 
-#[derive(Serialize, Clone)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Activity {
+    #[serde(default)]
     pub id: String,
     pub name: String,
+    #[serde(default)]
     pub description: String,
+    #[serde(default)]
+    pub author: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
     pub workouts: Vec<Workout>,
+    // File the activity was loaded from, built-in workouts have none
+    #[serde(skip)]
+    pub path: Option<PathBuf>,
 }
 
-#[derive(Serialize, Clone)]
-#[serde(rename_all = "camelCase")]
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase", default)]
 pub struct Workout {
     pub workout_type: WorkoutType,
     pub duration: u16,
     pub cadence: u8,
+    // Target cadence range, 0 when the step has none
+    pub cadence_low: u8,
+    pub cadence_high: u8,
     pub power_low: f64,
     pub power_high: f64,
     pub power_steady: f64,
+    pub power_unit: PowerUnit,
+    // Free rides without the gradient of the course
+    pub flat_road: bool,
+    pub text_events: Vec<TextEvent>,
+}
+
+/// Unit of the power targets of a step
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq, Debug)]
+pub enum PowerUnit {
+    // Fraction of FTP
+    #[default]
+    Ftp,
+    // Absolute power, e.g. ERG files without an FTP
+    Watts,
+}
+
+impl Workout {
+    /// Step with its targets as fractions of `ftp`, `None` for watts without an FTP
+    pub fn relative_to_ftp(&self, ftp: u16) -> Option<Workout> {
+        let scale = match (self.power_unit, ftp) {
+            (PowerUnit::Ftp, _) => return Some(self.clone()),
+            (PowerUnit::Watts, 0) => return None,
+            (PowerUnit::Watts, ftp) => ftp as f64,
+        };
+
+        Some(Workout {
+            power_low: self.power_low / scale,
+            power_high: self.power_high / scale,
+            power_steady: self.power_steady / scale,
+            power_unit: PowerUnit::Ftp,
+            ..self.clone()
+        })
+    }
+}
+
+impl Activity {
+    /// Activity with all targets as fractions of `ftp`, `None` for watts without an FTP
+    pub fn relative_to_ftp(&self, ftp: u16) -> Option<Activity> {
+        let workouts = self
+            .workouts
+            .iter()
+            .map(|workout| workout.relative_to_ftp(ftp))
+            .collect::<Option<Vec<Workout>>>()?;
+
+        Some(Activity {
+            workouts,
+            ..self.clone()
+        })
+    }
+
+    pub fn has_watt_targets(&self) -> bool {
+        self.workouts
+            .iter()
+            .any(|workout| workout.power_unit == PowerUnit::Watts)
+    }
 }
 
-#[derive(Serialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, Default)]
 pub enum WorkoutType {
     Warmup,
+    #[default]
     SteadyState,
     Cooldown,
+    Ramp,
+    // No target power
+    FreeRide,
+    MaxEffort,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TextEvent {
+    // Seconds since the start of the step
+    pub offset: u16,
+    pub message: String,
 }
 
 impl From<WorkoutFile> for Activity {
     fn from(value: WorkoutFile) -> Self {
-        let workouts = value
-            .workout
-            .workouts
-            .iter()
-            .map(|workout| {
-                let workout_type = match workout {
-                    zwo::WorkoutType::Warmup { .. } => WorkoutType::Warmup,
-                    zwo::WorkoutType::SteadyState { .. } => WorkoutType::SteadyState,
-                    zwo::WorkoutType::Cooldown { .. } => WorkoutType::Cooldown,
-                };
-
-                let (duration, cadence, power_high, power_low, power_steady) = match workout {
-                    zwo::WorkoutType::Warmup {
-                        duration,
-                        power_low,
-                        power_high,
-                        cadence,
-                    }
-                    | zwo::WorkoutType::Cooldown {
-                        duration,
-                        power_low,
-                        power_high,
-                        cadence,
-                    } => (
-                        duration.to_owned(),
-                        cadence.to_owned(),
-                        power_high.to_owned(),
-                        power_low.to_owned(),
-                        0.0,
-                    ),
-                    zwo::WorkoutType::SteadyState {
-                        duration,
-                        power,
-                        cadence,
-                    } => (
-                        duration.to_owned(),
-                        cadence.to_owned(),
-                        0.0,
-                        0.0,
-                        power.to_owned(),
-                    ),
-                };
-
-                Workout {
-                    workout_type,
+        let mut workouts: Vec<Workout> = Vec::new();
+        // Messages outside of the steps, with their offset from the start of the workout
+        let mut workout_text_events: Vec<(u32, String)> = Vec::new();
+
+        for step in value.workout.workouts.iter() {
+            match step {
+                zwo::WorkoutType::Warmup {
+                    duration,
+                    power_low,
+                    power_high,
                     cadence,
+                    cadence_low,
+                    cadence_high,
+                    text_events,
+                }
+                | zwo::WorkoutType::Cooldown {
                     duration,
+                    power_low,
                     power_high,
+                    cadence,
+                    cadence_low,
+                    cadence_high,
+                    text_events,
+                }
+                | zwo::WorkoutType::Ramp {
+                    duration,
                     power_low,
-                    power_steady,
+                    power_high,
+                    cadence,
+                    cadence_low,
+                    cadence_high,
+                    text_events,
+                } => {
+                    let workout_type = match step {
+                        zwo::WorkoutType::Warmup { .. } => WorkoutType::Warmup,
+                        zwo::WorkoutType::Cooldown { .. } => WorkoutType::Cooldown,
+                        _ => WorkoutType::Ramp,
+                    };
+
+                    workouts.push(Workout {
+                        workout_type,
+                        duration: *duration,
+                        power_low: *power_low,
+                        power_high: *power_high,
+                        text_events: to_text_events(text_events),
+                        ..with_cadence(cadence, cadence_low, cadence_high)
+                    });
                 }
-            })
-            .collect();
+                zwo::WorkoutType::SteadyState {
+                    duration,
+                    power,
+                    cadence,
+                    cadence_low,
+                    cadence_high,
+                    text_events,
+                } => workouts.push(Workout {
+                    workout_type: WorkoutType::SteadyState,
+                    duration: *duration,
+                    power_steady: *power,
+                    text_events: to_text_events(text_events),
+                    ..with_cadence(cadence, cadence_low, cadence_high)
+                }),
+                zwo::WorkoutType::IntervalsT {
+                    repeat,
+                    on_duration,
+                    off_duration,
+                    on_power,
+                    off_power,
+                    cadence,
+                    cadence_low,
+                    cadence_high,
+                    cadence_resting,
+                    text_events,
+                } => {
+                    let interval = *on_duration as u32 + *off_duration as u32;
+
+                    for index in 0..*repeat as u32 {
+                        let on_start = index * interval;
+                        let off_start = on_start + *on_duration as u32;
+
+                        workouts.push(Workout {
+                            workout_type: WorkoutType::SteadyState,
+                            duration: *on_duration,
+                            power_steady: *on_power,
+                            text_events: text_events_between(text_events, on_start, off_start),
+                            ..with_cadence(cadence, cadence_low, cadence_high)
+                        });
+
+                        workouts.push(Workout {
+                            workout_type: WorkoutType::SteadyState,
+                            duration: *off_duration,
+                            cadence: cadence_resting.unwrap_or(0),
+                            power_steady: *off_power,
+                            text_events: text_events_between(
+                                text_events,
+                                off_start,
+                                off_start + *off_duration as u32,
+                            ),
+                            ..Default::default()
+                        });
+                    }
+                }
+                zwo::WorkoutType::FreeRide {
+                    duration,
+                    flat_road,
+                    cadence,
+                    cadence_low,
+                    cadence_high,
+                    text_events,
+                } => workouts.push(Workout {
+                    workout_type: WorkoutType::FreeRide,
+                    duration: *duration,
+                    flat_road: flat_road.is_some_and(|flat_road| flat_road > 0),
+                    text_events: to_text_events(text_events),
+                    ..with_cadence(cadence, cadence_low, cadence_high)
+                }),
+                zwo::WorkoutType::MaxEffort {
+                    duration,
+                    text_events,
+                } => workouts.push(Workout {
+                    workout_type: WorkoutType::MaxEffort,
+                    duration: *duration,
+                    text_events: to_text_events(text_events),
+                    ..Default::default()
+                }),
+                // Ridden like a free ride unless it has a target
+                zwo::WorkoutType::SolistPerformance {
+                    duration,
+                    power,
+                    cadence,
+                    cadence_low,
+                    cadence_high,
+                    text_events,
+                } => workouts.push(Workout {
+                    workout_type: match power {
+                        Some(_) => WorkoutType::SteadyState,
+                        None => WorkoutType::FreeRide,
+                    },
+                    duration: *duration,
+                    power_steady: power.unwrap_or(0.0),
+                    text_events: to_text_events(text_events),
+                    ..with_cadence(cadence, cadence_low, cadence_high)
+                }),
+                zwo::WorkoutType::TextEvent {
+                    time_offset,
+                    message,
+                } => workout_text_events.push((*time_offset as u32, message.clone())),
+            }
+        }
+
+        add_workout_text_events(&mut workouts, workout_text_events);
 
         Activity {
-            id: Uuid::new_v4().to_string(),
+            // Assigned by the library, see `library::assign_ids`
+            id: String::new(),
+            tags: value.tags(),
             name: value.name,
             description: value.description,
+            author: value.author,
             workouts,
+            path: None,
         }
     }
 }
 
-pub fn load_activities() {
-    let files = get_workouts_from_file();
-    let activities: Vec<Activity> = files
+// Target cadence of a step, either a single value or a range
+fn with_cadence(
+    cadence: &Option<u8>,
+    cadence_low: &Option<u8>,
+    cadence_high: &Option<u8>,
+) -> Workout {
+    Workout {
+        cadence: cadence.unwrap_or(0),
+        cadence_low: cadence_low.unwrap_or(0),
+        cadence_high: cadence_high.unwrap_or(0),
+        ..Default::default()
+    }
+}
+
+fn to_text_events(text_events: &[zwo::TextEvent]) -> Vec<TextEvent> {
+    text_events_between(text_events, 0, u32::MAX)
+}
+
+// Text events from `start` until `end`, relative to `start`
+fn text_events_between(text_events: &[zwo::TextEvent], start: u32, end: u32) -> Vec<TextEvent> {
+    text_events
         .iter()
-        .map(|file| Activity::from(file.to_owned()))
-        .collect();
+        .filter(|event| (start..end).contains(&(event.time_offset as u32)))
+        .map(|event| TextEvent {
+            offset: (event.time_offset as u32 - start) as u16,
+            message: event.message.clone(),
+        })
+        .collect()
+}
+
+// Moves the messages of the workout into the steps they are shown in
+pub fn add_workout_text_events(workouts: &mut [Workout], text_events: Vec<(u32, String)>) {
+    for (offset, message) in text_events {
+        let mut start = 0;
+
+        for workout in workouts.iter_mut() {
+            let end = start + workout.duration as u32;
+
+            if offset < end {
+                workout.text_events.push(TextEvent {
+                    offset: (offset - start) as u16,
+                    message,
+                });
+                workout.text_events.sort_by_key(|event| event.offset);
+
+                break;
+            }
+
+            start = end;
+        }
+    }
+}
+
+pub async fn load_activities() {
+    let ftp = get_user_settings().await.ftp;
+    let mut activities = get_workouts_from_file(ftp);
+
+    library::assign_ids(&mut activities);
+    activities.extend(test_protocols());
+    library::update_index(&activities, ftp);
 
     if let Err(_) = ACTIVITIES.set(RwLock::new(activities)) {
         warn!("Unable to load workouts.");
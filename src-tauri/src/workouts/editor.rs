@@ -0,0 +1,464 @@
+use log::info;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+use tokio::sync::RwLock;
+
+use crate::error::error_generic;
+use crate::prelude::*;
+use crate::system::{directory, user::get_user_settings};
+
+use super::{
+    activities::{Activity, WorkoutType, ACTIVITIES},
+    library::{file_id, update_index},
+    reader::file_name,
+    validation::{has_errors, WorkoutIssue},
+    zwo::workout_to_zwo,
+};
+
+const LOGGER_NAME: &str = "workouts::editor";
+
+/// Saves a new workout as a ZWO file in the workouts directory
+pub async fn create_workout(activity: Activity) -> Result<Activity> {
+    let ftp = get_user_settings().await.ftp;
+    let activity = relative_to_ftp(&activity, ftp)?;
+
+    check(&activity)?;
+
+    let path = new_file_path(&activity.name)?;
+
+    let activity = Activity {
+        id: file_id(&path),
+        path: Some(path),
+        ..activity
+    };
+
+    write(&activity)?;
+
+    let mut activities = activities()?.write().await;
+    activities.push(activity.clone());
+    update_index(&activities, ftp);
+
+    Ok(activity)
+}
+
+/// Replaces the steps and details of a workout, rewriting its file
+pub async fn update_workout(activity: Activity) -> Result<Activity> {
+    let ftp = get_user_settings().await.ftp;
+    let activity = relative_to_ftp(&activity, ftp)?;
+
+    check(&activity)?;
+
+    let mut activities = activities()?.write().await;
+
+    let Some(existing) = activities
+        .iter_mut()
+        .find(|existing| existing.id == activity.id)
+    else {
+        return Err(error_generic("Workout not found"));
+    };
+
+    let is_zwo = existing
+        .path
+        .as_ref()
+        .and_then(|path| path.extension())
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("zwo"));
+
+    if !is_zwo {
+        return Err(error_generic(
+            "Only ZWO workouts can be edited, duplicate it to edit a copy",
+        ));
+    }
+
+    let activity = Activity {
+        path: existing.path.clone(),
+        ..activity
+    };
+
+    write(&activity)?;
+    *existing = activity.clone();
+    update_index(&activities, ftp);
+
+    Ok(activity)
+}
+
+/// Saves a copy of any workout, including the built-in ones, as a new ZWO file
+pub async fn duplicate_workout(activity_id: &str) -> Result<Activity> {
+    let original = find(activity_id).await?;
+
+    create_workout(Activity {
+        name: format!("{} (copy)", original.name),
+        ..original
+    })
+    .await
+}
+
+/// Removes a workout and its file
+pub async fn delete_workout(activity_id: &str) -> Result<()> {
+    let ftp = get_user_settings().await.ftp;
+    let mut activities = activities()?.write().await;
+
+    let Some(index) = activities
+        .iter()
+        .position(|activity| activity.id == activity_id)
+    else {
+        return Err(error_generic("Workout not found"));
+    };
+
+    let Some(path) = &activities[index].path else {
+        return Err(error_generic("Built-in workouts can't be deleted"));
+    };
+
+    fs::remove_file(path)?;
+
+    info!("{}:delete_workout: Deleted {}", LOGGER_NAME, path.display());
+
+    activities.remove(index);
+    update_index(&activities, ftp);
+
+    Ok(())
+}
+
+/// Problems that keep a workout from being saved, with warnings for odd but valid steps
+pub fn validate(activity: &Activity) -> Vec<WorkoutIssue> {
+    let mut issues = Vec::new();
+
+    if activity.name.trim().is_empty() {
+        issues.push(WorkoutIssue::error("Workout has no name"));
+    }
+
+    if activity.workouts.is_empty() {
+        issues.push(WorkoutIssue::error("Workout has no steps"));
+    }
+
+    for (index, workout) in activity.workouts.iter().enumerate() {
+        let mut step_issues = Vec::new();
+
+        if workout.duration == 0 {
+            step_issues.push(WorkoutIssue::error("Duration is zero"));
+        }
+
+        let powers = match workout.workout_type {
+            WorkoutType::Warmup | WorkoutType::Cooldown | WorkoutType::Ramp => {
+                vec![workout.power_low, workout.power_high]
+            }
+            WorkoutType::SteadyState => vec![workout.power_steady],
+            WorkoutType::FreeRide | WorkoutType::MaxEffort => vec![],
+        };
+
+        if powers
+            .iter()
+            .any(|power| !power.is_finite() || *power < 0.0)
+        {
+            step_issues.push(WorkoutIssue::error("Power is not a positive number"));
+        }
+
+        if workout.cadence_low > workout.cadence_high {
+            step_issues.push(WorkoutIssue::error("Cadence range is reversed"));
+        }
+
+        if workout
+            .text_events
+            .iter()
+            .any(|event| event.offset >= workout.duration)
+        {
+            step_issues.push(WorkoutIssue::warning(
+                "Message is shown after the end of the step",
+            ));
+        }
+
+        let element = format!("workouts[{}]", index);
+
+        issues.extend(step_issues.into_iter().map(|issue| WorkoutIssue {
+            element: Some(element.clone()),
+            ..issue
+        }));
+    }
+
+    issues
+}
+
+// Fails with the errors found by `validate`
+fn check(activity: &Activity) -> Result<()> {
+    let issues = validate(activity);
+
+    if !has_errors(&issues) {
+        return Ok(());
+    }
+
+    let reasons: Vec<String> = issues
+        .iter()
+        .map(|issue| match &issue.element {
+            Some(element) => format!("{}: {}", element, issue.reason),
+            None => issue.reason.clone(),
+        })
+        .collect();
+
+    Err(error_generic(&format!(
+        "Workout is not valid. {}",
+        reasons.join("; ")
+    )))
+}
+
+// ZWO files only have targets relative to FTP
+fn relative_to_ftp(activity: &Activity, ftp: u16) -> Result<Activity> {
+    activity.relative_to_ftp(ftp).ok_or_else(|| {
+        error_generic("Targets in watts can't be saved without an FTP in the user settings")
+    })
+}
+
+fn write(activity: &Activity) -> Result<()> {
+    let Some(path) = &activity.path else {
+        return Err(error_generic("Workout has no file"));
+    };
+
+    fs::write(path, workout_to_zwo(activity))?;
+
+    info!("{}:write: Saved {}", LOGGER_NAME, path.display());
+
+    Ok(())
+}
+
+// File in the workouts directory that is not taken yet
+fn new_file_path(name: &str) -> Result<PathBuf> {
+    Ok(free_file_path(&directory::get_workouts_directory()?, name))
+}
+
+fn free_file_path(directory: &Path, name: &str) -> PathBuf {
+    let name = file_name(name.trim());
+
+    let mut path = directory.join(format!("{}.zwo", name));
+    let mut count = 1;
+
+    while path.exists() {
+        count += 1;
+        path = directory.join(format!("{} {}.zwo", name, count));
+    }
+
+    path
+}
+
+fn activities() -> Result<&'static RwLock<Vec<Activity>>> {
+    ACTIVITIES
+        .get()
+        .ok_or_else(|| error_generic("Activities are not loaded"))
+}
+
+async fn find(activity_id: &str) -> Result<Activity> {
+    activities()?
+        .read()
+        .await
+        .iter()
+        .find(|activity| activity.id == activity_id)
+        .cloned()
+        .ok_or_else(|| error_generic("Workout not found"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workouts::{
+        activities::{TextEvent, Workout},
+        validation::Severity,
+    };
+
+    fn step() -> Workout {
+        Workout {
+            workout_type: WorkoutType::SteadyState,
+            duration: 300,
+            power_steady: 0.75,
+            ..Default::default()
+        }
+    }
+
+    fn workout(id: &str, steps: Vec<Workout>) -> Activity {
+        Activity {
+            id: id.to_string(),
+            name: "Tempo".to_string(),
+            description: String::new(),
+            author: String::new(),
+            tags: Vec::new(),
+            workouts: steps,
+            path: None,
+        }
+    }
+
+    fn reasons(activity: &Activity) -> Vec<(Severity, Option<String>, String)> {
+        validate(activity)
+            .into_iter()
+            .map(|issue| (issue.severity, issue.element, issue.reason))
+            .collect()
+    }
+
+    fn step_error(reason: &str) -> Vec<(Severity, Option<String>, String)> {
+        vec![(
+            Severity::Error,
+            Some("workouts[0]".to_string()),
+            reason.to_string(),
+        )]
+    }
+
+    // Shared by the tests, as the activities can only be loaded once
+    async fn add_activity(activity: Activity) {
+        ACTIVITIES
+            .get_or_init(|| RwLock::new(Vec::new()))
+            .write()
+            .await
+            .push(activity);
+    }
+
+    #[test]
+    fn accepts_a_valid_workout() {
+        assert!(validate(&workout("tempo", vec![step()])).is_empty());
+    }
+
+    #[test]
+    fn requires_a_name_and_steps() {
+        let activity = Activity {
+            name: " ".to_string(),
+            ..workout("tempo", Vec::new())
+        };
+
+        assert_eq!(
+            reasons(&activity),
+            vec![
+                (Severity::Error, None, "Workout has no name".to_string()),
+                (Severity::Error, None, "Workout has no steps".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_steps_without_duration() {
+        let activity = workout(
+            "tempo",
+            vec![Workout {
+                duration: 0,
+                ..step()
+            }],
+        );
+
+        assert_eq!(reasons(&activity), step_error("Duration is zero"));
+    }
+
+    #[test]
+    fn rejects_negative_and_non_finite_power() {
+        for power in [-0.1, f64::NAN, f64::INFINITY] {
+            let activity = workout(
+                "tempo",
+                vec![Workout {
+                    power_steady: power,
+                    ..step()
+                }],
+            );
+
+            assert_eq!(
+                reasons(&activity),
+                step_error("Power is not a positive number")
+            );
+        }
+
+        // Only the targets used by the step type are checked
+        let ramp = workout(
+            "ramp",
+            vec![Workout {
+                workout_type: WorkoutType::Ramp,
+                power_low: 0.5,
+                power_high: -1.0,
+                ..step()
+            }],
+        );
+
+        assert_eq!(reasons(&ramp), step_error("Power is not a positive number"));
+    }
+
+    #[test]
+    fn rejects_a_reversed_cadence_range() {
+        let activity = workout(
+            "tempo",
+            vec![Workout {
+                cadence_low: 100,
+                cadence_high: 80,
+                ..step()
+            }],
+        );
+
+        assert_eq!(reasons(&activity), step_error("Cadence range is reversed"));
+    }
+
+    #[test]
+    fn warns_about_messages_after_the_end_of_the_step() {
+        let activity = workout(
+            "tempo",
+            vec![
+                step(),
+                Workout {
+                    text_events: vec![TextEvent {
+                        offset: 300,
+                        message: "Too late".to_string(),
+                    }],
+                    ..step()
+                },
+            ],
+        );
+
+        let issues = validate(&activity);
+
+        assert!(!has_errors(&issues));
+        assert_eq!(
+            reasons(&activity),
+            vec![(
+                Severity::Warning,
+                Some("workouts[1]".to_string()),
+                "Message is shown after the end of the step".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn numbers_files_with_the_same_name() {
+        let directory =
+            std::env::temp_dir().join(format!("cycling_trainer_editor_{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+
+        let first = free_file_path(&directory, " Sweet/spot ");
+        assert_eq!(first, directory.join("Sweet_spot.zwo"));
+
+        fs::write(&first, "").unwrap();
+        let second = free_file_path(&directory, "Sweet/spot");
+
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(second, directory.join("Sweet_spot 2.zwo"));
+    }
+
+    #[tokio::test]
+    async fn only_edits_zwo_files() {
+        add_activity(Activity {
+            path: Some(PathBuf::from("ramp.erg")),
+            ..workout("editor_erg", vec![step()])
+        })
+        .await;
+
+        let result = update_workout(workout("editor_erg", vec![step()])).await;
+
+        assert!(matches!(
+            result,
+            Err(Error::Generic(message))
+                if message == "Only ZWO workouts can be edited, duplicate it to edit a copy"
+        ));
+    }
+
+    #[tokio::test]
+    async fn keeps_built_in_workouts() {
+        add_activity(workout("editor_built_in", vec![step()])).await;
+
+        let result = delete_workout("editor_built_in").await;
+
+        assert!(matches!(
+            result,
+            Err(Error::Generic(message)) if message == "Built-in workouts can't be deleted"
+        ));
+    }
+}
@@ -0,0 +1,429 @@
+use std::{fs, path::Path};
+
+use super::{
+    activities::{add_workout_text_events, Activity, PowerUnit, Workout, WorkoutType},
+    validation::{has_errors, WorkoutIssue},
+};
+
+// Points closer than this are treated as the same power
+const POWER_TOLERANCE: f64 = 1e-6;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum TimeUnit {
+    Minutes,
+    Seconds,
+}
+
+#[derive(PartialEq)]
+enum Section {
+    None,
+    Header,
+    Data,
+    Text,
+}
+
+/// Contents of an ERG or MRC file.
+///
+/// ```text
+/// [COURSE HEADER]
+/// VERSION = 2
+/// UNITS = ENGLISH
+/// DESCRIPTION = Sweet spot
+/// FILE NAME = sweet_spot
+/// FTP = 250
+/// MINUTES WATTS
+/// [END COURSE HEADER]
+/// [COURSE DATA]
+/// 0.00    100
+/// 10.00   200
+/// 10.00   225
+/// 30.00   225
+/// [END COURSE DATA]
+/// [COURSE TEXT]
+/// 600     Settle in   10
+/// [END COURSE TEXT]
+/// ```
+///
+/// Power changes linearly between two points, so equal points give a steady
+/// state and different ones a ramp. Two points at the same time are a jump.
+pub struct Course {
+    pub name: String,
+    pub description: String,
+    pub ftp: Option<u16>,
+    // Power column, watts in `.erg` files and percent of FTP in `.mrc` files
+    pub power_unit: PowerUnit,
+    time_unit: TimeUnit,
+    // Time in the time unit and power in the power unit
+    points: Vec<(f64, f64)>,
+    // Seconds since the start of the workout
+    text_events: Vec<(u32, String)>,
+}
+
+/// Reads an ERG file
+pub fn erg_to_workout(file_path: &Path) -> (Option<Activity>, Vec<WorkoutIssue>) {
+    read_course(file_path, PowerUnit::Watts)
+}
+
+/// Reads an MRC file
+pub fn mrc_to_workout(file_path: &Path) -> (Option<Activity>, Vec<WorkoutIssue>) {
+    read_course(file_path, PowerUnit::Ftp)
+}
+
+fn read_course(file_path: &Path, power_unit: PowerUnit) -> (Option<Activity>, Vec<WorkoutIssue>) {
+    let text = match fs::read_to_string(file_path) {
+        Ok(text) => text,
+        Err(err) => return (None, vec![WorkoutIssue::error(&err.to_string())]),
+    };
+
+    let name = file_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let (course, mut issues) = parse(&text, &name, power_unit);
+
+    if has_errors(&issues) {
+        return (None, issues);
+    }
+
+    match course.into_activity() {
+        Ok(activity) => (Some(activity), issues),
+        Err(issue) => {
+            issues.push(issue);
+
+            (None, issues)
+        }
+    }
+}
+
+/// Parses the sections of a course, `power_unit` is used when the header has no column names
+pub fn parse(text: &str, name: &str, power_unit: PowerUnit) -> (Course, Vec<WorkoutIssue>) {
+    let mut course = Course {
+        name: name.to_string(),
+        description: String::new(),
+        ftp: None,
+        power_unit,
+        time_unit: TimeUnit::Minutes,
+        points: Vec::new(),
+        text_events: Vec::new(),
+    };
+
+    let mut issues = Vec::new();
+    let mut section = Section::None;
+
+    for (index, line) in text.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with(';') {
+            continue;
+        }
+
+        if line.starts_with('[') {
+            section = match line.to_uppercase().as_str() {
+                "[COURSE HEADER]" => Section::Header,
+                "[COURSE DATA]" => Section::Data,
+                "[COURSE TEXT]" => Section::Text,
+                "[END COURSE HEADER]" | "[END COURSE DATA]" | "[END COURSE TEXT]" => Section::None,
+                _ => {
+                    issues.push(
+                        WorkoutIssue::warning("Unknown section is ignored").at(line_number, line),
+                    );
+
+                    Section::None
+                }
+            };
+
+            continue;
+        }
+
+        let issue = match section {
+            Section::Header => course.parse_header(line),
+            Section::Data => course.parse_point(line),
+            Section::Text => course.parse_text_event(line),
+            Section::None => Some(WorkoutIssue::warning("Line outside of a section")),
+        };
+
+        if let Some(issue) = issue {
+            let element = match section {
+                Section::Header => "COURSE HEADER",
+                Section::Data => "COURSE DATA",
+                Section::Text => "COURSE TEXT",
+                Section::None => "",
+            };
+
+            issues.push(issue.at(line_number, element));
+        }
+    }
+
+    if course.points.len() < 2 {
+        issues.push(WorkoutIssue::error("Course data needs at least two points"));
+    }
+
+    (course, issues)
+}
+
+impl Course {
+    fn parse_header(&mut self, line: &str) -> Option<WorkoutIssue> {
+        let Some((key, value)) = line.split_once('=') else {
+            return self.parse_columns(line);
+        };
+
+        let value = value.trim();
+
+        match key.trim().to_uppercase().as_str() {
+            "DESCRIPTION" => self.description = value.to_string(),
+            "FILE NAME" => {
+                // Often the name of the original file
+                let name = value
+                    .rsplit_once('.')
+                    .map(|(name, _)| name)
+                    .unwrap_or(value);
+
+                if !name.is_empty() {
+                    self.name = name.to_string();
+                }
+            }
+            "FTP" => match value.parse::<u16>() {
+                Ok(ftp) if ftp > 0 => self.ftp = Some(ftp),
+                _ => return Some(WorkoutIssue::error("FTP is not a positive whole number")),
+            },
+            // Only used by distance based courses
+            "UNITS" if !["ENGLISH", "METRIC"].contains(&value.to_uppercase().as_str()) => {
+                return Some(WorkoutIssue::warning("UNITS is not ENGLISH or METRIC"));
+            }
+            _ => {}
+        }
+
+        None
+    }
+
+    // Names of the course data columns, e.g. `MINUTES WATTS`
+    fn parse_columns(&mut self, line: &str) -> Option<WorkoutIssue> {
+        let columns: Vec<String> = line.split_whitespace().map(str::to_uppercase).collect();
+
+        let [time, power] = columns.as_slice() else {
+            return Some(WorkoutIssue::warning("Unknown header line is ignored"));
+        };
+
+        self.time_unit = match time.as_str() {
+            "MINUTES" => TimeUnit::Minutes,
+            "SECONDS" => TimeUnit::Seconds,
+            _ => return Some(WorkoutIssue::error("Only time based courses are supported")),
+        };
+
+        self.power_unit = match power.as_str() {
+            "WATTS" => PowerUnit::Watts,
+            "PERCENT" => PowerUnit::Ftp,
+            _ => return Some(WorkoutIssue::error("Power column is not WATTS or PERCENT")),
+        };
+
+        None
+    }
+
+    fn parse_point(&mut self, line: &str) -> Option<WorkoutIssue> {
+        let values: Vec<Option<f64>> = line
+            .split_whitespace()
+            .take(2)
+            .map(|value| value.parse::<f64>().ok())
+            .collect();
+
+        let [Some(time), Some(power)] = values.as_slice() else {
+            return Some(WorkoutIssue::error("Point is not a time and a power"));
+        };
+
+        if *time < 0.0 || *power < 0.0 {
+            return Some(WorkoutIssue::error("Point has a negative value"));
+        }
+
+        if self.points.last().is_some_and(|(last, _)| time < last) {
+            return Some(WorkoutIssue::error("Point is before the previous one"));
+        }
+
+        self.points.push((*time, *power));
+
+        None
+    }
+
+    // `offset message [duration]`, offset in seconds
+    fn parse_text_event(&mut self, line: &str) -> Option<WorkoutIssue> {
+        let mut fields = line.split('\t').map(str::trim);
+
+        let offset = fields.next().and_then(|offset| offset.parse::<f64>().ok());
+        let message = fields.next().unwrap_or_default();
+
+        match offset {
+            Some(offset) if offset >= 0.0 && !message.is_empty() => {
+                self.text_events
+                    .push((offset.round() as u32, message.to_string()));
+
+                None
+            }
+            _ => Some(WorkoutIssue::warning(
+                "Text is not a time offset and a message, it is ignored",
+            )),
+        }
+    }
+
+    /// Steps between the points.
+    ///
+    /// Watts are scaled to the FTP of the course, courses without one keep them as watts.
+    pub fn into_activity(self) -> Result<Activity, WorkoutIssue> {
+        let (scale, power_unit) = match (self.power_unit, self.ftp) {
+            (PowerUnit::Ftp, _) => (100.0, PowerUnit::Ftp),
+            (PowerUnit::Watts, Some(ftp)) => (ftp as f64, PowerUnit::Ftp),
+            (PowerUnit::Watts, None) => (1.0, PowerUnit::Watts),
+        };
+
+        let seconds = |time: f64| match self.time_unit {
+            TimeUnit::Minutes => (time * 60.0).round() as u32,
+            TimeUnit::Seconds => time.round() as u32,
+        };
+
+        let mut workouts: Vec<Workout> = Vec::new();
+
+        for pair in self.points.windows(2) {
+            let [(start, power_start), (end, power_end)] = pair else {
+                continue;
+            };
+
+            let duration = seconds(*end) - seconds(*start);
+
+            if duration == 0 {
+                continue;
+            }
+
+            let (power_low, power_high) = (power_start / scale, power_end / scale);
+
+            workouts.push(if (power_low - power_high).abs() < POWER_TOLERANCE {
+                Workout {
+                    workout_type: WorkoutType::SteadyState,
+                    duration: duration.min(u16::MAX as u32) as u16,
+                    power_steady: power_low,
+                    power_unit,
+                    ..Default::default()
+                }
+            } else {
+                Workout {
+                    workout_type: WorkoutType::Ramp,
+                    duration: duration.min(u16::MAX as u32) as u16,
+                    power_low,
+                    power_high,
+                    power_unit,
+                    ..Default::default()
+                }
+            });
+        }
+
+        if workouts.is_empty() {
+            return Err(WorkoutIssue::error("Course data has no duration"));
+        }
+
+        add_workout_text_events(&mut workouts, self.text_events);
+
+        Ok(Activity {
+            id: String::new(),
+            name: self.name,
+            description: self.description,
+            author: String::new(),
+            tags: Vec::new(),
+            workouts,
+            path: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ERG: &str = "[COURSE HEADER]
+VERSION = 2
+UNITS = ENGLISH
+DESCRIPTION = Sweet spot
+FILE NAME = sweet_spot.erg
+FTP = 200
+MINUTES WATTS
+[END COURSE HEADER]
+[COURSE DATA]
+0.00\t100
+10.00\t180
+10.00\t180
+20.00\t180
+20.00\t100
+25.00\t100
+[END COURSE DATA]
+[COURSE TEXT]
+660\tHold it\t10
+[END COURSE TEXT]
+";
+
+    #[test]
+    fn reads_erg_steps() {
+        let (course, issues) = parse(ERG, "file", PowerUnit::Ftp);
+
+        assert!(issues.is_empty());
+        assert_eq!(course.power_unit, PowerUnit::Watts);
+
+        let activity = course.into_activity().unwrap();
+
+        assert_eq!(activity.name, "sweet_spot");
+        assert_eq!(activity.description, "Sweet spot");
+        assert_eq!(activity.workouts.len(), 3);
+
+        let ramp = &activity.workouts[0];
+        assert!(matches!(ramp.workout_type, WorkoutType::Ramp));
+        assert_eq!(ramp.duration, 600);
+        assert_eq!((ramp.power_low, ramp.power_high), (0.5, 0.9));
+        assert_eq!(ramp.power_unit, PowerUnit::Ftp);
+
+        let steady = &activity.workouts[1];
+        assert!(matches!(steady.workout_type, WorkoutType::SteadyState));
+        assert_eq!(steady.power_steady, 0.9);
+        assert_eq!(steady.text_events[0].offset, 60);
+
+        assert_eq!(activity.workouts[2].duration, 300);
+    }
+
+    #[test]
+    fn reads_mrc_percent() {
+        let mrc = "[COURSE HEADER]\nMINUTES PERCENT\n[END COURSE HEADER]\n\
+            [COURSE DATA]\n0\t50\n5\t50\n5\t110\n6.5\t110\n[END COURSE DATA]\n";
+
+        let (course, issues) = parse(mrc, "vo2", PowerUnit::Ftp);
+        assert!(issues.is_empty());
+
+        let activity = course.into_activity().unwrap();
+
+        assert_eq!(activity.name, "vo2");
+        assert_eq!(activity.workouts.len(), 2);
+        assert_eq!(activity.workouts[1].duration, 90);
+        assert_eq!(activity.workouts[1].power_steady, 1.1);
+    }
+
+    #[test]
+    fn keeps_watts_without_an_ftp() {
+        let erg = ERG.replace("FTP = 200\n", "");
+        let (course, _) = parse(&erg, "file", PowerUnit::Watts);
+
+        let activity = course.into_activity().unwrap();
+
+        let ramp = &activity.workouts[0];
+        assert_eq!(ramp.power_unit, PowerUnit::Watts);
+        assert_eq!((ramp.power_low, ramp.power_high), (100.0, 180.0));
+
+        let relative = activity.relative_to_ftp(200).unwrap();
+        assert_eq!(relative.workouts[1].power_steady, 0.9);
+        assert!(activity.relative_to_ftp(0).is_none());
+    }
+
+    #[test]
+    fn reports_broken_points() {
+        let erg = ERG.replace("20.00\t100", "15.00\tabc");
+        let (_, issues) = parse(&erg, "file", PowerUnit::Watts);
+
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].line, Some(14));
+        assert!(has_errors(&issues));
+    }
+}
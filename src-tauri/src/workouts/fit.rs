@@ -0,0 +1,688 @@
+use chrono::Local;
+use std::{fs, path::Path, path::PathBuf};
+
+use crate::error::error_generic;
+use crate::export::fit::FILE_ID;
+use crate::prelude::*;
+use crate::system::directory;
+use crate::utils::fit::{decode, to_fit_timestamp, FitWriter, Message, Value, MESSAGE_INDEX_FIELD};
+
+use super::{
+    activities::{Activity, PowerUnit, TextEvent, Workout, WorkoutType, ACTIVITIES},
+    reader::file_name,
+    validation::{has_errors, WorkoutIssue},
+};
+
+// Global message numbers
+pub const WORKOUT: u16 = 26;
+pub const WORKOUT_STEP: u16 = 27;
+
+// Profile values
+const FILE_TYPE_WORKOUT: u8 = 5;
+const MANUFACTURER_DEVELOPMENT: u16 = 255;
+const SPORT_CYCLING: u8 = 2;
+
+const DURATION_TIME: u8 = 0;
+const DURATION_OPEN: u8 = 5;
+const DURATION_REPEAT_UNTIL_STEPS_COMPLETE: u8 = 6;
+
+const TARGET_OPEN: u8 = 2;
+const TARGET_CADENCE: u8 = 3;
+const TARGET_POWER: u8 = 4;
+
+const INTENSITY_ACTIVE: u8 = 0;
+const INTENSITY_REST: u8 = 1;
+const INTENSITY_WARMUP: u8 = 2;
+const INTENSITY_COOLDOWN: u8 = 3;
+
+// Custom power targets up to this value are percent of FTP, above it watts plus this offset
+const POWER_WATTS_OFFSET: u32 = 1000;
+
+// Steady states below this are written as rest steps, as a fraction of FTP
+const REST_POWER: f64 = 0.6;
+
+// Power zone targets, as fractions of FTP
+const POWER_ZONES: [(f64, f64); 7] = [
+    (0.45, 0.55),
+    (0.56, 0.75),
+    (0.76, 0.90),
+    (0.91, 1.05),
+    (1.06, 1.20),
+    (1.21, 1.50),
+    (1.51, 2.00),
+];
+
+const WORKOUT_NAME_SIZE: u8 = 32;
+const NOTES_SIZE: u8 = 64;
+
+// Limits of the expanded repeats, so a broken file can't take all the memory
+const MAX_REPEAT_COUNT: usize = 100;
+const MAX_STEPS: usize = 1000;
+// Seconds
+const MAX_DURATION: u32 = 24 * 3600;
+
+/// Step of a FIT workout, both targets are optional
+struct FitStep {
+    duration_type: Option<u8>,
+    duration_value: Option<u32>,
+    target_type: Option<u8>,
+    target_value: Option<u32>,
+    target_low: Option<u32>,
+    target_high: Option<u32>,
+    secondary_target_type: Option<u8>,
+    secondary_target_low: Option<u32>,
+    secondary_target_high: Option<u32>,
+    intensity: Option<u8>,
+    name: Option<String>,
+    notes: Option<String>,
+}
+
+impl From<&Message> for FitStep {
+    fn from(message: &Message) -> Self {
+        let integer = |field: u8| message.integer(field);
+        let string = |field: u8| match message.get(field) {
+            Some(Value::String(value, _)) if !value.is_empty() => Some(value.clone()),
+            _ => None,
+        };
+
+        FitStep {
+            duration_type: integer(1).map(|v| v as u8),
+            duration_value: integer(2).map(|v| v as u32),
+            target_type: integer(3).map(|v| v as u8),
+            target_value: integer(4).map(|v| v as u32),
+            target_low: integer(5).map(|v| v as u32),
+            target_high: integer(6).map(|v| v as u32),
+            secondary_target_type: integer(19).map(|v| v as u8),
+            secondary_target_low: integer(21).map(|v| v as u32),
+            secondary_target_high: integer(22).map(|v| v as u32),
+            intensity: integer(7).map(|v| v as u8),
+            name: string(0),
+            notes: string(8),
+        }
+    }
+}
+
+/// Reads a FIT workout file
+pub fn fit_to_workout(file_path: &Path) -> (Option<Activity>, Vec<WorkoutIssue>) {
+    let messages = match fs::read(file_path)
+        .map_err(|err| err.into())
+        .and_then(|bytes| decode(&bytes))
+    {
+        Ok(messages) => messages,
+        Err(err) => return (None, vec![WorkoutIssue::error(&err.to_string())]),
+    };
+
+    let name = file_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    decode_workout(&messages, &name)
+}
+
+/// Steps of the workout and step messages, repeat steps are expanded
+pub fn decode_workout(
+    messages: &[Message],
+    default_name: &str,
+) -> (Option<Activity>, Vec<WorkoutIssue>) {
+    let file_type = messages
+        .iter()
+        .find(|message| message.global == FILE_ID)
+        .and_then(|message| message.integer(0));
+
+    if file_type != Some(FILE_TYPE_WORKOUT as i64) {
+        return (None, vec![WorkoutIssue::error("FIT file is not a workout")]);
+    }
+
+    let name = messages
+        .iter()
+        .find(|message| message.global == WORKOUT)
+        .and_then(|message| match message.get(8) {
+            Some(Value::String(name, _)) if !name.is_empty() => Some(name.clone()),
+            _ => None,
+        })
+        .unwrap_or(default_name.to_string());
+
+    let mut issues = Vec::new();
+    let mut workouts: Vec<Workout> = Vec::new();
+    // Index of the first workout of each FIT step
+    let mut step_starts: Vec<usize> = Vec::new();
+
+    let steps = messages
+        .iter()
+        .filter(|message| message.global == WORKOUT_STEP);
+
+    for (index, message) in steps.enumerate() {
+        let step = FitStep::from(message);
+        let element = format!("workout_step[{}]", index);
+
+        step_starts.push(workouts.len());
+
+        if step.duration_type == Some(DURATION_REPEAT_UNTIL_STEPS_COMPLETE) {
+            let from = step.duration_value.unwrap_or(0) as usize;
+            let count = step.target_value.unwrap_or(1) as usize;
+
+            let Some(start) = step_starts.get(from).copied().filter(|_| from < index) else {
+                issues.push(WorkoutIssue {
+                    element: Some(element),
+                    ..WorkoutIssue::warning("Repeat of a later step is ignored")
+                });
+                continue;
+            };
+
+            let repeated = workouts[start..].to_vec();
+            let total = workouts.len() + repeated.len().saturating_mul(count.saturating_sub(1));
+
+            if count > MAX_REPEAT_COUNT || total > MAX_STEPS {
+                issues.push(WorkoutIssue {
+                    element: Some(element),
+                    ..WorkoutIssue::error(&format!(
+                        "Repeat is over the limit of {} repeats or {} steps",
+                        MAX_REPEAT_COUNT, MAX_STEPS
+                    ))
+                });
+                break;
+            }
+
+            // The steps were already ridden once
+            for _ in 1..count {
+                workouts.extend(repeated.iter().cloned());
+            }
+
+            continue;
+        }
+
+        match to_workout(&step) {
+            Ok(workout) => workouts.push(workout),
+            Err(issue) => issues.push(WorkoutIssue {
+                element: Some(element),
+                ..issue
+            }),
+        }
+    }
+
+    let duration: u32 = workouts.iter().map(|workout| workout.duration as u32).sum();
+
+    if workouts.is_empty() {
+        issues.push(WorkoutIssue::error("Workout has no steps"));
+    } else if workouts.len() > MAX_STEPS || duration > MAX_DURATION {
+        issues.push(WorkoutIssue::error(&format!(
+            "Workout is over the limit of {} steps or {} hours",
+            MAX_STEPS,
+            MAX_DURATION / 3600
+        )));
+    }
+
+    if has_errors(&issues) {
+        return (None, issues);
+    }
+
+    let activity = Activity {
+        id: String::new(),
+        name,
+        description: String::new(),
+        author: String::new(),
+        tags: Vec::new(),
+        workouts,
+        path: None,
+    };
+
+    (Some(activity), issues)
+}
+
+fn to_workout(step: &FitStep) -> std::result::Result<Workout, WorkoutIssue> {
+    let duration = match step.duration_type {
+        Some(DURATION_TIME) => {
+            let seconds = step.duration_value.unwrap_or(0) as f64 / 1000.0;
+
+            seconds.round().min(u16::MAX as f64) as u16
+        }
+        Some(DURATION_OPEN) => {
+            return Err(WorkoutIssue::warning(
+                "Steps ended with the lap button are not supported, the step is skipped",
+            ))
+        }
+        _ => {
+            return Err(WorkoutIssue::warning(
+                "Only time based steps are supported, the step is skipped",
+            ))
+        }
+    };
+
+    let mut workout = Workout {
+        workout_type: WorkoutType::FreeRide,
+        duration,
+        ..Default::default()
+    };
+
+    match step.target_type {
+        Some(TARGET_POWER) => {
+            let (low, high, power_unit) = power_target(step)?;
+
+            workout.power_unit = power_unit;
+
+            workout.workout_type = match step.intensity {
+                Some(INTENSITY_WARMUP) if low != high => WorkoutType::Warmup,
+                Some(INTENSITY_COOLDOWN) if low != high => WorkoutType::Cooldown,
+                _ => WorkoutType::SteadyState,
+            };
+
+            match workout.workout_type {
+                WorkoutType::Warmup => (workout.power_low, workout.power_high) = (low, high),
+                // Cooldowns go down from the high end of the range
+                WorkoutType::Cooldown => (workout.power_low, workout.power_high) = (high, low),
+                _ => workout.power_steady = (low + high) / 2.0,
+            }
+        }
+        Some(TARGET_CADENCE) => {
+            (workout.cadence_low, workout.cadence_high) =
+                cadence_target(step.target_low, step.target_high);
+        }
+        _ => {}
+    }
+
+    if step.secondary_target_type == Some(TARGET_CADENCE) {
+        (workout.cadence_low, workout.cadence_high) =
+            cadence_target(step.secondary_target_low, step.secondary_target_high);
+    }
+
+    // A single cadence instead of a range
+    if workout.cadence_low > 0 && workout.cadence_low == workout.cadence_high {
+        workout.cadence = workout.cadence_low;
+        (workout.cadence_low, workout.cadence_high) = (0, 0);
+    }
+
+    let messages = step.name.iter().chain(step.notes.iter());
+
+    workout.text_events = messages
+        .map(|message| TextEvent {
+            offset: 0,
+            message: message.clone(),
+        })
+        .collect();
+
+    Ok(workout)
+}
+
+// Range of the power target, in watts or as fractions of FTP
+fn power_target(step: &FitStep) -> std::result::Result<(f64, f64, PowerUnit), WorkoutIssue> {
+    let (Some(low), Some(high)) = (step.target_low, step.target_high) else {
+        let zone = step.target_value.unwrap_or(0) as usize;
+
+        return POWER_ZONES
+            .get(zone.wrapping_sub(1))
+            .map(|(low, high)| (*low, *high, PowerUnit::Ftp))
+            .ok_or_else(|| WorkoutIssue::warning("Power target has no range or zone"));
+    };
+
+    let (low, high, power_unit) = match (low > POWER_WATTS_OFFSET, high > POWER_WATTS_OFFSET) {
+        (true, true) => (
+            (low - POWER_WATTS_OFFSET) as f64,
+            (high - POWER_WATTS_OFFSET) as f64,
+            PowerUnit::Watts,
+        ),
+        (false, false) => (low as f64 / 100.0, high as f64 / 100.0, PowerUnit::Ftp),
+        _ => {
+            return Err(WorkoutIssue::error(
+                "Power target mixes watts and percent of FTP",
+            ))
+        }
+    };
+
+    Ok((low.min(high), low.max(high), power_unit))
+}
+
+fn cadence_target(low: Option<u32>, high: Option<u32>) -> (u8, u8) {
+    let rpm = |value: Option<u32>| value.unwrap_or(0).min(u8::MAX as u32 - 1) as u8;
+
+    (rpm(low), rpm(high))
+}
+
+/// Encodes an activity as a FIT workout file.
+///
+/// Repeated pairs of steps, e.g. intervals, are written once with a repeat step.
+/// Ramps become a power range, as FIT workouts have no ramps.
+pub fn encode_workout(activity: &Activity) -> Vec<u8> {
+    let mut steps: Vec<Message> = Vec::new();
+    let workouts = &activity.workouts;
+    let mut index = 0;
+
+    while index < workouts.len() {
+        let repeat = repeat_count(&workouts[index..]);
+
+        if repeat > 1 {
+            let from = steps.len() as u16;
+
+            steps.push(step_message(&workouts[index]));
+            steps.push(step_message(&workouts[index + 1]));
+            steps.push(Message::new(
+                WORKOUT_STEP,
+                vec![
+                    (1, Value::Enum(Some(DURATION_REPEAT_UNTIL_STEPS_COMPLETE))),
+                    (2, Value::UInt32(Some(from as u32))),
+                    (4, Value::UInt32(Some(repeat as u32))),
+                ],
+            ));
+
+            index += repeat * 2;
+        } else {
+            steps.push(step_message(&workouts[index]));
+            index += 1;
+        }
+    }
+
+    let mut writer = FitWriter::new();
+
+    writer.write(&Message::new(
+        FILE_ID,
+        vec![
+            (0, Value::Enum(Some(FILE_TYPE_WORKOUT))),
+            (1, Value::UInt16(Some(MANUFACTURER_DEVELOPMENT))),
+            (2, Value::UInt16(Some(0))),
+            (4, Value::UInt32(Some(to_fit_timestamp(Local::now())))),
+        ],
+    ));
+
+    writer.write(&Message::new(
+        WORKOUT,
+        vec![
+            (4, Value::Enum(Some(SPORT_CYCLING))),
+            (6, Value::UInt16(Some(steps.len() as u16))),
+            (8, Value::String(activity.name.clone(), WORKOUT_NAME_SIZE)),
+        ],
+    ));
+
+    for (index, mut step) in steps.into_iter().enumerate() {
+        step.fields
+            .insert(0, (MESSAGE_INDEX_FIELD, Value::UInt16(Some(index as u16))));
+
+        writer.write(&step);
+    }
+
+    writer.finish()
+}
+
+/// Exports a loaded activity into the exports directory
+pub async fn export_workout(activity_id: &str) -> Result<PathBuf> {
+    let Some(lock) = ACTIVITIES.get() else {
+        return Err(error_generic("Activities are not loaded"));
+    };
+
+    let activities = lock.read().await;
+
+    let Some(activity) = activities
+        .iter()
+        .find(|activity| activity.id == activity_id)
+    else {
+        return Err(error_generic("Activity not found"));
+    };
+
+    directory::save_export(
+        &encode_workout(activity),
+        format!("{}.fit", file_name(&activity.name)),
+    )
+}
+
+// Times the first two steps are repeated back to back
+fn repeat_count(workouts: &[Workout]) -> usize {
+    let [on, off, ..] = workouts else {
+        return 0;
+    };
+
+    workouts
+        .chunks_exact(2)
+        .take_while(|pair| same_step(&pair[0], on) && same_step(&pair[1], off))
+        .count()
+}
+
+// Steps without messages, which would be shown once per repeat
+fn same_step(a: &Workout, b: &Workout) -> bool {
+    a.text_events.is_empty()
+        && b.text_events.is_empty()
+        && std::mem::discriminant(&a.workout_type) == std::mem::discriminant(&b.workout_type)
+        && a.duration == b.duration
+        && (a.cadence, a.cadence_low, a.cadence_high) == (b.cadence, b.cadence_low, b.cadence_high)
+        && (a.power_low, a.power_high, a.power_steady)
+            == (b.power_low, b.power_high, b.power_steady)
+        && a.power_unit == b.power_unit
+        && a.flat_road == b.flat_road
+}
+
+fn step_message(workout: &Workout) -> Message {
+    let target = |power: f64| {
+        let value = match workout.power_unit {
+            PowerUnit::Ftp => power * 100.0,
+            PowerUnit::Watts => power + POWER_WATTS_OFFSET as f64,
+        };
+
+        Value::UInt32(Some(value.round().max(0.0) as u32))
+    };
+
+    let (intensity, power) = match workout.workout_type {
+        WorkoutType::Warmup => (
+            INTENSITY_WARMUP,
+            Some((workout.power_low, workout.power_high)),
+        ),
+        WorkoutType::Cooldown => (
+            INTENSITY_COOLDOWN,
+            Some((workout.power_high, workout.power_low)),
+        ),
+        WorkoutType::Ramp => (
+            INTENSITY_ACTIVE,
+            Some((
+                workout.power_low.min(workout.power_high),
+                workout.power_low.max(workout.power_high),
+            )),
+        ),
+        WorkoutType::SteadyState
+            if workout.power_unit == PowerUnit::Ftp && workout.power_steady < REST_POWER =>
+        {
+            (
+                INTENSITY_REST,
+                Some((workout.power_steady, workout.power_steady)),
+            )
+        }
+        WorkoutType::SteadyState => (
+            INTENSITY_ACTIVE,
+            Some((workout.power_steady, workout.power_steady)),
+        ),
+        WorkoutType::FreeRide | WorkoutType::MaxEffort => (INTENSITY_ACTIVE, None),
+    };
+
+    let mut fields = vec![
+        (1, Value::Enum(Some(DURATION_TIME))),
+        (2, Value::UInt32(Some(workout.duration as u32 * 1000))),
+        (7, Value::Enum(Some(intensity))),
+    ];
+
+    match power {
+        Some((low, high)) => fields.extend([
+            (3, Value::Enum(Some(TARGET_POWER))),
+            (4, Value::UInt32(Some(0))),
+            (5, target(low)),
+            (6, target(high)),
+        ]),
+        None => fields.push((3, Value::Enum(Some(TARGET_OPEN)))),
+    }
+
+    let cadence = match (workout.cadence, workout.cadence_low, workout.cadence_high) {
+        (_, low, high) if low > 0 && high > 0 => Some((low, high)),
+        (cadence, _, _) if cadence > 0 => Some((cadence, cadence)),
+        _ => None,
+    };
+
+    if let Some((low, high)) = cadence {
+        fields.extend([
+            (19, Value::Enum(Some(TARGET_CADENCE))),
+            (20, Value::UInt32(Some(0))),
+            (21, Value::UInt32(Some(low as u32))),
+            (22, Value::UInt32(Some(high as u32))),
+        ]);
+    }
+
+    if !workout.text_events.is_empty() {
+        let notes: Vec<&str> = workout
+            .text_events
+            .iter()
+            .map(|event| event.message.as_str())
+            .collect();
+
+        fields.push((8, Value::String(notes.join(" / "), NOTES_SIZE)));
+    }
+
+    Message::new(WORKOUT_STEP, fields)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::fit::crc;
+
+    fn steady(duration: u16, power: f64) -> Workout {
+        Workout {
+            workout_type: WorkoutType::SteadyState,
+            duration,
+            power_steady: power,
+            ..Default::default()
+        }
+    }
+
+    fn activity() -> Activity {
+        let mut workouts = vec![Workout {
+            workout_type: WorkoutType::Warmup,
+            duration: 600,
+            power_low: 0.4,
+            power_high: 0.75,
+            cadence: 90,
+            ..Default::default()
+        }];
+
+        for _ in 0..5 {
+            workouts.push(steady(60, 1.2));
+            workouts.push(steady(120, 0.5));
+        }
+
+        workouts.push(Workout {
+            workout_type: WorkoutType::Cooldown,
+            duration: 300,
+            power_low: 0.6,
+            power_high: 0.4,
+            cadence_low: 80,
+            cadence_high: 95,
+            ..Default::default()
+        });
+
+        Activity {
+            id: "id".to_string(),
+            name: "5x1".to_string(),
+            description: String::new(),
+            author: String::new(),
+            tags: Vec::new(),
+            workouts,
+            path: None,
+        }
+    }
+
+    fn steps(messages: &[Message]) -> Vec<&Message> {
+        messages
+            .iter()
+            .filter(|message| message.global == WORKOUT_STEP)
+            .collect()
+    }
+
+    #[test]
+    fn encodes_repeats() {
+        let bytes = encode_workout(&activity());
+
+        assert_eq!(crc(&bytes), 0);
+
+        let messages = decode(&bytes).unwrap();
+        let steps = steps(&messages);
+
+        assert_eq!(steps.len(), 5);
+        assert_eq!(
+            steps[3].integer(1),
+            Some(DURATION_REPEAT_UNTIL_STEPS_COMPLETE as i64)
+        );
+        assert_eq!(steps[3].integer(2), Some(1));
+        assert_eq!(steps[3].integer(4), Some(5));
+        assert_eq!(steps[1].integer(5), Some(120));
+    }
+
+    #[test]
+    fn round_trips_through_fit() {
+        let original = activity();
+        let messages = decode(&encode_workout(&original)).unwrap();
+
+        let (activity, issues) = decode_workout(&messages, "file");
+        let activity = activity.unwrap();
+
+        assert!(issues.is_empty());
+        assert_eq!(activity.name, "5x1");
+        assert_eq!(
+            serde_json::to_value(&activity.workouts).unwrap(),
+            serde_json::to_value(&original.workouts).unwrap()
+        );
+    }
+
+    #[test]
+    fn reads_targets_in_watts() {
+        let step = FitStep {
+            duration_type: Some(DURATION_TIME),
+            duration_value: Some(60_000),
+            target_type: Some(TARGET_POWER),
+            target_value: Some(0),
+            target_low: Some(POWER_WATTS_OFFSET + 240),
+            target_high: Some(POWER_WATTS_OFFSET + 260),
+            secondary_target_type: None,
+            secondary_target_low: None,
+            secondary_target_high: None,
+            intensity: Some(INTENSITY_ACTIVE),
+            name: None,
+            notes: None,
+        };
+
+        let workout = to_workout(&step).unwrap();
+
+        assert_eq!(workout.power_unit, PowerUnit::Watts);
+        assert_eq!(workout.power_steady, 250.0);
+
+        // Written back in watts
+        let message = step_message(&workout);
+        assert_eq!(message.integer(5), Some((POWER_WATTS_OFFSET + 250) as i64));
+    }
+
+    #[test]
+    fn rejects_repeats_over_the_limits() {
+        let file_id = Message::new(FILE_ID, vec![(0, Value::Enum(Some(FILE_TYPE_WORKOUT)))]);
+        let repeat = |count: u32| {
+            Message::new(
+                WORKOUT_STEP,
+                vec![
+                    (1, Value::Enum(Some(DURATION_REPEAT_UNTIL_STEPS_COMPLETE))),
+                    (2, Value::UInt32(Some(0))),
+                    (4, Value::UInt32(Some(count))),
+                ],
+            )
+        };
+
+        let messages = vec![file_id.clone(), step_message(&steady(60, 1.0)), repeat(10)];
+        let (activity, _) = decode_workout(&messages, "file");
+        assert_eq!(activity.unwrap().workouts.len(), 10);
+
+        let messages = vec![
+            file_id.clone(),
+            step_message(&steady(60, 1.0)),
+            repeat(u32::MAX),
+        ];
+        let (activity, issues) = decode_workout(&messages, "file");
+        assert!(activity.is_none());
+        assert_eq!(issues[0].element.as_deref(), Some("workout_step[1]"));
+
+        // 100 repeats of an hour are within the step limit but not the duration limit
+        let messages = vec![file_id, step_message(&steady(3600, 1.0)), repeat(100)];
+        let (activity, issues) = decode_workout(&messages, "file");
+        assert!(activity.is_none());
+        assert!(has_errors(&issues));
+    }
+}
@@ -0,0 +1,556 @@
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
+
+use super::{
+    activities::{Activity, TextEvent, Workout, WorkoutType},
+    validation::{has_errors, WorkoutIssue},
+};
+
+/// Native workout file, see `workouts/sample.json`.
+///
+/// ```json
+/// {
+///   "info": { "name": "Z2", "author": "", "description": "", "sportType": "bike", "tags": ["z2"] },
+///   "workout": [
+///     { "type": "warmup", "duration": 360, "powerLow": 0.4, "powerHigh": 0.6, "cadence": 60 },
+///     { "type": "steady-state", "duration": 180, "power": 0.7, "cadenceLow": 85, "cadenceHigh": 95 },
+///     { "type": "intervals", "repeat": 4, "onDuration": 30, "offDuration": 30, "onPower": 1.2, "offPower": 0.5 }
+///   ]
+/// }
+/// ```
+///
+/// Power is a fraction of FTP and durations are in whole seconds.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct JsonWorkout {
+    pub info: WorkoutInfo,
+    pub workout: Vec<Step>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkoutInfo {
+    pub name: String,
+    #[serde(default)]
+    pub author: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default = "default_sport_type")]
+    pub sport_type: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// Cadence target shared by the steps, in rpm
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct StepCadence {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cadence: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cadence_low: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cadence_high: Option<u8>,
+}
+
+/// Message shown `offset` seconds into a step
+#[derive(Serialize, Deserialize, Clone)]
+pub struct StepTextEvent {
+    pub offset: u16,
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum Step {
+    #[serde(rename_all = "camelCase")]
+    Warmup {
+        duration: u16,
+        power_low: f64,
+        power_high: f64,
+        #[serde(flatten)]
+        cadence: StepCadence,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        text_events: Vec<StepTextEvent>,
+    },
+    #[serde(rename_all = "camelCase")]
+    SteadyState {
+        duration: u16,
+        power: f64,
+        #[serde(flatten)]
+        cadence: StepCadence,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        text_events: Vec<StepTextEvent>,
+    },
+    #[serde(rename_all = "camelCase")]
+    Cooldown {
+        duration: u16,
+        power_low: f64,
+        power_high: f64,
+        #[serde(flatten)]
+        cadence: StepCadence,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        text_events: Vec<StepTextEvent>,
+    },
+    #[serde(rename_all = "camelCase")]
+    Ramp {
+        duration: u16,
+        power_low: f64,
+        power_high: f64,
+        #[serde(flatten)]
+        cadence: StepCadence,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        text_events: Vec<StepTextEvent>,
+    },
+    /// Expanded into `repeat` steady states at `on_power` and `off_power`
+    #[serde(rename_all = "camelCase")]
+    Intervals {
+        repeat: u16,
+        on_duration: u16,
+        off_duration: u16,
+        on_power: f64,
+        off_power: f64,
+        #[serde(flatten)]
+        cadence: StepCadence,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cadence_resting: Option<u8>,
+    },
+    #[serde(rename_all = "camelCase")]
+    FreeRide {
+        duration: u16,
+        #[serde(default)]
+        flat_road: bool,
+        #[serde(flatten)]
+        cadence: StepCadence,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        text_events: Vec<StepTextEvent>,
+    },
+    #[serde(rename_all = "camelCase")]
+    MaxEffort {
+        duration: u16,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        text_events: Vec<StepTextEvent>,
+    },
+}
+
+/// Reads a JSON workout file along with the problems found in it
+pub fn json_to_workout(file_path: &Path) -> (Option<Activity>, Vec<WorkoutIssue>) {
+    let json = match fs::read_to_string(file_path) {
+        Ok(json) => json,
+        Err(err) => return (None, vec![WorkoutIssue::error(&err.to_string())]),
+    };
+
+    let workout: JsonWorkout = match serde_json::from_str(&json) {
+        Ok(workout) => workout,
+        Err(err) => {
+            let issue = WorkoutIssue {
+                line: Some(err.line()),
+                ..WorkoutIssue::error(&err.to_string())
+            };
+
+            return (None, vec![issue]);
+        }
+    };
+
+    let issues = validate(&workout);
+
+    if has_errors(&issues) {
+        return (None, issues);
+    }
+
+    (Some(Activity::from(workout)), issues)
+}
+
+/// Checks of the values serde can't express, e.g. zero durations
+pub fn validate(workout: &JsonWorkout) -> Vec<WorkoutIssue> {
+    let mut issues = Vec::new();
+
+    if workout.info.name.trim().is_empty() {
+        issues.push(WorkoutIssue::error("Workout has no name"));
+    }
+
+    if workout.workout.is_empty() {
+        issues.push(WorkoutIssue::error("Workout has no steps"));
+    }
+
+    for (index, step) in workout.workout.iter().enumerate() {
+        let (durations, powers) = match step {
+            Step::Warmup {
+                duration,
+                power_low,
+                power_high,
+                ..
+            }
+            | Step::Cooldown {
+                duration,
+                power_low,
+                power_high,
+                ..
+            }
+            | Step::Ramp {
+                duration,
+                power_low,
+                power_high,
+                ..
+            } => (
+                vec![("duration", *duration)],
+                vec![("powerLow", *power_low), ("powerHigh", *power_high)],
+            ),
+            Step::SteadyState {
+                duration, power, ..
+            } => (vec![("duration", *duration)], vec![("power", *power)]),
+            Step::Intervals {
+                repeat,
+                on_duration,
+                off_duration,
+                on_power,
+                off_power,
+                ..
+            } => (
+                vec![
+                    ("repeat", *repeat),
+                    ("onDuration", *on_duration),
+                    ("offDuration", *off_duration),
+                ],
+                vec![("onPower", *on_power), ("offPower", *off_power)],
+            ),
+            Step::FreeRide { duration, .. } | Step::MaxEffort { duration, .. } => {
+                (vec![("duration", *duration)], vec![])
+            }
+        };
+
+        let element = format!("workout[{}]", index);
+
+        for (key, _) in durations.iter().filter(|(_, value)| *value == 0) {
+            issues.push(WorkoutIssue {
+                element: Some(element.clone()),
+                ..WorkoutIssue::warning(&format!("{} is zero", key))
+            });
+        }
+
+        for (key, _) in powers.iter().filter(|(_, value)| *value < 0.0) {
+            issues.push(WorkoutIssue {
+                element: Some(element.clone()),
+                ..WorkoutIssue::error(&format!("{} is negative", key))
+            });
+        }
+    }
+
+    issues
+}
+
+impl From<JsonWorkout> for Activity {
+    fn from(value: JsonWorkout) -> Self {
+        let mut workouts = Vec::new();
+
+        for step in value.workout {
+            let workout_type = match &step {
+                Step::Warmup { .. } => WorkoutType::Warmup,
+                Step::Cooldown { .. } => WorkoutType::Cooldown,
+                Step::Ramp { .. } => WorkoutType::Ramp,
+                Step::SteadyState { .. } | Step::Intervals { .. } => WorkoutType::SteadyState,
+                Step::FreeRide { .. } => WorkoutType::FreeRide,
+                Step::MaxEffort { .. } => WorkoutType::MaxEffort,
+            };
+
+            match step {
+                Step::Warmup {
+                    duration,
+                    power_low,
+                    power_high,
+                    cadence,
+                    text_events,
+                }
+                | Step::Cooldown {
+                    duration,
+                    power_low,
+                    power_high,
+                    cadence,
+                    text_events,
+                }
+                | Step::Ramp {
+                    duration,
+                    power_low,
+                    power_high,
+                    cadence,
+                    text_events,
+                } => workouts.push(Workout {
+                    workout_type,
+                    duration,
+                    power_low,
+                    power_high,
+                    text_events: to_text_events(text_events),
+                    ..with_cadence(&cadence)
+                }),
+                Step::SteadyState {
+                    duration,
+                    power,
+                    cadence,
+                    text_events,
+                } => workouts.push(Workout {
+                    workout_type,
+                    duration,
+                    power_steady: power,
+                    text_events: to_text_events(text_events),
+                    ..with_cadence(&cadence)
+                }),
+                Step::Intervals {
+                    repeat,
+                    on_duration,
+                    off_duration,
+                    on_power,
+                    off_power,
+                    cadence,
+                    cadence_resting,
+                } => {
+                    for _ in 0..repeat {
+                        workouts.push(Workout {
+                            workout_type: workout_type.clone(),
+                            duration: on_duration,
+                            power_steady: on_power,
+                            ..with_cadence(&cadence)
+                        });
+
+                        workouts.push(Workout {
+                            workout_type: workout_type.clone(),
+                            duration: off_duration,
+                            power_steady: off_power,
+                            cadence: cadence_resting.unwrap_or(0),
+                            ..Default::default()
+                        });
+                    }
+                }
+                Step::FreeRide {
+                    duration,
+                    flat_road,
+                    cadence,
+                    text_events,
+                } => workouts.push(Workout {
+                    workout_type,
+                    duration,
+                    flat_road,
+                    text_events: to_text_events(text_events),
+                    ..with_cadence(&cadence)
+                }),
+                Step::MaxEffort {
+                    duration,
+                    text_events,
+                } => workouts.push(Workout {
+                    workout_type,
+                    duration,
+                    text_events: to_text_events(text_events),
+                    ..Default::default()
+                }),
+            }
+        }
+
+        Activity {
+            id: String::new(),
+            name: value.info.name,
+            description: value.info.description,
+            author: value.info.author,
+            tags: value.info.tags,
+            workouts,
+            path: None,
+        }
+    }
+}
+
+// Targets have to be fractions of FTP, see `Activity::relative_to_ftp`
+impl From<&Activity> for JsonWorkout {
+    fn from(value: &Activity) -> Self {
+        let workout = value
+            .workouts
+            .iter()
+            .map(|workout| {
+                let cadence = StepCadence {
+                    cadence: (workout.cadence > 0).then_some(workout.cadence),
+                    cadence_low: (workout.cadence_low > 0).then_some(workout.cadence_low),
+                    cadence_high: (workout.cadence_high > 0).then_some(workout.cadence_high),
+                };
+
+                let text_events = workout
+                    .text_events
+                    .iter()
+                    .map(|event| StepTextEvent {
+                        offset: event.offset,
+                        message: event.message.clone(),
+                    })
+                    .collect();
+
+                let duration = workout.duration;
+                let (power_low, power_high) = (workout.power_low, workout.power_high);
+
+                match workout.workout_type {
+                    WorkoutType::Warmup => Step::Warmup {
+                        duration,
+                        power_low,
+                        power_high,
+                        cadence,
+                        text_events,
+                    },
+                    WorkoutType::Cooldown => Step::Cooldown {
+                        duration,
+                        power_low,
+                        power_high,
+                        cadence,
+                        text_events,
+                    },
+                    WorkoutType::Ramp => Step::Ramp {
+                        duration,
+                        power_low,
+                        power_high,
+                        cadence,
+                        text_events,
+                    },
+                    WorkoutType::SteadyState => Step::SteadyState {
+                        duration,
+                        power: workout.power_steady,
+                        cadence,
+                        text_events,
+                    },
+                    WorkoutType::FreeRide => Step::FreeRide {
+                        duration,
+                        flat_road: workout.flat_road,
+                        cadence,
+                        text_events,
+                    },
+                    WorkoutType::MaxEffort => Step::MaxEffort {
+                        duration,
+                        text_events,
+                    },
+                }
+            })
+            .collect();
+
+        JsonWorkout {
+            info: WorkoutInfo {
+                name: value.name.clone(),
+                author: value.author.clone(),
+                description: value.description.clone(),
+                sport_type: default_sport_type(),
+                tags: value.tags.clone(),
+            },
+            workout,
+        }
+    }
+}
+
+fn with_cadence(cadence: &StepCadence) -> Workout {
+    Workout {
+        cadence: cadence.cadence.unwrap_or(0),
+        cadence_low: cadence.cadence_low.unwrap_or(0),
+        cadence_high: cadence.cadence_high.unwrap_or(0),
+        ..Default::default()
+    }
+}
+
+fn to_text_events(text_events: Vec<StepTextEvent>) -> Vec<TextEvent> {
+    text_events
+        .into_iter()
+        .map(|event| TextEvent {
+            offset: event.offset,
+            message: event.message,
+        })
+        .collect()
+}
+
+fn default_sport_type() -> String {
+    "bike".to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> JsonWorkout {
+        serde_json::from_str(include_str!("../../../workouts/sample.json")).unwrap()
+    }
+
+    #[test]
+    fn reads_sample_workout() {
+        let workout = sample();
+
+        assert!(validate(&workout).is_empty());
+
+        let activity = Activity::from(workout);
+
+        assert_eq!(activity.name, "Z2 Chiller - 30mins");
+        assert_eq!(activity.tags, vec!["z2".to_string()]);
+        assert_eq!(activity.workouts.len(), 17);
+        assert_eq!(
+            activity
+                .workouts
+                .iter()
+                .map(|w| w.duration as u32)
+                .sum::<u32>(),
+            360 * 2 + 180 * 15
+        );
+    }
+
+    #[test]
+    fn round_trips_through_activity() {
+        let activity = Activity::from(sample());
+        let json = serde_json::to_string(&JsonWorkout::from(&activity)).unwrap();
+        let read = Activity::from(serde_json::from_str::<JsonWorkout>(&json).unwrap());
+
+        assert_eq!(
+            serde_json::to_value(&read.workouts).unwrap(),
+            serde_json::to_value(&activity.workouts).unwrap()
+        );
+        assert_eq!(read.author, activity.author);
+        assert_eq!(read.tags, activity.tags);
+    }
+
+    #[test]
+    fn expands_intervals() {
+        let workout: JsonWorkout = serde_json::from_str(
+            r#"{
+                "info": { "name": "Intervals" },
+                "workout": [{
+                    "type": "intervals", "repeat": 3, "onDuration": 30, "offDuration": 60,
+                    "onPower": 1.2, "offPower": 0.5, "cadence": 100, "cadenceResting": 85
+                }]
+            }"#,
+        )
+        .unwrap();
+
+        let activity = Activity::from(workout);
+
+        assert_eq!(activity.workouts.len(), 6);
+        assert_eq!(activity.workouts[0].cadence, 100);
+        assert_eq!(activity.workouts[1].cadence, 85);
+        assert_eq!(activity.workouts[1].power_steady, 0.5);
+    }
+
+    #[test]
+    fn reports_invalid_values() {
+        let workout: JsonWorkout = serde_json::from_str(
+            r#"{
+                "info": { "name": "Broken" },
+                "workout": [
+                    { "type": "steady-state", "duration": 0, "power": 0.5 },
+                    { "type": "ramp", "duration": 60, "powerLow": -0.1, "powerHigh": 0.5 }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let issues = validate(&workout);
+
+        assert_eq!(issues.len(), 2);
+        assert_eq!(issues[0].element.as_deref(), Some("workout[0]"));
+        assert!(has_errors(&issues));
+    }
+
+    #[test]
+    fn rejects_missing_fields_with_line() {
+        let error = serde_json::from_str::<JsonWorkout>(
+            "{\n\"info\": { \"name\": \"Broken\" },\n\"workout\": [{ \"type\": \"steady-state\", \"duration\": 60 }]\n}",
+        )
+        .err()
+        .unwrap();
+
+        assert!(error.to_string().contains("power"));
+        assert_eq!(error.line(), 3);
+    }
+}
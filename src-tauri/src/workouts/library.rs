@@ -0,0 +1,242 @@
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path, path::PathBuf};
+
+use crate::data::training_load::planned_training_stress_score;
+use crate::error::error_generic;
+use crate::prelude::*;
+use crate::system::{directory, user::get_user_settings};
+
+use super::activities::{Activity, ACTIVITIES};
+
+const LOGGER_NAME: &str = "workouts::library";
+
+// 64 bit FNV-1a, stable across builds unlike the hasher of the standard library
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// What the workout library index keeps of a loaded workout
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryEntry {
+    pub id: String,
+    // None for the built-in workouts
+    pub path: Option<PathBuf>,
+    // Version of the steps, see `workout_version`
+    pub hash: String,
+    pub name: String,
+    pub tags: Vec<String>,
+    pub author: String,
+    // Seconds
+    pub duration: u32,
+    // None for targets in watts without an FTP
+    pub training_stress_score: Option<f64>,
+}
+
+impl LibraryEntry {
+    pub fn new(activity: &Activity, ftp: u16) -> Self {
+        Self {
+            id: activity.id.clone(),
+            path: activity.path.clone(),
+            hash: workout_version(activity),
+            name: activity.name.clone(),
+            tags: activity.tags.clone(),
+            author: activity.author.clone(),
+            duration: activity
+                .workouts
+                .iter()
+                .map(|workout| workout.duration as u32)
+                .sum(),
+            training_stress_score: activity
+                .relative_to_ftp(ftp)
+                .map(|activity| planned_training_stress_score(&activity)),
+        }
+    }
+}
+
+/// Id of a workout file, derived from its path in the workouts directory
+pub fn file_id(path: &Path) -> String {
+    let relative = directory::get_workouts_directory()
+        .ok()
+        .and_then(|directory| path.strip_prefix(directory).ok().map(Path::to_path_buf))
+        .unwrap_or(path.to_path_buf());
+
+    format!(
+        "{:016x}",
+        stable_hash(relative.to_string_lossy().as_bytes())
+    )
+}
+
+/// Changes whenever the steps of the workout change, renaming keeps it
+pub fn workout_version(activity: &Activity) -> String {
+    let steps = serde_json::to_vec(&activity.workouts).unwrap_or_default();
+
+    format!("{:016x}", stable_hash(&steps))
+}
+
+/// Version of a loaded workout, `None` when it is no longer in the library
+pub async fn current_version(activity_id: &str) -> Option<String> {
+    find_workout(activity_id).await.map(|(_, version)| version)
+}
+
+/// Name and version of a loaded workout, `None` when it is no longer in the library
+pub async fn find_workout(activity_id: &str) -> Option<(String, String)> {
+    let activities = ACTIVITIES.get()?.read().await;
+
+    activities
+        .iter()
+        .find(|activity| activity.id == activity_id)
+        .map(|activity| (activity.name.clone(), workout_version(activity)))
+}
+
+/// Gives the workouts read from files their id.
+///
+/// A file keeps the id it had in the index. A file that was moved or renamed
+/// keeps its id too, as long as its steps did not change at the same time.
+pub fn assign_ids(activities: &mut [Activity]) {
+    let index = read_index();
+
+    let loaded: Vec<&Path> = activities
+        .iter()
+        .filter_map(|activity| activity.path.as_deref())
+        .collect();
+
+    // Entries of files that are gone, which a renamed file can take over
+    let mut missing: Vec<&LibraryEntry> = index
+        .iter()
+        .filter(|entry| {
+            entry
+                .path
+                .as_deref()
+                .is_some_and(|path| !loaded.contains(&path))
+        })
+        .collect();
+
+    for activity in activities.iter_mut() {
+        let Some(path) = activity.path.as_deref() else {
+            continue;
+        };
+
+        let known = index
+            .iter()
+            .find(|entry| entry.path.as_deref() == Some(path));
+
+        let id = match known {
+            Some(entry) => entry.id.clone(),
+            None => {
+                let version = workout_version(activity);
+
+                match missing.iter().position(|entry| entry.hash == version) {
+                    Some(position) => missing.remove(position).id.clone(),
+                    None => file_id(path),
+                }
+            }
+        };
+
+        activity.id = id;
+    }
+}
+
+/// Writes the index of the workout library, logging failures
+pub fn update_index(activities: &[Activity], ftp: u16) {
+    let index: Vec<LibraryEntry> = activities
+        .iter()
+        .map(|activity| LibraryEntry::new(activity, ftp))
+        .collect();
+
+    let result = directory::get_workout_library_file().and_then(|path| {
+        serde_json::to_writer(fs::File::create(path)?, &index)
+            .map_err(|err| error_generic(&err.to_string()))
+    });
+
+    if let Err(err) = result {
+        warn!(
+            "{}::update_index: Unable to write workout library index: {}",
+            LOGGER_NAME, err
+        );
+    }
+}
+
+/// Entries of the loaded workouts, the planned TSS follows the current FTP
+pub async fn get_library() -> Result<Vec<LibraryEntry>> {
+    let Some(lock) = ACTIVITIES.get() else {
+        return Err(error_generic("Activities are not loaded"));
+    };
+
+    let ftp = get_user_settings().await.ftp;
+
+    Ok(lock
+        .read()
+        .await
+        .iter()
+        .map(|activity| LibraryEntry::new(activity, ftp))
+        .collect())
+}
+
+// A missing or broken index only loses the ids of renamed files
+fn read_index() -> Vec<LibraryEntry> {
+    directory::get_workout_library_file()
+        .and_then(|path| Ok(fs::File::open(path)?))
+        .and_then(|file| {
+            serde_json::from_reader(file).map_err(|err| error_generic(&err.to_string()))
+        })
+        .unwrap_or_default()
+}
+
+fn stable_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(FNV_OFFSET_BASIS, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(FNV_PRIME)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workouts::activities::{Workout, WorkoutType};
+
+    fn activity(name: &str, power: f64) -> Activity {
+        Activity {
+            id: String::new(),
+            name: name.to_string(),
+            description: String::new(),
+            author: String::new(),
+            tags: Vec::new(),
+            workouts: vec![Workout {
+                workout_type: WorkoutType::SteadyState,
+                duration: 600,
+                power_steady: power,
+                ..Default::default()
+            }],
+            path: None,
+        }
+    }
+
+    #[test]
+    fn hashes_are_stable() {
+        // Reference values of 64 bit FNV-1a
+        assert_eq!(stable_hash(b""), 0xcbf29ce484222325);
+        assert_eq!(stable_hash(b"a"), 0xaf63dc4c8601ec8c);
+    }
+
+    #[test]
+    fn version_follows_the_steps() {
+        let original = activity("Tempo", 0.8);
+
+        assert_eq!(
+            workout_version(&original),
+            workout_version(&activity("Renamed", 0.8))
+        );
+        assert_ne!(
+            workout_version(&original),
+            workout_version(&activity("Tempo", 0.85))
+        );
+    }
+
+    #[test]
+    fn ids_follow_the_path() {
+        let path = Path::new("/tmp/workouts/tempo.zwo");
+
+        assert_eq!(file_id(path), file_id(path));
+        assert_ne!(file_id(path), file_id(Path::new("/tmp/workouts/other.zwo")));
+    }
+}
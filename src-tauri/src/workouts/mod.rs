@@ -1,3 +1,10 @@
 pub mod zwo;
+pub mod json;
+pub mod erg;
+pub mod fit;
 pub mod reader;
+pub mod editor;
+pub mod library;
 pub mod activities;
+pub mod protocols;
+pub mod validation;
@@ -0,0 +1,136 @@
+use super::activities::{Activity, Workout, WorkoutType};
+
+pub const RAMP_TEST_ID: &str = "ramp_test";
+pub const TWENTY_MINUTE_TEST_ID: &str = "twenty_minute_test";
+
+const PROTOCOL_AUTHOR: &str = "Cycling Trainer";
+const TEST_TAG: &str = "ftp test";
+
+// Ramp steps in fractions of FTP, ridden until failure
+const RAMP_START: f64 = 0.6;
+const RAMP_INCREMENT: f64 = 0.06;
+const RAMP_STEPS: usize = 20;
+const RAMP_STEP_DURATION: u16 = 60;
+
+/// The built-in FTP test workouts
+pub fn test_protocols() -> Vec<Activity> {
+    vec![ramp_test(), twenty_minute_test()]
+}
+
+fn ramp_test() -> Activity {
+    let mut workouts = vec![ramp(WorkoutType::Warmup, 300, 0.4, RAMP_START)];
+
+    workouts.extend((0..RAMP_STEPS).map(|step| {
+        steady_state(
+            RAMP_STEP_DURATION,
+            RAMP_START + RAMP_INCREMENT * step as f64,
+        )
+    }));
+
+    workouts.push(ramp(WorkoutType::Cooldown, 300, 0.5, 0.3));
+
+    Activity {
+        id: RAMP_TEST_ID.to_string(),
+        name: "Ramp Test".to_string(),
+        description: "Power goes up every minute until you can't hold it anymore. \
+            FTP is estimated at 75% of the best 1 minute power."
+            .to_string(),
+        author: PROTOCOL_AUTHOR.to_string(),
+        tags: vec![TEST_TAG.to_string()],
+        workouts,
+        path: None,
+    }
+}
+
+fn twenty_minute_test() -> Activity {
+    Activity {
+        id: TWENTY_MINUTE_TEST_ID.to_string(),
+        name: "20 Minute Test".to_string(),
+        description: "After a warmup and a short hard effort to clear the legs, \
+            ride 20 minutes as hard as you can sustain. \
+            FTP is estimated at 95% of the 20 minute power."
+            .to_string(),
+        author: PROTOCOL_AUTHOR.to_string(),
+        tags: vec![TEST_TAG.to_string()],
+        workouts: vec![
+            ramp(WorkoutType::Warmup, 600, 0.5, 0.75),
+            steady_state(300, 1.05),
+            steady_state(600, 0.5),
+            steady_state(1200, 1.0),
+            ramp(WorkoutType::Cooldown, 600, 0.6, 0.4),
+        ],
+        path: None,
+    }
+}
+
+fn ramp(workout_type: WorkoutType, duration: u16, power_low: f64, power_high: f64) -> Workout {
+    Workout {
+        workout_type,
+        duration,
+        power_low,
+        power_high,
+        ..Default::default()
+    }
+}
+
+fn steady_state(duration: u16, power: f64) -> Workout {
+    Workout {
+        workout_type: WorkoutType::SteadyState,
+        duration,
+        power_steady: power,
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn total_duration(activity: &Activity) -> u32 {
+        activity
+            .workouts
+            .iter()
+            .map(|workout| workout.duration as u32)
+            .sum()
+    }
+
+    #[test]
+    fn ramp_test_goes_up_every_minute() {
+        let activity = ramp_test();
+        let steps = &activity.workouts[1..=RAMP_STEPS];
+
+        assert_eq!(activity.workouts.len(), RAMP_STEPS + 2);
+        assert!(steps.iter().all(|step| step.duration == 60));
+        assert!((steps[0].power_steady - 0.6).abs() < 1e-9);
+        assert!((steps[1].power_steady - 0.66).abs() < 1e-9);
+        assert!((steps[RAMP_STEPS - 1].power_steady - 1.74).abs() < 1e-9);
+        // Warmup ends where the ramp starts
+        assert_eq!(activity.workouts[0].power_high, steps[0].power_steady);
+        assert_eq!(total_duration(&activity), 1800);
+    }
+
+    #[test]
+    fn twenty_minute_test_has_a_full_effort() {
+        let activity = twenty_minute_test();
+
+        let efforts: Vec<&Workout> = activity
+            .workouts
+            .iter()
+            .filter(|workout| workout.duration == 1200)
+            .collect();
+
+        assert_eq!(efforts.len(), 1);
+        assert_eq!(efforts[0].power_steady, 1.0);
+        assert_eq!(total_duration(&activity), 3300);
+    }
+
+    #[test]
+    fn protocols_have_their_ids() {
+        let ids: Vec<String> = test_protocols()
+            .into_iter()
+            .map(|activity| activity.id)
+            .collect();
+
+        assert_eq!(ids, vec![RAMP_TEST_ID, TWENTY_MINUTE_TEST_ID]);
+    }
+}
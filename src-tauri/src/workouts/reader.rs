@@ -1,11 +1,21 @@
-use log::error;
+use log::{error, warn};
 use serde::Serialize;
-use std::fs;
+use std::{fs, sync::Mutex};
 
-use super::zwo::{zwo_to_workout, WorkoutFile};
+use super::{
+    activities::Activity,
+    erg::{erg_to_workout, mrc_to_workout},
+    fit::fit_to_workout,
+    json::json_to_workout,
+    validation::{WorkoutFileReport, WorkoutIssue},
+    zwo::zwo_to_workout,
+};
 
 const LOGGER_NAME: &str = "workouts::reader";
 
+// Outcome of the last load of the workouts directory
+static LOAD_REPORT: Mutex<Vec<WorkoutFileReport>> = Mutex::new(Vec::new());
+
 #[derive(Serialize)]
 pub struct WorkoutItem {
     pub id: usize,
@@ -13,7 +23,10 @@ pub struct WorkoutItem {
     pub description: String,
 }
 
-pub fn get_workouts_from_file() -> Vec<WorkoutFile> {
+/// Reads the workouts directory, `ftp` is only checked to warn about targets in watts.
+///
+/// The activities have no id yet, see `library::assign_ids`.
+pub fn get_workouts_from_file(ftp: u16) -> Vec<Activity> {
     // TODO: Use event based reading for large XML files
     let path = match dirs::document_dir() {
         Some(dir) => dir.join("Cycling Trainer").join("workouts"),
@@ -27,23 +40,66 @@ pub fn get_workouts_from_file() -> Vec<WorkoutFile> {
         }
     };
 
-    let workout_files: Vec<WorkoutFile> = match fs::read_dir(path) {
+    let mut report: Vec<WorkoutFileReport> = Vec::new();
+
+    let activities: Vec<Activity> = match fs::read_dir(path) {
         Ok(files) => files
             .filter_map(|entry| {
                 let entry = entry.ok()?;
 
                 let file_path = entry.path();
-                let extension = file_path.extension()?;
 
-                match extension.to_string_lossy().to_string().as_str() {
-                    "zwo" => zwo_to_workout(&file_path),
+                if file_path.is_dir() {
+                    return None;
+                }
 
-                    // TODO: Support other file times
-                    _ => {
-                        error!("{}:get_workouts: Unsupported file type", LOGGER_NAME);
-                        None
+                let extension = file_path
+                    .extension()
+                    .map(|extension| extension.to_string_lossy().to_lowercase());
+
+                let (workout, mut issues) = match extension.as_deref() {
+                    Some("zwo") => {
+                        let (workout, issues) = zwo_to_workout(&file_path);
+
+                        (workout.map(Activity::from), issues)
                     }
+                    Some("json") => json_to_workout(&file_path),
+                    Some("erg") => erg_to_workout(&file_path),
+                    Some("mrc") => mrc_to_workout(&file_path),
+                    Some("fit") => fit_to_workout(&file_path),
+
+                    // TODO: Support other file times
+                    _ => (None, vec![WorkoutIssue::warning("Unsupported file type")]),
+                };
+
+                // Loaded anyway, the targets are ridden once an FTP is set
+                if ftp == 0 && workout.as_ref().is_some_and(Activity::has_watt_targets) {
+                    issues.push(WorkoutIssue::warning(
+                        "Targets in watts need an FTP in the user settings for FTP based values",
+                    ));
                 }
+
+                if !issues.is_empty() {
+                    warn!(
+                        "{}:get_workouts: {} has {} issues, see the workout load report",
+                        LOGGER_NAME,
+                        file_path.display(),
+                        issues.len()
+                    );
+                }
+
+                let workout = workout.map(|activity| Activity {
+                    path: Some(file_path.clone()),
+                    ..activity
+                });
+
+                report.push(WorkoutFileReport {
+                    path: file_path,
+                    loaded: workout.is_some(),
+                    issues,
+                });
+
+                workout
             })
             .collect(),
         Err(error) => {
@@ -56,5 +112,27 @@ pub fn get_workouts_from_file() -> Vec<WorkoutFile> {
         }
     };
 
-    workout_files
+    if let Ok(mut load_report) = LOAD_REPORT.lock() {
+        *load_report = report;
+    }
+
+    activities
+}
+
+/// Files of the workouts directory with the problems found when they were loaded
+pub fn get_load_report() -> Vec<WorkoutFileReport> {
+    LOAD_REPORT
+        .lock()
+        .map(|report| report.clone())
+        .unwrap_or_default()
+}
+
+/// Name of an activity without the characters that are not allowed in file names
+pub fn file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c => c,
+        })
+        .collect()
 }
@@ -0,0 +1,85 @@
+use serde::Serialize;
+use std::path::PathBuf;
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    // The workout is not loaded
+    Error,
+    Warning,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkoutIssue {
+    pub severity: Severity,
+    pub line: Option<usize>,
+    pub element: Option<String>,
+    pub reason: String,
+}
+
+/// Outcome of loading one file of the workouts directory
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkoutFileReport {
+    pub path: PathBuf,
+    pub loaded: bool,
+    pub issues: Vec<WorkoutIssue>,
+}
+
+impl WorkoutIssue {
+    pub fn error(reason: &str) -> Self {
+        Self {
+            severity: Severity::Error,
+            line: None,
+            element: None,
+            reason: reason.to_string(),
+        }
+    }
+
+    pub fn warning(reason: &str) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Self::error(reason)
+        }
+    }
+
+    pub fn at(self, line: usize, element: &str) -> Self {
+        Self {
+            line: Some(line),
+            element: Some(element.to_string()),
+            ..self
+        }
+    }
+}
+
+pub fn has_errors(issues: &[WorkoutIssue]) -> bool {
+    issues.iter().any(|issue| issue.severity == Severity::Error)
+}
+
+/// Line of a byte offset, starting at 1
+pub fn line_at(text: &str, position: usize) -> usize {
+    text.as_bytes()[..position.min(text.len())]
+        .iter()
+        .filter(|byte| **byte == b'\n')
+        .count()
+        + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_the_line_of_an_offset() {
+        let text = "<workout>\n    <Ramp/>\n</workout>";
+
+        assert_eq!(line_at(text, 0), 1);
+        // Newlines belong to the line they end
+        assert_eq!(line_at(text, 9), 1);
+        assert_eq!(line_at(text, 10), 2);
+        assert_eq!(line_at(text, 15), 2);
+        assert_eq!(line_at(text, text.len()), 3);
+        assert_eq!(line_at(text, text.len() + 10), 3);
+    }
+}
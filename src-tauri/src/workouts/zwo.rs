@@ -1,21 +1,32 @@
-use std::{fs, path::PathBuf};
+use quick_xml::{escape::escape, events::Event, Reader};
+use std::{fmt::Write, fs, path::Path};
 
 use serde::Deserialize;
 
+use super::{
+    activities::{self, Activity},
+    validation::{has_errors, line_at, WorkoutIssue},
+};
+
+const BIKE_SPORT_TYPE: &str = "bike";
+
 #[derive(Deserialize, Clone)]
 pub struct WorkoutFile {
     pub name: String,
     pub description: String,
     pub workout: Workout,
 
-    #[serde(rename = "sportType")]
+    #[serde(rename = "sportType", default)]
     sport_type: String,
-    author: String,
+    #[serde(default)]
+    pub author: String,
+    #[serde(default)]
     tags: Tags,
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Deserialize, Clone, Default)]
 struct Tags {
+    #[serde(default)]
     tag: Vec<Tag>,
 }
 
@@ -25,12 +36,27 @@ struct Tag {
     name: String,
 }
 
+impl WorkoutFile {
+    pub fn tags(&self) -> Vec<String> {
+        self.tags.tag.iter().map(|tag| tag.name.clone()).collect()
+    }
+}
+
 #[derive(Deserialize, Clone)]
 pub struct Workout {
     #[serde(rename = "$value")]
     pub workouts: Vec<WorkoutType>,
 }
 
+/// Message shown during a step, `time_offset` is relative to the start of the step
+#[derive(Deserialize, Clone)]
+pub struct TextEvent {
+    #[serde(rename = "@timeoffset")]
+    pub time_offset: u16,
+    #[serde(rename = "@message")]
+    pub message: String,
+}
+
 #[derive(Deserialize, Clone)]
 pub enum WorkoutType {
     Warmup {
@@ -41,7 +67,13 @@ pub enum WorkoutType {
         #[serde(rename = "@PowerHigh")]
         power_high: f64,
         #[serde(rename = "@Cadence")]
-        cadence: u8,
+        cadence: Option<u8>,
+        #[serde(rename = "@CadenceLow")]
+        cadence_low: Option<u8>,
+        #[serde(rename = "@CadenceHigh")]
+        cadence_high: Option<u8>,
+        #[serde(rename = "textevent", default)]
+        text_events: Vec<TextEvent>,
     },
     SteadyState {
         #[serde(rename = "@Duration")]
@@ -49,7 +81,13 @@ pub enum WorkoutType {
         #[serde(rename = "@Power")]
         power: f64,
         #[serde(rename = "@Cadence")]
-        cadence: u8,
+        cadence: Option<u8>,
+        #[serde(rename = "@CadenceLow")]
+        cadence_low: Option<u8>,
+        #[serde(rename = "@CadenceHigh")]
+        cadence_high: Option<u8>,
+        #[serde(rename = "textevent", default)]
+        text_events: Vec<TextEvent>,
     },
     Cooldown {
         #[serde(rename = "@Duration")]
@@ -59,12 +97,584 @@ pub enum WorkoutType {
         #[serde(rename = "@PowerHigh")]
         power_high: f64,
         #[serde(rename = "@Cadence")]
-        cadence: u8,
+        cadence: Option<u8>,
+        #[serde(rename = "@CadenceLow")]
+        cadence_low: Option<u8>,
+        #[serde(rename = "@CadenceHigh")]
+        cadence_high: Option<u8>,
+        #[serde(rename = "textevent", default)]
+        text_events: Vec<TextEvent>,
     },
+    Ramp {
+        #[serde(rename = "@Duration")]
+        duration: u16,
+        #[serde(rename = "@PowerLow")]
+        power_low: f64,
+        #[serde(rename = "@PowerHigh")]
+        power_high: f64,
+        #[serde(rename = "@Cadence")]
+        cadence: Option<u8>,
+        #[serde(rename = "@CadenceLow")]
+        cadence_low: Option<u8>,
+        #[serde(rename = "@CadenceHigh")]
+        cadence_high: Option<u8>,
+        #[serde(rename = "textevent", default)]
+        text_events: Vec<TextEvent>,
+    },
+    /// `Repeat` times an on step followed by an off step
+    IntervalsT {
+        #[serde(rename = "@Repeat")]
+        repeat: u16,
+        #[serde(rename = "@OnDuration")]
+        on_duration: u16,
+        #[serde(rename = "@OffDuration")]
+        off_duration: u16,
+        #[serde(rename = "@OnPower")]
+        on_power: f64,
+        #[serde(rename = "@OffPower")]
+        off_power: f64,
+        #[serde(rename = "@Cadence")]
+        cadence: Option<u8>,
+        #[serde(rename = "@CadenceLow")]
+        cadence_low: Option<u8>,
+        #[serde(rename = "@CadenceHigh")]
+        cadence_high: Option<u8>,
+        // Cadence of the off steps
+        #[serde(rename = "@CadenceResting")]
+        cadence_resting: Option<u8>,
+        // Offsets are relative to the start of the first on step
+        #[serde(rename = "textevent", default)]
+        text_events: Vec<TextEvent>,
+    },
+    FreeRide {
+        #[serde(rename = "@Duration")]
+        duration: u16,
+        #[serde(rename = "@FlatRoad")]
+        flat_road: Option<u8>,
+        #[serde(rename = "@Cadence")]
+        cadence: Option<u8>,
+        #[serde(rename = "@CadenceLow")]
+        cadence_low: Option<u8>,
+        #[serde(rename = "@CadenceHigh")]
+        cadence_high: Option<u8>,
+        #[serde(rename = "textevent", default)]
+        text_events: Vec<TextEvent>,
+    },
+    MaxEffort {
+        #[serde(rename = "@Duration")]
+        duration: u16,
+        #[serde(rename = "textevent", default)]
+        text_events: Vec<TextEvent>,
+    },
+    SolistPerformance {
+        #[serde(rename = "@Duration")]
+        duration: u16,
+        #[serde(rename = "@Power")]
+        power: Option<f64>,
+        #[serde(rename = "@Cadence")]
+        cadence: Option<u8>,
+        #[serde(rename = "@CadenceLow")]
+        cadence_low: Option<u8>,
+        #[serde(rename = "@CadenceHigh")]
+        cadence_high: Option<u8>,
+        #[serde(rename = "textevent", default)]
+        text_events: Vec<TextEvent>,
+    },
+    /// Message outside of a step, `time_offset` is relative to the start of the workout
+    #[serde(rename = "textevent")]
+    TextEvent {
+        #[serde(rename = "@timeoffset")]
+        time_offset: u16,
+        #[serde(rename = "@message")]
+        message: String,
+    },
+}
+
+/// Reads a ZWO file along with the problems found in it.
+///
+/// The workout is left out when any of the problems is an error.
+pub fn zwo_to_workout(file_path: &Path) -> (Option<WorkoutFile>, Vec<WorkoutIssue>) {
+    match fs::read_to_string(file_path) {
+        Ok(xml) => parse(&xml),
+        Err(err) => (None, vec![WorkoutIssue::error(&err.to_string())]),
+    }
+}
+
+fn parse(xml: &str) -> (Option<WorkoutFile>, Vec<WorkoutIssue>) {
+    let mut issues = validate(xml);
+
+    if has_errors(&issues) {
+        return (None, issues);
+    }
+
+    let workout: WorkoutFile = match quick_xml::de::from_str(xml) {
+        Ok(workout) => workout,
+        Err(err) => {
+            issues.push(WorkoutIssue::error(&err.to_string()));
+
+            return (None, issues);
+        }
+    };
+
+    // Files without a sport type are assumed to be bike workouts
+    let sport_type = workout.sport_type.trim();
+
+    if !sport_type.is_empty() && !sport_type.eq_ignore_ascii_case(BIKE_SPORT_TYPE) {
+        issues.push(WorkoutIssue::error(&format!(
+            "Only bike workouts are supported, this one is for {}",
+            sport_type
+        )));
+
+        return (None, issues);
+    }
+
+    (Some(workout), issues)
+}
+
+/// Structural checks of the workout steps, with the line of each problem
+fn validate(xml: &str) -> Vec<WorkoutIssue> {
+    let mut issues = Vec::new();
+    let mut reader = Reader::from_str(xml);
+    reader.trim_text(true);
+
+    // Names of the open elements
+    let mut path: Vec<String> = Vec::new();
+
+    loop {
+        let (element, is_empty) = match reader.read_event() {
+            Ok(Event::Start(element)) => (element, false),
+            Ok(Event::Empty(element)) => (element, true),
+            Ok(Event::End(_)) => {
+                path.pop();
+                continue;
+            }
+            Ok(Event::Eof) => break,
+            Ok(_) => continue,
+            Err(err) => {
+                let line = line_at(xml, reader.buffer_position());
+                let element = path.last().cloned().unwrap_or_default();
+
+                issues.push(WorkoutIssue::error(&err.to_string()).at(line, &element));
+                break;
+            }
+        };
+
+        let name = String::from_utf8_lossy(element.name().as_ref()).to_string();
+        let line = line_at(xml, reader.buffer_position());
+
+        let attributes: Vec<(String, String)> = element
+            .attributes()
+            .filter_map(|attribute| attribute.ok())
+            .map(|attribute| {
+                (
+                    String::from_utf8_lossy(attribute.key.as_ref()).to_string(),
+                    attribute
+                        .unescape_value()
+                        .map(|value| value.to_string())
+                        .unwrap_or_default(),
+                )
+            })
+            .collect();
+
+        let parent = path.last().map(String::as_str);
+        let in_step = path.len() >= 2 && path[path.len() - 2] == "workout";
+
+        if parent == Some("workout") || (in_step && name == "textevent") {
+            issues.extend(
+                validate_step(&name, &attributes)
+                    .into_iter()
+                    .map(|issue| issue.at(line, &name)),
+            );
+        }
+
+        if !is_empty {
+            path.push(name);
+        }
+    }
+
+    issues
 }
 
-pub fn zwo_to_workout(file_path: &PathBuf) -> Option<WorkoutFile> {
-    let xml = fs::read_to_string(file_path).ok()?;
+fn validate_step(name: &str, attributes: &[(String, String)]) -> Vec<WorkoutIssue> {
+    let (required, durations, powers): (&[&str], &[&str], &[&str]) = match name {
+        "Warmup" | "Cooldown" | "Ramp" => (
+            &["Duration", "PowerLow", "PowerHigh"],
+            &["Duration"],
+            &["PowerLow", "PowerHigh"],
+        ),
+        "SteadyState" => (&["Duration", "Power"], &["Duration"], &["Power"]),
+        "IntervalsT" => (
+            &["Repeat", "OnDuration", "OffDuration", "OnPower", "OffPower"],
+            &["Repeat", "OnDuration", "OffDuration"],
+            &["OnPower", "OffPower"],
+        ),
+        "FreeRide" | "MaxEffort" => (&["Duration"], &["Duration"], &[]),
+        "SolistPerformance" => (&["Duration"], &["Duration"], &["Power"]),
+        "textevent" => (&["timeoffset", "message"], &[], &[]),
+        _ => {
+            return vec![WorkoutIssue::error(&format!(
+                "Unsupported element <{}>",
+                name
+            ))]
+        }
+    };
+
+    let value = |key: &str| {
+        attributes
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value.trim())
+    };
+
+    let mut issues: Vec<WorkoutIssue> = required
+        .iter()
+        .filter(|key| value(key).is_none())
+        .map(|key| WorkoutIssue::error(&format!("Missing required attribute {}", key)))
+        .collect();
+
+    for key in durations.iter() {
+        match value(key).map(str::parse::<u16>) {
+            Some(Ok(0)) => issues.push(WorkoutIssue::warning(&format!("{} is zero", key))),
+            Some(Err(_)) => issues.push(WorkoutIssue::error(&format!(
+                "{} is not a whole number of seconds",
+                key
+            ))),
+            _ => {}
+        }
+    }
+
+    for key in powers.iter() {
+        match value(key).map(str::parse::<f64>) {
+            Some(Ok(power)) if power < 0.0 => {
+                issues.push(WorkoutIssue::error(&format!("{} is negative", key)))
+            }
+            Some(Err(_)) => issues.push(WorkoutIssue::error(&format!("{} is not a number", key))),
+            _ => {}
+        }
+    }
+
+    issues
+}
+
+/// Writes an activity as a ZWO file, the reverse of `zwo_to_workout`.
+///
+/// Targets have to be fractions of FTP, see `Activity::relative_to_ftp`.
+pub fn workout_to_zwo(activity: &Activity) -> String {
+    let mut xml = String::from("<workout_file>\n");
+
+    let _ = writeln!(xml, "    <author>{}</author>", escape(&activity.author));
+    let _ = writeln!(xml, "    <name>{}</name>", escape(&activity.name));
+    let _ = writeln!(
+        xml,
+        "    <description>{}</description>",
+        escape(&activity.description)
+    );
+    let _ = writeln!(xml, "    <sportType>{}</sportType>", BIKE_SPORT_TYPE);
+
+    if activity.tags.is_empty() {
+        xml.push_str("    <tags/>\n");
+    } else {
+        xml.push_str("    <tags>\n");
+
+        for tag in activity.tags.iter() {
+            let _ = writeln!(xml, "        <tag name=\"{}\"/>", escape(tag));
+        }
+
+        xml.push_str("    </tags>\n");
+    }
+
+    xml.push_str("    <workout>\n");
+
+    for workout in activity.workouts.iter() {
+        let (element, mut attributes) = match workout.workout_type {
+            activities::WorkoutType::Warmup => ("Warmup", ramp_attributes(workout)),
+            activities::WorkoutType::Cooldown => ("Cooldown", ramp_attributes(workout)),
+            activities::WorkoutType::Ramp => ("Ramp", ramp_attributes(workout)),
+            activities::WorkoutType::SteadyState => (
+                "SteadyState",
+                vec![
+                    ("Duration", workout.duration.to_string()),
+                    ("Power", workout.power_steady.to_string()),
+                ],
+            ),
+            activities::WorkoutType::FreeRide => {
+                let mut attributes = vec![("Duration", workout.duration.to_string())];
+
+                if workout.flat_road {
+                    attributes.push(("FlatRoad", "1".to_string()));
+                }
+
+                ("FreeRide", attributes)
+            }
+            activities::WorkoutType::MaxEffort => (
+                "MaxEffort",
+                vec![("Duration", workout.duration.to_string())],
+            ),
+        };
+
+        // Max efforts have no cadence target
+        if !matches!(workout.workout_type, activities::WorkoutType::MaxEffort) {
+            let cadences = [
+                ("Cadence", workout.cadence),
+                ("CadenceLow", workout.cadence_low),
+                ("CadenceHigh", workout.cadence_high),
+            ];
+
+            attributes.extend(
+                cadences
+                    .into_iter()
+                    .filter(|(_, cadence)| *cadence > 0)
+                    .map(|(name, cadence)| (name, cadence.to_string())),
+            );
+        }
+
+        let attributes: String = attributes
+            .iter()
+            .map(|(name, value)| format!(" {}=\"{}\"", name, value))
+            .collect();
+
+        if workout.text_events.is_empty() {
+            let _ = writeln!(xml, "        <{}{}/>", element, attributes);
+            continue;
+        }
+
+        let _ = writeln!(xml, "        <{}{}>", element, attributes);
+
+        for event in workout.text_events.iter() {
+            let _ = writeln!(
+                xml,
+                "            <textevent timeoffset=\"{}\" message=\"{}\"/>",
+                event.offset,
+                escape(&event.message)
+            );
+        }
+
+        let _ = writeln!(xml, "        </{}>", element);
+    }
+
+    xml.push_str("    </workout>\n</workout_file>\n");
+
+    xml
+}
+
+fn ramp_attributes(workout: &activities::Workout) -> Vec<(&'static str, String)> {
+    vec![
+        ("Duration", workout.duration.to_string()),
+        ("PowerLow", workout.power_low.to_string()),
+        ("PowerHigh", workout.power_high.to_string()),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workouts::{
+        activities::{TextEvent, Workout, WorkoutType},
+        validation::Severity,
+    };
+
+    fn activity() -> Activity {
+        Activity {
+            id: "id".to_string(),
+            name: "Over & unders".to_string(),
+            description: "Tempo with \"surges\"".to_string(),
+            author: "Rider".to_string(),
+            tags: vec!["threshold".to_string(), "indoor".to_string()],
+            workouts: vec![
+                Workout {
+                    workout_type: WorkoutType::Warmup,
+                    duration: 600,
+                    power_low: 0.4,
+                    power_high: 0.75,
+                    cadence: 90,
+                    ..Default::default()
+                },
+                Workout {
+                    workout_type: WorkoutType::SteadyState,
+                    duration: 300,
+                    power_steady: 0.95,
+                    cadence_low: 85,
+                    cadence_high: 95,
+                    text_events: vec![TextEvent {
+                        offset: 30,
+                        message: "Stay < threshold".to_string(),
+                    }],
+                    ..Default::default()
+                },
+                Workout {
+                    workout_type: WorkoutType::FreeRide,
+                    duration: 120,
+                    flat_road: true,
+                    ..Default::default()
+                },
+                Workout {
+                    workout_type: WorkoutType::MaxEffort,
+                    duration: 15,
+                    ..Default::default()
+                },
+                Workout {
+                    workout_type: WorkoutType::Cooldown,
+                    duration: 300,
+                    power_low: 0.6,
+                    power_high: 0.4,
+                    ..Default::default()
+                },
+            ],
+            path: None,
+        }
+    }
+
+    #[test]
+    fn round_trips_through_zwo() {
+        let original = activity();
+        let xml = workout_to_zwo(&original);
+
+        assert!(validate(&xml).is_empty());
+
+        let file: WorkoutFile = quick_xml::de::from_str(&xml).unwrap();
+        let activity = Activity::from(file);
+
+        assert_eq!(activity.name, original.name);
+        assert_eq!(activity.description, original.description);
+        assert_eq!(activity.author, original.author);
+        assert_eq!(activity.tags, original.tags);
+        assert_eq!(
+            serde_json::to_value(&activity.workouts).unwrap(),
+            serde_json::to_value(&original.workouts).unwrap()
+        );
+    }
+
+    #[test]
+    fn rejects_workouts_for_other_sports() {
+        let xml = workout_to_zwo(&activity());
+
+        let (workout, issues) = parse(&xml);
+        assert!(workout.is_some());
+        assert!(issues.is_empty());
+
+        let (workout, issues) = parse(&xml.replace(">bike<", ">run<"));
+        assert!(workout.is_none());
+        assert!(has_errors(&issues));
+
+        // Older files have no sport type
+        let (workout, _) = parse(&xml.replace("    <sportType>bike</sportType>\n", ""));
+        assert!(workout.is_some());
+    }
+
+    fn issues(xml: &str) -> Vec<(Severity, Option<usize>, Option<String>, String)> {
+        validate(xml)
+            .into_iter()
+            .map(|issue| (issue.severity, issue.line, issue.element, issue.reason))
+            .collect()
+    }
+
+    fn step(line: usize, element: &str) -> (Option<usize>, Option<String>) {
+        (Some(line), Some(element.to_string()))
+    }
+
+    #[test]
+    fn reports_a_missing_attribute_with_its_line() {
+        let xml = r#"<workout_file>
+    <name>Tempo</name>
+    <workout>
+        <Warmup Duration="600" PowerLow="0.4" PowerHigh="0.7"/>
+        <SteadyState Duration="300"/>
+    </workout>
+</workout_file>"#;
+
+        let (line, element) = step(5, "SteadyState");
+
+        assert_eq!(
+            issues(xml),
+            vec![(
+                Severity::Error,
+                line,
+                element,
+                "Missing required attribute Power".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn warns_about_zero_durations() {
+        let xml = r#"<workout_file>
+    <workout>
+        <FreeRide Duration="0"/>
+    </workout>
+</workout_file>"#;
+
+        let (line, element) = step(3, "FreeRide");
+
+        assert_eq!(
+            issues(xml),
+            vec![(
+                Severity::Warning,
+                line,
+                element,
+                "Duration is zero".to_string()
+            )]
+        );
+        assert!(!has_errors(&validate(xml)));
+    }
+
+    #[test]
+    fn rejects_negative_power() {
+        let xml = r#"<workout_file>
+    <workout>
+        <IntervalsT Repeat="3" OnDuration="60" OffDuration="60" OnPower="1.2" OffPower="-0.5">
+            <textevent timeoffset="10" message="Go"/>
+        </IntervalsT>
+    </workout>
+</workout_file>"#;
+
+        let (line, element) = step(3, "IntervalsT");
+
+        assert_eq!(
+            issues(xml),
+            vec![(
+                Severity::Error,
+                line,
+                element,
+                "OffPower is negative".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn rejects_unsupported_elements() {
+        let xml = r#"<workout_file>
+    <workout>
+        <SteadyState Duration="300" Power="0.8"/>
+        <Sprint Duration="10"/>
+    </workout>
+</workout_file>"#;
+
+        let (line, element) = step(4, "Sprint");
+
+        assert_eq!(
+            issues(xml),
+            vec![(
+                Severity::Error,
+                line,
+                element,
+                "Unsupported element <Sprint>".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn reports_the_line_of_malformed_xml() {
+        let xml = r#"<workout_file>
+    <workout>
+        <SteadyState Duration="300" Power="0.8"/>
+    </workouts>
+</workout_file>"#;
+
+        let issues = validate(xml);
 
-    quick_xml::de::from_str(xml.as_str()).ok()
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].severity, Severity::Error);
+        assert_eq!(issues[0].line, Some(4));
+        assert_eq!(issues[0].element.as_deref(), Some("workout"));
+    }
 }
@@ -47,6 +47,8 @@ export type Activity = {
   id: string
   name: string
   description: string
+  author: string
+  tags: Array<string>
   ftp: number
   workouts: Array<Workout>
 }
@@ -56,15 +58,33 @@ export type Workout = {
   status: WorkoutStatus
   duration: number
   cadence: number
+  cadenceLow: number
+  cadenceHigh: number
   powerLow: number
   powerHigh: number
   powerSteady: number
+  powerUnit: PowerUnit
+  flatRoad: boolean
+  textEvents: Array<TextEvent>
+}
+
+export type TextEvent = {
+  offset: number
+  message: string
 }
 
 export enum WorkoutType {
   Warmup = 'Warmup',
   SteadyState = 'SteadyState',
   Cooldown = 'Cooldown',
+  Ramp = 'Ramp',
+  FreeRide = 'FreeRide',
+  MaxEffort = 'MaxEffort',
+}
+
+export enum PowerUnit {
+  Ftp = 'Ftp',
+  Watts = 'Watts',
 }
 
 export enum WorkoutStatus {
@@ -73,19 +93,182 @@ export enum WorkoutStatus {
 }
 
 export enum SessionStatus {
-  Started = 'started',
+  Idle = 'idle',
+  Running = 'running',
   Paused = 'paused',
-  Stopped = 'stopped',
+  Finished = 'finished',
 }
 
 export type SessionData = {
   status: SessionStatus
+  startTime: string | null
+  endTime: string | null
+  pausedTime: number
+  pauses: Array<Pause>
+  laps: Array<number>
   cadenceData: Array<number>
   powerData: Array<number>
   speedData: Array<number>
   heartRateData: Array<number>
-  indoorBikeData: Array<{ cadence: number; power: number; speed: number }>
+  records: Array<SessionRecord>
   totalDistance: number
+  name?: string | null
+  workoutName?: string | null
+  workoutId?: string | null
+  workoutVersion?: string | null
+  notes?: string | null
+  rpe?: number | null
+}
+
+export type Pause = {
+  elapsed: number
+  duration: number
+}
+
+export type SessionSummary = {
+  id: string
+  name: string | null
+  workoutName: string | null
+  date: string | null
+  duration: number
+  distance: number
+  normalizedPower: number | null
+  trainingStressScore: number | null
+  rpe: number | null
+}
+
+export type RecoverableSession = {
+  id: string
+  startTime: string | null
+  duration: number
+  distance: number
+  finished: boolean
+}
+
+export type TrainingLoadDay = {
+  date: string
+  trainingStressScore: number
+  chronicTrainingLoad: number
+  acuteTrainingLoad: number
+  trainingStressBalance: number
+}
+
+export type PlannedWorkout = {
+  activityId: string
+  date: string
+}
+
+export type Zone = {
+  name: string
+  min: number
+  max: number | null
+}
+
+export type Zones = {
+  power: Array<Zone>
+  heartRate: Array<Zone>
+}
+
+export type ZoneTime = {
+  zone: Zone
+  seconds: number
+}
+
+export type SummaryPeriod = 'week' | 'month'
+
+export type PeriodTotals = {
+  rides: number
+  duration: number
+  distance: number
+  work: number
+  trainingStressScore: number
+  powerZones: Array<ZoneTime>
+  averageIntensityFactor: number | null
+}
+
+export type PeriodSummary = {
+  start: string
+  end: string
+  totals: PeriodTotals
+  previous: PeriodTotals
+  change: {
+    rides: number
+    duration: number
+    distance: number
+    work: number
+    trainingStressScore: number
+    averageIntensityFactor: number | null
+  }
+}
+
+export type Alignment = { type: 'elapsed_time'; interval: number } | { type: 'workout_step' }
+
+export type SegmentValues = {
+  averagePower: number | null
+  averageHeartRate: number | null
+  averageCadence: number | null
+}
+
+export type SessionComparison = {
+  sessions: Array<{ id: string; name: string | null; date: string | null }>
+  segments: Array<{
+    start: number
+    end: number
+    workoutType: string | null
+    values: Array<SegmentValues>
+    deltas: Array<SegmentValues>
+  }>
+  heartRateAtPower: Array<{ power: number; heartRate: Array<number> }>
+  workoutChanged: boolean
+}
+
+export type FtpTest = 'ramp' | 'twenty_minute'
+
+export type FtpEstimate = {
+  sessionId: string
+  test: FtpTest
+  ftp: number
+  power: number
+  currentFtp: number
+}
+
+export type CriticalPowerModel = {
+  criticalPower: number
+  wPrime: number
+  rSquared: number
+}
+
+export type WorkoutIssue = {
+  severity: 'error' | 'warning'
+  line: number | null
+  element: string | null
+  reason: string
+}
+
+export type WorkoutFileReport = {
+  path: string
+  loaded: boolean
+  issues: Array<WorkoutIssue>
+}
+
+export type LibraryEntry = {
+  id: string
+  path: string | null
+  hash: string
+  name: string
+  tags: Array<string>
+  author: string
+  duration: number
+  trainingStressScore: number | null
+}
+
+export type SessionRecord = {
+  elapsed: number
+  heartRate: number | null
+  power: number | null
+  cadence: number | null
+  speed: number | null
+  wBalance?: number | null
 }
 
 export type AppUser = {
@@ -1,4 +1,10 @@
-import { WorkoutType, type Activity, type Workout } from '../types'
+import {
+  PowerUnit,
+  WorkoutType,
+  type Activity,
+  type SessionRecord,
+  type Workout,
+} from '../types'
 
 type Data = {
   power: number
@@ -43,24 +49,40 @@ const calculatePower = (
   intervalTime: number
 ): number => {
   const { workoutType, powerSteady, powerLow, powerHigh, duration } = workout
+  const scale = powerScale(workout, ftp)
+
+  // No target power, the rider chooses the effort
+  if (
+    workoutType === WorkoutType.FreeRide ||
+    workoutType === WorkoutType.MaxEffort
+  ) {
+    return 0
+  }
+
   if (workoutType === WorkoutType.SteadyState) {
-    const power = Math.floor(powerSteady * ftp)
+    const power = Math.floor(powerSteady * scale)
 
     return setToNearestPowerJump(power)
   }
 
-  return calculateRangePower(ftp, powerLow, powerHigh, duration, intervalTime)
+  return calculateRangePower(
+    scale,
+    powerLow,
+    powerHigh,
+    duration,
+    intervalTime
+  )
 }
 
 const calculateRangePower = (
-  ftp: number,
+  scale: number,
   low: number,
   high: number,
   duration: number,
   elapsedTime: number
 ): number => {
-  const lowPower = Math.floor(low * ftp)
-  const highPower = Math.floor(high * ftp)
+  const lowPower = Math.floor(low * scale)
+  const highPower = Math.floor(high * scale)
 
   const range = Math.abs(highPower - lowPower)
   const timeJump = Math.floor(duration / Math.floor(range / POWER_JUMP))
@@ -75,26 +97,31 @@ const calculateRangePower = (
   return setToNearestPowerJump(power)
 }
 
+// Watts per unit of the power targets of a step
+export const powerScale = (workout: Workout, ftp: number): number =>
+  workout.powerUnit === PowerUnit.Watts ? 1 : ftp
+
 const setToNearestPowerJump = (power: number): number =>
   Math.round(power / POWER_JUMP) * POWER_JUMP
 
-export const formatIndoorBikeData = (
-  data: Array<{ cadence: number; power: number; speed: number }>
-) => {
+export const formatSessionRecords = (data: Array<SessionRecord>) => {
   const formattedData: {
     cadenceData: number[]
     powerData: number[]
     speedData: number[]
+    heartRateData: number[]
   } = {
     cadenceData: [],
     powerData: [],
     speedData: [],
+    heartRateData: [],
   }
 
-  data.forEach(({ cadence, power, speed }) => {
-    formattedData.cadenceData.push(cadence)
-    formattedData.powerData.push(power)
-    formattedData.speedData.push(speed)
+  data.forEach(({ cadence, power, speed, heartRate }) => {
+    formattedData.cadenceData.push(cadence || 0)
+    formattedData.powerData.push(power || 0)
+    formattedData.speedData.push(speed || 0)
+    formattedData.heartRateData.push(heartRate || 0)
   })
 
   return formattedData
@@ -1,5 +1,6 @@
 import type { ScriptableContext } from 'chart.js'
-import type { BasicObject } from '../types'
+import { invoke } from '@tauri-apps/api/tauri'
+import type { Zone, Zones } from '../types'
 
 export const ZONE_COLORS = {
   red: 'rgb(255, 99, 132)',
@@ -10,33 +11,18 @@ export const ZONE_COLORS = {
   grey: 'rgb(201, 203, 207)',
 }
 
-export const ZONES: BasicObject = {
-  Z1: {
-    threshold: 0,
-    color: ZONE_COLORS.grey,
-  },
-  Z2: {
-    threshold: 0.6,
-    color: ZONE_COLORS.blue,
-  },
-  Z3: {
-    threshold: 0.76,
-    color: ZONE_COLORS.green,
-  },
-  Z4: {
-    threshold: 0.9,
-    color: ZONE_COLORS.yellow,
-  },
-  Z5: {
-    name: 'z5',
-    threshold: 1.05,
-    color: ZONE_COLORS.orange,
-  },
-  Z6: {
-    threshold: 1.18,
-    color: ZONE_COLORS.red,
-  },
-}
+// Colors of the power zones from the lowest up, zones above the last color use it too
+const POWER_ZONE_COLORS = [
+  ZONE_COLORS.grey,
+  ZONE_COLORS.blue,
+  ZONE_COLORS.green,
+  ZONE_COLORS.yellow,
+  ZONE_COLORS.orange,
+  ZONE_COLORS.red,
+]
+
+// Zones of the user, resolved by the backend from the zone settings
+export const fetchZones = (): Promise<Zones> => invoke('get_zones')
 
 export const getDefaultChartOptions = (
   type: string,
@@ -84,13 +70,8 @@ export const getDefaultChartOptions = (
   },
 })
 
-export const getZones = (ftp: number, maxPower: number) =>
-  Object.keys(ZONES).map((key) => {
-    const zoneValue = Math.floor(ftp * ZONES[key].threshold)
-    const threshold = zoneValue / maxPower
-
-    return {
-      threshold,
-      color: ZONES[key].color as string,
-    }
-  })
+export const getZones = (zones: Array<Zone>, maxPower: number) =>
+  zones.map((zone, index) => ({
+    threshold: zone.min / maxPower,
+    color: POWER_ZONE_COLORS[Math.min(index, POWER_ZONE_COLORS.length - 1)],
+  }))